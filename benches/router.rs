@@ -13,6 +13,14 @@ fn router_add(bencher: &mut Bencher) {
     });
 }
 
+#[bench]
+fn router_add_static(bencher: &mut Bencher) {
+    bencher.iter(|| {
+        let mut router = Router::<&str>::new();
+        router.add_static("/aaa/bbb", "payload")
+    });
+}
+
 #[bench]
 fn router_find_simple(bencher: &mut Bencher) {
     let mut router = Router::<&str>::new();
@@ -46,6 +54,18 @@ fn router_find_placeholder(bencher: &mut Bencher) {
     bencher.iter(|| router.find("/products/10"));
 }
 
+#[bench]
+fn router_template_for_placeholder(bencher: &mut Bencher) {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products", "products");
+    router.add("/products/:id", "product");
+    router.add("/products/:id/edit", "edit");
+    router.add("/products/featured", "featured");
+    bencher.iter(|| router.template_for("/products/10"));
+}
+
 #[bench]
 fn router_find_all(bencher: &mut Bencher) {
     let mut router = Router::<&str>::new();
@@ -103,3 +123,133 @@ fn router_find_long_string(bencher: &mut Bencher) {
 
     bencher.iter(|| router.find("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"));
 }
+
+// every route starts with its own character, giving "/api/" 500 children
+fn fanout_path(i: u32) -> String {
+    format!("/api/{}/show", char::from_u32(0x4e00 + i).unwrap())
+}
+
+fn router_with_fanout() -> Router<u32> {
+    let mut router = Router::<u32>::new();
+    for i in 0..500 {
+        router.add(fanout_path(i), i);
+    }
+    router
+}
+
+#[bench]
+fn router_add_fanout(bencher: &mut Bencher) {
+    bencher.iter(router_with_fanout);
+}
+
+#[bench]
+fn router_find_fanout(bencher: &mut Bencher) {
+    let router = router_with_fanout();
+    let path = fanout_path(499);
+    bencher.iter(|| router.find(path.as_str()));
+}
+
+#[bench]
+fn router_add_fanout_unchecked(bencher: &mut Bencher) {
+    bencher.iter(|| {
+        let mut router = Router::<u32>::new();
+        for i in 0..500 {
+            router.add_unchecked(fanout_path(i), i);
+        }
+        router.sort();
+        router
+    });
+}
+
+// hundreds of top level resources directly under the root, sharing prefixes
+// like real resource names do (`/users`, `/user_groups`, ...)
+const WIDE_ROOT_WORDS: [&str; 12] = [
+    "user", "post", "order", "product", "payment", "invoice", "account", "report", "team",
+    "project", "page", "price",
+];
+
+fn wide_root_resource(i: usize) -> String {
+    let word = WIDE_ROOT_WORDS[i % WIDE_ROOT_WORDS.len()];
+    format!("/{}_{}s", word, i / WIDE_ROOT_WORDS.len())
+}
+
+fn router_with_wide_root() -> Router<usize> {
+    let mut router = Router::<usize>::new();
+    for i in 0..300 {
+        router.add(wide_root_resource(i), i);
+        router.add(format!("{}/:id", wide_root_resource(i)), i);
+    }
+    router
+}
+
+#[bench]
+fn router_find_wide_root(bencher: &mut Bencher) {
+    let router = router_with_wide_root();
+    let path = format!("{}/42", wide_root_resource(299));
+    bencher.iter(|| router.find(path.as_str()));
+}
+
+#[bench]
+fn router_find_wide_root_indexed(bencher: &mut Bencher) {
+    let mut router = router_with_wide_root();
+    router.set_segment_index(true);
+    let path = format!("{}/42", wide_root_resource(299));
+    bencher.iter(|| router.find(path.as_str()));
+}
+
+// same number of segments with 0, 1 and 4 named params, so that comparing
+// `find` with `find_streaming` (which builds no params) isolates their cost
+const PARAMS_PATHS: [&str; 3] = ["/zero/a/b/c/d", "/one/1/b/c/d", "/four/1/2/3/4"];
+
+fn router_with_params() -> Router<&'static str> {
+    let mut router = Router::<&str>::new();
+    router.add("/zero/a/b/c/d", "zero");
+    router.add("/one/:a/b/c/d", "one");
+    router.add("/four/:a/:b/:c/:d", "four");
+    router
+}
+
+#[bench]
+fn router_find_params_0(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| router.find(PARAMS_PATHS[0]));
+}
+
+#[bench]
+fn router_find_params_1(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| router.find(PARAMS_PATHS[1]));
+}
+
+#[bench]
+fn router_find_params_4(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| router.find(PARAMS_PATHS[2]));
+}
+
+#[bench]
+fn router_walk_params_0(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| router.find_streaming(PARAMS_PATHS[0], |_, _| {}));
+}
+
+#[bench]
+fn router_walk_params_1(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| router.find_streaming(PARAMS_PATHS[1], |_, _| {}));
+}
+
+#[bench]
+fn router_walk_params_4(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| router.find_streaming(PARAMS_PATHS[2], |_, _| {}));
+}
+
+#[bench]
+fn router_find_lazy_params_4(bencher: &mut Bencher) {
+    let router = router_with_params();
+    bencher.iter(|| {
+        let result = router.find_lazy(PARAMS_PATHS[2]);
+        result.get_param("d").map(|value| value.len())
+    });
+}
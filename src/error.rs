@@ -0,0 +1,179 @@
+use std::fmt;
+
+/// Describes why a path cannot be added next to the routes already in a Tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConflictKind {
+    /// The exact same pattern has already been registered with a payload.
+    Duplicate,
+    /// A named (`:`) or catch all (`*`) sibling with a different name would overlap.
+    AmbiguousDynamic,
+}
+
+/// Error returned by `Tree::from_sorted`, holding the index of the offending route.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InsertError {
+    /// The route sorts before the route preceding it.
+    Unsorted(usize),
+    /// The route cannot be added next to the routes preceding it.
+    Conflict(usize, ConflictKind),
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InsertError::Unsorted(index) => {
+                write!(f, "route {} sorts before the route preceding it", index)
+            }
+            InsertError::Conflict(index, kind) => {
+                write!(
+                    f,
+                    "route {} conflicts with a previous route: {:?}",
+                    index, kind
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+/// Error returned by `Tree::try_find` when the path is malformed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FindError {
+    /// The path contains a control character (e.g. NUL) at the given character position.
+    ControlCharacter(usize),
+}
+
+impl fmt::Display for FindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FindError::ControlCharacter(pos) => {
+                write!(f, "control character at position {} in path", pos)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FindError {}
+
+/// Error returned by `Tree::find_strict` when another route, as specific as
+/// the route found, matches the path as well.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AmbiguityError {
+    /// Pattern of the route `find` returns.
+    pub chosen: String,
+    /// Pattern of the sibling route matching the path too.
+    pub rival: String,
+}
+
+impl fmt::Display for AmbiguityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "path matches both {} and {}", self.chosen, self.rival)
+    }
+}
+
+impl std::error::Error for AmbiguityError {}
+
+/// Error returned by `Tree::check_invariants`, holding the pattern of the node
+/// breaking the invariant.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InvariantViolation {
+    /// Two static children of the node start with the given character.
+    SharedFirstChar(String, char),
+    /// Two named or two catch all children of the node start with the same
+    /// marker, so they should share a node up to their names.
+    DynamicCollision(String),
+    /// The children of the node are not in the order they are tried in, see
+    /// `Tree::sort`.
+    Unsorted(String),
+    /// The static node holds no payload and a single static child, which
+    /// `Tree::compact` would merge.
+    Uncompacted(String),
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::SharedFirstChar(pattern, ch) => {
+                write!(f, "children of {} share first character {:?}", pattern, ch)
+            }
+            InvariantViolation::DynamicCollision(pattern) => {
+                write!(f, "dynamic children of {} collide", pattern)
+            }
+            InvariantViolation::Unsorted(pattern) => {
+                write!(f, "children of {} are not sorted", pattern)
+            }
+            InvariantViolation::Uncompacted(pattern) => {
+                write!(f, "{} can be merged into its only child", pattern)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+/// Error returned by `validate_pattern`, positions are counted in characters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PatternError {
+    /// A named parameter (`:`) at the given position has no name.
+    EmptyParamName(usize),
+    /// The catch all parameter (`*`) at the given position is followed by more segments.
+    CatchAllNotLast(usize),
+    /// A marker (`:` or `*`) at the given position appears inside a parameter name.
+    StrayMarker(usize),
+    /// The parameter name is declared more than once.
+    DuplicateParamName(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::EmptyParamName(pos) => {
+                write!(f, "named parameter without name at position {}", pos)
+            }
+            PatternError::CatchAllNotLast(pos) => {
+                write!(f, "catch all parameter at position {} is not last", pos)
+            }
+            PatternError::StrayMarker(pos) => {
+                write!(f, "marker inside a parameter name at position {}", pos)
+            }
+            PatternError::DuplicateParamName(name) => {
+                write!(f, "parameter {} is declared more than once", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Error returned by `Result::require`.
+#[derive(Debug)]
+pub enum ParamError {
+    /// The Result holds no parameter with the given name.
+    Missing(String),
+    /// The value of the parameter could not be parsed.
+    Parse {
+        name: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamError::Missing(name) => write!(f, "missing parameter {}", name),
+            ParamError::Parse { name, source } => {
+                write!(f, "invalid parameter {}: {}", name, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParamError::Missing(_) => None,
+            ParamError::Parse { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
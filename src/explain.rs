@@ -0,0 +1,61 @@
+use crate::syntax::Syntax;
+
+/// Why `Tree::find_explain` did not walk through a part of the Tree. Every
+/// reason holds the pattern (up to the rejected node) it applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// The child starts with another character than the rest of the path.
+    FirstChar { pattern: String },
+    /// The child starts with the same character as the rest of the path but
+    /// differs before its first separator or parameter.
+    Prefix { pattern: String },
+    /// The constrained named parameter doesn't allow *value*.
+    Constraint { pattern: String, value: String },
+    /// The child matches the rest of the path too, but the sibling *chosen*
+    /// is tried first.
+    Precedence { pattern: String, chosen: String },
+    /// The path differs from the key of a node the walk went into.
+    Diverged { pattern: String },
+    /// The path ends before the key of the node does.
+    PathEnded { pattern: String },
+    /// The path goes on after the key of a node without children.
+    PathContinues { pattern: String },
+    /// The path ends with the key of a node that holds no route.
+    NoRoute { pattern: String },
+}
+
+impl RejectionReason {
+    /// Returns the pattern the reason applies to.
+    pub fn pattern(&self) -> &str {
+        match self {
+            RejectionReason::FirstChar { pattern }
+            | RejectionReason::Prefix { pattern }
+            | RejectionReason::Constraint { pattern, .. }
+            | RejectionReason::Precedence { pattern, .. }
+            | RejectionReason::Diverged { pattern }
+            | RejectionReason::PathEnded { pattern }
+            | RejectionReason::PathContinues { pattern }
+            | RejectionReason::NoRoute { pattern } => pattern,
+        }
+    }
+
+    pub(crate) fn with_syntax(mut self, syntax: &Syntax) -> Self {
+        match &mut self {
+            RejectionReason::Precedence { pattern, chosen } => {
+                *pattern = syntax.external(pattern);
+                *chosen = syntax.external(chosen);
+            }
+            RejectionReason::Constraint { pattern, value } => {
+                *pattern = syntax.external(pattern);
+                *value = syntax.external(value);
+            }
+            RejectionReason::FirstChar { pattern }
+            | RejectionReason::Prefix { pattern }
+            | RejectionReason::Diverged { pattern }
+            | RejectionReason::PathEnded { pattern }
+            | RejectionReason::PathContinues { pattern }
+            | RejectionReason::NoRoute { pattern } => *pattern = syntax.external(pattern),
+        }
+        self
+    }
+}
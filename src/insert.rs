@@ -0,0 +1,39 @@
+/// Describes where `Tree::add` placed a path, as returned by `Tree::add_with_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertInfo {
+    /// Branch of the insertion that fired.
+    pub branch: InsertBranch,
+    /// Number of characters shared between the path and the key of the node
+    /// where the branch fired.
+    pub pos: usize,
+    /// Number of nodes walked down from the root before the branch fired.
+    pub depth: usize,
+}
+
+/// Branches of the insertion walk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertBranch {
+    /// The path replaced the placeholder root of an empty Tree.
+    Root,
+    /// The rest of the path was pushed as a new child.
+    NewChild,
+    /// The path matched an existing node without payload.
+    PayloadSet,
+    /// The key of an existing node was split to hold the path.
+    Split,
+}
+
+impl InsertInfo {
+    pub(crate) fn new(branch: InsertBranch, pos: usize) -> Self {
+        Self {
+            branch,
+            pos,
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn descend(mut self) -> Self {
+        self.depth += 1;
+        self
+    }
+}
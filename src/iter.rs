@@ -0,0 +1,48 @@
+use crate::node::Node;
+use crate::syntax::Syntax;
+
+/// An owning iterator over the routes of a Tree, created by `Tree::into_iter`.
+///
+/// Yields every payload along with its full path, in traversal order.
+pub struct IntoIter<T> {
+    routes: std::vec::IntoIter<(String, T)>,
+}
+
+impl<T> IntoIter<T> {
+    pub(crate) fn new(root: Node<T>, syntax: &Syntax) -> Self {
+        let mut routes = Vec::new();
+        IntoIter::<T>::collect_routes(root, String::new(), &mut routes);
+        if !syntax.is_default() {
+            for (path, _) in routes.iter_mut() {
+                *path = syntax.external(path);
+            }
+        }
+        Self {
+            routes: routes.into_iter(),
+        }
+    }
+
+    fn collect_routes(node: Node<T>, mut path: String, routes: &mut Vec<(String, T)>) {
+        path.push_str(&node.key);
+        if let Some(payload) = node.payload {
+            routes.push((path.clone(), payload));
+        }
+        for child in node.children {
+            IntoIter::<T>::collect_routes(child, path.clone(), routes);
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (String, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.routes.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.routes.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+
+use crate::node::Node;
+use crate::syntax::Syntax;
+use crate::tree::UNNAMED_CATCH_ALL;
+use crate::utils::{
+    catch_all_name, is_segment_glob, is_separator, split_constraint, split_default,
+    split_extension, split_format,
+};
+
+/// Outcome of `Tree::find_lazy`, capturing no parameter until asked for one.
+///
+/// Each call to `get_param` walks the keys of the matched nodes along the path
+/// again, stopping at the requested parameter, instead of building every
+/// parameter upfront like `Result`.
+#[derive(Debug)]
+pub struct LazyResult<'a, 'p, T> {
+    nodes: Vec<&'a Node<T>>,
+    /// Path walked, using the separator of the nodes (`/`).
+    path: Cow<'p, str>,
+    syntax: &'a Syntax,
+    pub payload: &'a Option<T>,
+}
+
+impl<'a, 'p, T> LazyResult<'a, 'p, T> {
+    pub(crate) fn new(
+        nodes: Vec<&'a Node<T>>,
+        path: Cow<'p, str>,
+        syntax: &'a Syntax,
+        payload: &'a Option<T>,
+    ) -> Self {
+        Self {
+            nodes,
+            path,
+            syntax,
+            payload,
+        }
+    }
+
+    /// Returns the pattern of the matched route, see `Result::key`.
+    pub fn key(&self) -> String {
+        let key: String = self.nodes.iter().map(|node| &*node.key).collect();
+        if self.syntax.is_default() {
+            return key;
+        }
+        self.syntax.external(&key)
+    }
+
+    /// Returns the value of the named or catch-all parameter *name*, `None`
+    /// when the matched route has no such parameter. Values only get copied
+    /// for Trees using another separator or other markers.
+    pub fn get_param(&self, name: &str) -> Option<Cow<'_, str>> {
+        let value = self.capture(name)?;
+        if self.syntax.is_default() {
+            return Some(Cow::Borrowed(value));
+        }
+        Some(Cow::Owned(self.syntax.external(value)))
+    }
+
+    fn capture(&self, name: &str) -> Option<&str> {
+        let path = self.path.as_ref();
+        let separators = self.syntax.extra_separators();
+        let separator = |ch: char| is_separator(ch, separators);
+        let segment_end = |start: usize| {
+            path[start..]
+                .find(separator)
+                .map_or(path.len(), |end| start + end)
+        };
+        // byte positions in the path and in the key of the current node
+        let mut path_pos = 0;
+        for node in &self.nodes {
+            let key = &*node.key;
+            let mut key_pos = 0;
+            while let Some(ch) = key[key_pos..].chars().next() {
+                let key_end = key[key_pos..]
+                    .find(separator)
+                    .map_or(key.len(), |end| key_pos + end);
+                if ch == ':' {
+                    let (raw, default) = split_default(&key[key_pos + 1..key_end]);
+                    let (raw, format) = split_format(raw);
+                    let (param, _) = split_constraint(raw);
+                    let segment = &path[path_pos..segment_end(path_pos)];
+                    let (value, extension) = match format {
+                        Some(_) => split_extension(segment),
+                        None => (segment, ""),
+                    };
+                    if param == name {
+                        // only a path ending before the parameter takes its
+                        // default value, not an empty segment
+                        let ended = path_pos == path.len();
+                        return Some(default.filter(|_| ended).unwrap_or(value));
+                    }
+                    if format == Some(name) {
+                        return Some(extension);
+                    }
+                    path_pos += segment.len();
+                    key_pos = key_end;
+                } else if ch == '*' && is_segment_glob(&key[key_pos..]) {
+                    let segment = &path[path_pos..segment_end(path_pos)];
+                    let param = match &key[key_pos + 2..key_end] {
+                        "" => UNNAMED_CATCH_ALL,
+                        param => param,
+                    };
+                    if param == name {
+                        return Some(segment);
+                    }
+                    path_pos += segment.len();
+                    key_pos = key_end;
+                } else if ch == '*' {
+                    // catch all parameters end the route, leaving out the trailing slash
+                    if catch_all_name(key, key[..key_pos].chars().count() + 1) != name {
+                        return None;
+                    }
+                    let rest = &path[path_pos..];
+                    return Some(rest.strip_suffix('/').unwrap_or(rest));
+                } else {
+                    // static characters match one character of the path, which
+                    // may be shorter when matched ignoring a trailing slash
+                    path_pos += path[path_pos..].chars().next().map_or(0, char::len_utf8);
+                    key_pos += ch.len_utf8();
+                }
+            }
+        }
+        None
+    }
+}
@@ -1,5 +1,18 @@
+mod error;
+#[cfg(feature = "debug")]
+mod explain;
+#[cfg(feature = "debug")]
+mod insert;
+mod iter;
+mod lazy;
+mod params;
+mod pattern;
 mod result;
+#[cfg(feature = "instrumentation")]
+mod stats;
+mod syntax;
 mod tree;
+mod view;
 
 #[doc(hidden)]
 mod node;
@@ -7,7 +20,26 @@ mod node;
 #[doc(hidden)]
 mod utils;
 
-pub use crate::result::Result;
-pub use crate::tree::Tree;
+pub use crate::error::{
+    AmbiguityError, ConflictKind, FindError, InsertError, InvariantViolation, ParamError,
+    PatternError,
+};
+#[cfg(feature = "debug")]
+pub use crate::explain::RejectionReason;
+#[cfg(feature = "debug")]
+pub use crate::insert::{InsertBranch, InsertInfo};
+pub use crate::iter::IntoIter;
+pub use crate::lazy::LazyResult;
+pub use crate::node::Kind;
+pub use crate::params::FromParams;
+pub use crate::pattern::{cmp_routes, validate_pattern, ParamSpec};
+pub use crate::result::{Matched, OwnedResult, ResolvedRoute, Result};
+#[cfg(feature = "instrumentation")]
+pub use crate::stats::FindStats;
+pub use crate::tree::{
+    DuplicatePolicy, FlatRoute, MatchPolicy, PartialMatch, RouteDiff, TrailingSlash, Tree,
+    TreeSnapshot, UNNAMED_CATCH_ALL,
+};
+pub use crate::view::NodeView;
 
 pub type Router<T> = crate::tree::Tree<T>;
@@ -1,56 +1,475 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+use crate::error::InvariantViolation;
+#[cfg(feature = "debug")]
+use crate::explain::RejectionReason;
+use crate::syntax::Syntax;
+use crate::utils::{
+    byte_offset, defaulted_param, is_greedy_glob, is_segment_glob, is_separator, parse_constraints,
+    shared_key, split_constraint, split_default, split_extension, split_format,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Node<T> {
-    pub key: String,
+    /// Borrowed when the route was added from a `&'static str`, see
+    /// `Tree::add_static`.
+    pub key: Cow<'static, str>,
     pub payload: Option<T>,
     pub(crate) placeholder: bool,
     pub(crate) children: Vec<Node<T>>,
+    /// First character of every child and its position in children, ordered
+    /// by character. Rebuilt by `sort_children`.
+    pub(crate) indices: Vec<(char, usize)>,
+    pub(crate) excluded: Vec<String>,
+    /// Values allowed for each constrained named parameter in the key.
+    constraints: Vec<(String, Vec<String>)>,
     kind: Kind,
     priority: i32,
+    pub(crate) boost: i32,
 }
 
+/// Kind of a node, given by the first marker in its key.
+///
+/// # Examples
+///
+/// ```
+/// use patricia_router::{Kind, Tree};
+///
+/// let mut tree = Tree::<&str>::new();
+/// tree.add("/products/:id", "product");
+/// let label = match tree.classify("/products/10") {
+///     Some(Kind::Normal) => "static",
+///     Some(Kind::Named) => "named",
+///     Some(Kind::Glob) => "catch all",
+///     None => "not found",
+/// };
+/// assert_eq!(label, "named");
+/// ```
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+#[repr(u8)]
 pub enum Kind {
-    Normal,
-    Named,
-    Glob,
+    /// Static key without parameter.
+    Normal = 0,
+    /// Key holding a named parameter (`:`).
+    Named = 1,
+    /// Key holding a catch all parameter (`*`).
+    Glob = 2,
+}
+
+impl Kind {
+    /// Returns the kind of parameter introduced by *ch*, if it is a marker.
+    pub(crate) fn from_marker(ch: char) -> Option<Kind> {
+        match ch {
+            '*' => Some(Kind::Glob),
+            ':' => Some(Kind::Named),
+            _ => None,
+        }
+    }
 }
 
 impl<T> Node<T> {
-    pub(crate) fn new(k: impl Into<String>, payload: Option<T>, placeholder: bool) -> Self {
+    pub(crate) fn new(
+        k: impl Into<Cow<'static, str>>,
+        payload: Option<T>,
+        placeholder: bool,
+    ) -> Self {
         let key = k.into();
         let (priority, kind) = Node::<T>::compute_priority(&key);
         Self {
+            constraints: parse_constraints(&key),
             key,
             placeholder,
             children: Vec::<Node<T>>::new(),
+            indices: Vec::new(),
+            excluded: Vec::new(),
             payload,
             kind,
             priority,
+            boost: 0,
         }
     }
 
-    fn compute_priority(key: &String) -> (i32, Kind) {
+    fn compute_priority(key: &str) -> (i32, Kind) {
         for (i, current_char) in key.chars().enumerate() {
-            if current_char == '*' {
-                return (i as i32, Kind::Glob);
-            } else if current_char == ':' {
-                return (i as i32, Kind::Named);
+            if let Some(kind) = Kind::from_marker(current_char) {
+                return (i as i32, kind);
             }
         }
-        (key.len() as i32, Kind::Normal)
+        (key.chars().count() as i32, Kind::Normal)
+    }
+
+    pub(crate) fn kind(&self) -> &Kind {
+        &self.kind
+    }
+
+    pub(crate) fn priority(&self) -> i32 {
+        self.priority
     }
 
-    pub(crate) fn set_key(&mut self, value: String) -> () {
-        self.key = value;
+    pub(crate) fn set_key(&mut self, value: impl Into<Cow<'static, str>>) {
+        self.key = value.into();
+        self.constraints = parse_constraints(&self.key);
         let (p, k) = Node::<T>::compute_priority(&self.key);
         self.priority = p;
         self.kind = k;
     }
 
-    pub(crate) fn sort_children(&mut self) -> () {
-        self.children.sort_by(|a, b| a.cmp(b))
+    pub(crate) fn sort_children(&mut self) {
+        self.children.sort_by(|a, b| a.cmp(b));
+        self.indices.clear();
+        self.indices.extend(
+            self.children
+                .iter()
+                .enumerate()
+                .filter_map(|(pos, child)| child.key.chars().next().map(|ch| (ch, pos))),
+        );
+        self.indices.sort_unstable();
+    }
+
+    /// Whether the children are sorted and indexed, which `add_unsorted` doesn't keep.
+    fn children_sorted(&self) -> bool {
+        self.indices.len()
+            == self
+                .children
+                .iter()
+                .filter(|child| !child.key.is_empty())
+                .count()
+            && self
+                .children
+                .windows(2)
+                .all(|pair| pair[0].cmp(&pair[1]) != Ordering::Greater)
+    }
+
+    /// Inserts *child* where it sorts, after the children comparing equal, and
+    /// updates the indices instead of sorting again, unless the children
+    /// weren't sorted.
+    pub(crate) fn insert_child(&mut self, child: Node<T>) {
+        if !self.children_sorted() {
+            self.children.push(child);
+            self.sort_children();
+            return;
+        }
+        let pos = self
+            .children
+            .partition_point(|probe| probe.cmp(&child) != Ordering::Greater);
+        for (_, index) in self.indices.iter_mut() {
+            if *index >= pos {
+                *index += 1;
+            }
+        }
+        if let Some(ch) = child.key.chars().next() {
+            let at = self.indices.partition_point(|&(first, _)| first < ch);
+            self.indices.insert(at, (ch, pos));
+        }
+        self.children.insert(pos, child);
+    }
+
+    /// Moves the child at *pos* where it sorts, after its key changed, sorting
+    /// every child when they weren't sorted.
+    pub(crate) fn reposition_child(&mut self, pos: usize) {
+        if !self.children_sorted() {
+            self.sort_children();
+            return;
+        }
+        let child = &self.children[pos];
+        let after_previous = pos == 0 || self.children[pos - 1].cmp(child) != Ordering::Greater;
+        let before_next = self
+            .children
+            .get(pos + 1)
+            .map_or(true, |next| child.cmp(next) != Ordering::Greater);
+        if after_previous && before_next {
+            return;
+        }
+        let child = self.children.remove(pos);
+        self.indices.retain(|&(_, index)| index != pos);
+        for (_, index) in self.indices.iter_mut() {
+            if *index > pos {
+                *index -= 1;
+            }
+        }
+        self.insert_child(child);
+    }
+
+    /// Returns the position of the child starting with *ch*, looking it up in
+    /// the indices instead of walking every child.
+    pub(crate) fn child_pos(&self, ch: char) -> Option<usize> {
+        let found = self.indices.binary_search_by_key(&ch, |&(first, _)| first);
+        let pos = self.indices[found.ok()?].1;
+        // indices are stale until the children get sorted again (see add_unsorted)
+        match self.children.get(pos) {
+            Some(child) if child.key.starts_with(ch) => Some(pos),
+            _ => None,
+        }
+    }
+
+    /// Returns the child *path* has to walk into: the static child sharing its
+    /// key with *path*, the first named or catch all child otherwise.
+    ///
+    /// With *ignore_case*, static keys are compared ignoring ASCII case. With
+    /// *glob_first*, a catch all child matching *path* is returned before any other.
+    pub(crate) fn child_for(
+        &self,
+        path: &str,
+        ignore_case: bool,
+        glob_first: bool,
+    ) -> Option<&Node<T>> {
+        if glob_first {
+            let glob = self.children.iter().find(|child| {
+                child.kind == Kind::Glob
+                    && (child.key.starts_with('*') || shared_key(path, &child.key))
+            });
+            if glob.is_some() {
+                return glob;
+            }
+        }
+        let first = path.chars().next();
+        let mut candidates = vec![first];
+        if ignore_case {
+            candidates.push(first.map(|ch| ch.to_ascii_lowercase()));
+            candidates.push(first.map(|ch| ch.to_ascii_uppercase()));
+        }
+        let static_child = candidates
+            .into_iter()
+            .flatten()
+            .filter_map(|ch| self.child_pos(ch))
+            .map(|pos| &self.children[pos])
+            .filter(|child| !child.is_named_or_catch_all())
+            .find(|child| {
+                if ignore_case {
+                    shared_key(&path.to_ascii_lowercase(), &child.key.to_ascii_lowercase())
+                } else {
+                    shared_key(path, &child.key)
+                }
+            });
+        // siblings hold at most one named and one catch all parameter, skip
+        // the named one when constrained to other values
+        static_child.or_else(|| {
+            [':', '*']
+                .iter()
+                .filter_map(|&marker| self.child_pos(marker))
+                .map(|pos| &self.children[pos])
+                .find(|child| child.accepts(path))
+        })
+    }
+
+    /// Returns the children other than *chosen* as specific as *chosen* (same
+    /// kind, boost and priority) whose key could start *path*.
+    pub(crate) fn rivals<'s>(
+        &'s self,
+        chosen: &'s Node<T>,
+        path: &'s str,
+        ignore_case: bool,
+    ) -> impl Iterator<Item = &'s Node<T>> {
+        self.children.iter().filter(move |child| {
+            if std::ptr::eq(*child, chosen)
+                || child.kind != chosen.kind
+                || child.boost != chosen.boost
+                || child.priority != chosen.priority
+            {
+                false
+            } else if child.is_named_or_catch_all() {
+                child.accepts(path)
+            } else if ignore_case {
+                shared_key(&path.to_ascii_lowercase(), &child.key.to_ascii_lowercase())
+            } else {
+                shared_key(path, &child.key)
+            }
+        })
+    }
+
+    /// Pushes into *rejections* why `child_for` doesn't pick each child but the
+    /// chosen one, *pattern* being the pattern of this node.
+    #[cfg(feature = "debug")]
+    pub(crate) fn explain_children(
+        &self,
+        path: &str,
+        ignore_case: bool,
+        glob_first: bool,
+        pattern: &str,
+        rejections: &mut Vec<RejectionReason>,
+    ) {
+        let chosen = self.child_for(path, ignore_case, glob_first);
+        for child in &self.children {
+            if chosen.is_some_and(|chosen| std::ptr::eq(chosen, child)) {
+                continue;
+            }
+            let child_pattern = format!("{}{}", pattern, child.key);
+            let shared = if ignore_case {
+                shared_key(&path.to_ascii_lowercase(), &child.key.to_ascii_lowercase())
+            } else {
+                shared_key(path, &child.key)
+            };
+            let same_first = match (path.chars().next(), child.key.chars().next()) {
+                (Some(p), Some(k)) => p == k || (ignore_case && p.eq_ignore_ascii_case(&k)),
+                _ => false,
+            };
+            rejections.push(if child.is_named_or_catch_all() && !child.accepts(path) {
+                RejectionReason::Constraint {
+                    pattern: child_pattern,
+                    value: path.split('/').next().unwrap_or("").to_string(),
+                }
+            } else if child.is_named_or_catch_all() || shared {
+                RejectionReason::Precedence {
+                    pattern: child_pattern,
+                    chosen: chosen
+                        .map_or(String::new(), |chosen| format!("{}{}", pattern, chosen.key)),
+                }
+            } else if same_first {
+                RejectionReason::Prefix {
+                    pattern: child_pattern,
+                }
+            } else {
+                RejectionReason::FirstChar {
+                    pattern: child_pattern,
+                }
+            });
+        }
+    }
+
+    pub(crate) fn sort_recursive(&mut self) {
+        self.sort_children();
+        for child in self.children.iter_mut() {
+            child.sort_recursive();
+        }
+    }
+
+    /// Merges every static node without payload into its only child when that
+    /// child is static as well, concatenating their keys.
+    pub(crate) fn compact(&mut self) {
+        for child in self.children.iter_mut() {
+            child.compact();
+        }
+        if self.kind == Kind::Normal
+            && self.children.len() == 1
+            && self.children[0].kind == Kind::Normal
+        {
+            self.merge_only_child();
+        }
+        // merged children have new keys and priorities
+        self.sort_children();
+    }
+
+    /// Collects in *index*, keyed by the first segment of their path, the
+    /// positions from the root of the descendants of this node whose key
+    /// holds the separator ending a static first segment, along with the
+    /// length of the path before them. *path* (the path of the parent) and
+    /// *positions* (the ones of this node) are left as they were given.
+    pub(crate) fn index_segments(
+        &self,
+        path: &mut String,
+        positions: &mut Vec<usize>,
+        index: &mut HashMap<String, (Vec<usize>, usize)>,
+    ) {
+        let len = path.len();
+        path.push_str(&self.key);
+        let end = path.char_indices().skip(1).find(|&(_, ch)| ch == '/');
+        let segment = &path[..end.map_or(path.len(), |(end, _)| end)];
+        if segment.chars().any(|ch| Kind::from_marker(ch).is_some()) {
+            // dynamic segments are left to the walk
+        } else if let Some((end, _)) = end {
+            // the root is where walks start anyway
+            if !positions.is_empty() {
+                index.insert(path[..end].to_string(), (positions.clone(), len));
+            }
+        } else {
+            for (pos, child) in self.children.iter().enumerate() {
+                positions.push(pos);
+                child.index_segments(path, positions, index);
+                positions.pop();
+            }
+        }
+        path.truncate(len);
+    }
+
+    /// Checks the invariants of this node and of its descendants, see
+    /// `Tree::check_invariants`. *pattern* holds the path of the parent and is
+    /// left as it was given. The node itself is only expected to be compacted
+    /// when *compactable*, unlike the root of a rooted Tree.
+    pub(crate) fn check_invariants(
+        &self,
+        pattern: &mut String,
+        compactable: bool,
+    ) -> Result<(), InvariantViolation> {
+        let len = pattern.len();
+        pattern.push_str(&self.key);
+        let mut indices: Vec<(char, usize)> = self
+            .children
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, child)| child.key.chars().next().map(|ch| (ch, pos)))
+            .collect();
+        indices.sort_unstable();
+        if let Some(pair) = indices.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            let ch = pair[0].0;
+            return Err(match Kind::from_marker(ch) {
+                Some(_) => InvariantViolation::DynamicCollision(pattern.clone()),
+                None => InvariantViolation::SharedFirstChar(pattern.clone(), ch),
+            });
+        }
+        let sorted = self
+            .children
+            .windows(2)
+            .all(|pair| pair[0].cmp(&pair[1]) != Ordering::Greater);
+        if !sorted || indices != self.indices {
+            return Err(InvariantViolation::Unsorted(pattern.clone()));
+        }
+        if compactable
+            && !self.placeholder
+            && self.kind == Kind::Normal
+            && self.payload.is_none()
+            && self.children.len() == 1
+            && self.children[0].kind == Kind::Normal
+        {
+            return Err(InvariantViolation::Uncompacted(pattern.clone()));
+        }
+        for child in &self.children {
+            child.check_invariants(pattern, true)?;
+        }
+        pattern.truncate(len);
+        Ok(())
+    }
+
+    /// Clears the payload of this node and of its descendants for which *f*,
+    /// given their full path (built in *path*, holding the path of the parent)
+    /// and payload, returns `false`. Descendants left without payload are
+    /// dropped when they have no children and merged into their only child
+    /// otherwise. *path* is left as it was given.
+    pub(crate) fn retain<F: FnMut(&str, &T) -> bool>(&mut self, path: &mut String, f: &mut F) {
+        let len = path.len();
+        path.push_str(&self.key);
+        if self
+            .payload
+            .as_ref()
+            .is_some_and(|payload| !f(path, payload))
+        {
+            self.payload = None;
+        }
+        for child in self.children.iter_mut() {
+            child.retain(path, f);
+            child.merge_only_child();
+        }
+        self.children
+            .retain(|child| child.payload.is_some() || !child.children.is_empty());
+        // merged children have new keys and priorities
+        self.sort_children();
+        path.truncate(len);
+    }
+
+    /// Merges this node into its only child when it holds no payload, whatever
+    /// the kind of the child, unlike `compact`.
+    pub(crate) fn merge_only_child(&mut self) {
+        if self.placeholder || self.payload.is_some() || self.children.len() != 1 {
+            return;
+        }
+        let child = self.children.remove(0);
+        self.set_key(format!("{}{}", self.key, child.key));
+        self.payload = child.payload;
+        self.children = child.children;
+        self.indices = child.indices;
+        self.excluded = child.excluded;
+        self.boost = child.boost;
     }
 
     fn cmp(&self, other: &Self) -> Ordering {
@@ -58,14 +477,260 @@ impl<T> Node<T> {
         if result != Ordering::Equal {
             return result;
         }
-        other.priority.cmp(&self.priority)
+        let result = other.boost.cmp(&self.boost);
+        if result != Ordering::Equal {
+            return result;
+        }
+        let result = other.priority.cmp(&self.priority);
+        if result != Ordering::Equal {
+            return result;
+        }
+        // keep siblings independent from insertion order
+        self.key.cmp(&other.key)
+    }
+
+    pub(crate) fn map<U, F: FnMut(T) -> U>(self, f: &mut F) -> Node<U> {
+        Node {
+            key: self.key,
+            payload: self.payload.map(&mut *f),
+            placeholder: self.placeholder,
+            children: self
+                .children
+                .into_iter()
+                .map(|child| child.map(f))
+                .collect(),
+            indices: self.indices,
+            excluded: self.excluded,
+            constraints: self.constraints,
+            kind: self.kind,
+            priority: self.priority,
+            boost: self.boost,
+        }
+    }
+
+    pub(crate) fn find_pattern(&self, pattern: &str) -> Option<&Node<T>> {
+        let rest = pattern.strip_prefix(&*self.key)?;
+        if rest.is_empty() {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .find_map(|child| child.find_pattern(rest))
+    }
+
+    /// Returns the node whose path (given as *path* for its parent) is the
+    /// first to start with *prefix*, along with that path.
+    pub(crate) fn find_prefix(&self, prefix: &str, mut path: String) -> Option<(&Node<T>, String)> {
+        path.push_str(&self.key);
+        if self.key.starts_with(prefix) {
+            return Some((self, path));
+        }
+        let rest = prefix.strip_prefix(&*self.key)?;
+        self.children
+            .iter()
+            .find_map(|child| child.find_prefix(rest, path.clone()))
+    }
+
+    /// Collects this node and its descendants holding a payload along with
+    /// their full path, *path* being the path of this node.
+    pub(crate) fn collect_routes<'a>(
+        &'a self,
+        path: &str,
+        routes: &mut Vec<(String, &'a Node<T>)>,
+    ) {
+        if self.payload.is_some() {
+            routes.push((path.to_string(), self));
+        }
+        for child in &self.children {
+            child.collect_routes(&format!("{}{}", path, child.key), routes);
+        }
+    }
+
+    /// Collects the full path of the routes beneath this node that `find`
+    /// never reaches, *path* being the path of this node. With *glob_first*, a
+    /// catch all child always wins over its siblings and over the node itself;
+    /// otherwise an unconstrained named child always wins over a catch all
+    /// sibling limited to a segment.
+    pub(crate) fn collect_shadowed(
+        &self,
+        path: &str,
+        glob_first: bool,
+        shadowed: &mut Vec<String>,
+    ) {
+        if glob_first && self.payload.is_some() && self.optional_catch_all().is_some() {
+            shadowed.push(path.to_string());
+        }
+        for child in &self.children {
+            let child_path = format!("{}{}", path, child.key);
+            if self.shadows(child, glob_first) {
+                let mut routes = Vec::new();
+                child.collect_routes(&child_path, &mut routes);
+                shadowed.extend(routes.into_iter().map(|(path, _)| path));
+            } else {
+                child.collect_shadowed(&child_path, glob_first, shadowed);
+            }
+        }
+    }
+
+    /// Checks whether `child_for` picks another child of this node before
+    /// *child* whatever the path.
+    fn shadows(&self, child: &Node<T>, glob_first: bool) -> bool {
+        self.children.iter().any(|sibling| {
+            if glob_first {
+                sibling.key.starts_with('*') && !child.key.starts_with('*')
+            } else {
+                is_segment_glob(&child.key)
+                    && sibling.key.strip_prefix(':').is_some_and(|rest| {
+                        split_constraint(split_default(rest.split('/').next().unwrap_or("")).0)
+                            .1
+                            .is_none()
+                    })
+            }
+        })
+    }
+
+    /// Returns the child matching an exhausted path with an empty catch all
+    /// parameter (e.g. `*rest`, `/*rest` or `/` followed by `*rest`).
+    pub(crate) fn optional_catch_all(&self) -> Option<&Node<T>> {
+        self.children.iter().find(|child| {
+            is_greedy_glob(&child.key)
+                || child.key.strip_prefix('/').is_some_and(is_greedy_glob)
+                || (child.key == "/" && child.children.iter().any(|c| is_greedy_glob(&c.key)))
+        })
+    }
+
+    /// Returns the route holding a named parameter with a default value that
+    /// an exhausted path matches (e.g. `:page=1` or `/:page=1`), along with the
+    /// name and default value of the parameter.
+    pub(crate) fn defaulted_child(&self) -> Option<(&Node<T>, &str, &str)> {
+        self.children.iter().find_map(|child| {
+            child.payload.as_ref()?;
+            let (name, default) = defaulted_param(&child.key)?;
+            Some((child, name, default))
+        })
+    }
+
+    /// Calls *f* with the full path and payload of this node and of its
+    /// descendants, building paths in *buf* (holding the path of the parent)
+    /// with *syntax*. *buf* is left as it was given.
+    pub(crate) fn for_each_route<F: FnMut(&str, &T)>(
+        &self,
+        buf: &mut String,
+        syntax: &Syntax,
+        f: &mut F,
+    ) {
+        let len = buf.len();
+        syntax.push_external(buf, &self.key);
+        if let Some(payload) = &self.payload {
+            f(buf, payload);
+        }
+        for child in &self.children {
+            child.for_each_route(buf, syntax, f);
+        }
+        buf.truncate(len);
+    }
+
+    /// Pushes the full path of this node and of its descendants holding a
+    /// payload into *patterns*, building paths in *path* (holding the path of
+    /// the parent). *path* is left as it was given.
+    pub(crate) fn collect_patterns(&self, path: &mut String, patterns: &mut Vec<String>) {
+        let len = path.len();
+        path.push_str(&self.key);
+        if self.payload.is_some() {
+            patterns.push(path.clone());
+        }
+        for child in &self.children {
+            child.collect_patterns(path, patterns);
+        }
+        path.truncate(len);
+    }
+
+    pub(crate) fn find_pattern_mut(&mut self, pattern: &str) -> Option<&mut Node<T>> {
+        let rest = pattern.strip_prefix(&*self.key)?;
+        if rest.is_empty() {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_pattern_mut(rest))
+    }
+
+    pub(crate) fn boost_pattern(&mut self, pattern: &str, boost: i32) -> bool {
+        let rest = match pattern.strip_prefix(&*self.key) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        if rest.is_empty() {
+            self.boost = boost;
+            return true;
+        }
+        if self
+            .children
+            .iter_mut()
+            .any(|child| child.boost_pattern(rest, boost))
+        {
+            self.sort_children();
+            return true;
+        }
+        false
+    }
+
+    /// Checks *value* against the values allowed for the named parameter *name*.
+    pub(crate) fn allows(&self, name: &str, value: &str) -> bool {
+        self.constraints
+            .iter()
+            .find(|(param, _)| param == name)
+            .map_or(true, |(_, values)| {
+                values.iter().any(|allowed| allowed == value)
+            })
+    }
+
+    /// Checks whether the named parameter starting the key of the node accepts
+    /// the first segment of *path*. Other nodes accept any path.
+    fn accepts(&self, path: &str) -> bool {
+        match self.key.strip_prefix(':') {
+            Some(rest) => {
+                let (raw, _) = split_default(rest.split('/').next().unwrap_or(""));
+                let (raw, format) = split_format(raw);
+                let value = path.split('/').next().unwrap_or("");
+                let value = match format {
+                    Some(_) => split_extension(value).0,
+                    None => value,
+                };
+                self.allows(split_constraint(raw).0, value)
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn is_excluded(&self, value: &str) -> bool {
+        self.excluded
+            .iter()
+            .any(|suffix| value.ends_with(suffix.as_str()))
+    }
+
+    pub(crate) fn structurally_eq<U>(&self, other: &Node<U>) -> bool {
+        self.key == other.key
+            && self.kind == other.kind
+            && self.priority == other.priority
+            && self.boost == other.boost
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(other.children.iter())
+                .all(|(a, b)| a.structurally_eq(b))
     }
 
-    pub(crate) fn has_catch_all(&self, pos: usize, size: usize) -> bool {
-        let mut a = self.key.chars();
-        let current = a.nth(pos);
-        let next = a.next();
-        pos < size && ((current == Some('/') && next == Some('*')) || current == Some('*'))
+    /// Detects whether the key continues at *pos* with a catch all parameter,
+    /// possibly after a separator (`/` or one of *extra*).
+    pub(crate) fn has_catch_all(&self, pos: usize, size: usize, extra: &[char]) -> bool {
+        let rest = &self.key[byte_offset(&self.key, pos)..];
+        pos < size
+            && (is_greedy_glob(rest)
+                || rest
+                    .strip_prefix(|ch| is_separator(ch, extra))
+                    .is_some_and(is_greedy_glob))
     }
 
     pub(crate) fn is_named_or_catch_all(&self) -> bool {
@@ -76,14 +741,14 @@ impl<T> Node<T> {
 
 #[cfg(test)]
 mod test {
-    use super::{Kind, Node};
+    use super::{Kind, Node, Syntax};
 
     #[test]
     fn key() {
         let mut node = Node::<()>::new("abc", None, true);
         assert_eq!(node.key, "abc");
 
-        node.key = "xyz".to_string();
+        node.key = "xyz".into();
         assert_eq!(node.key, "xyz");
     }
 
@@ -114,6 +779,15 @@ mod test {
         assert_eq!(node.priority, 3);
     }
 
+    #[test]
+    fn priority_unicode() {
+        let mut node = Node::<()>::new("あいう", None, true);
+        assert_eq!(node.priority, 3);
+
+        node = Node::<()>::new("/あ/:id", None, true);
+        assert_eq!(node.priority, 3);
+    }
+
     #[test]
     fn priority_named() {
         let mut node = Node::<()>::new("/posts/:id", None, true);
@@ -132,6 +806,218 @@ mod test {
         assert_eq!(node.priority, 1);
     }
 
+    #[test]
+    fn map() {
+        let mut root = Node::<i32>::new("/", Some(1), false);
+        root.children = vec![Node::<i32>::new(":id", Some(2), false)];
+        let mapped = root.map(&mut |payload| payload.to_string());
+
+        assert_eq!(mapped.payload, Some("1".to_string()));
+        assert_eq!(mapped.priority, 1);
+        assert_eq!(mapped.children[0].key, ":id");
+        assert_eq!(mapped.children[0].kind, Kind::Named);
+        assert_eq!(mapped.children[0].payload, Some("2".to_string()));
+    }
+
+    #[test]
+    fn structurally_eq() {
+        let mut a = Node::<i32>::new("/", Some(1), false);
+        a.children = vec![Node::<i32>::new(":id", Some(2), false)];
+        let mut b = Node::<&str>::new("/", None, false);
+        b.children = vec![Node::<&str>::new(":id", Some("id"), false)];
+        assert!(a.structurally_eq(&b));
+
+        b.children.push(Node::<&str>::new("about", None, false));
+        assert!(!a.structurally_eq(&b));
+    }
+
+    #[test]
+    fn find_pattern() {
+        let mut root = Node::<i32>::new("/", None, false);
+        let mut products = Node::<i32>::new("products/", None, false);
+        products.children = vec![Node::<i32>::new(":id", Some(1), false)];
+        root.children = vec![products, Node::<i32>::new("*filepath", Some(2), false)];
+
+        assert_eq!(root.find_pattern("/products/:id").unwrap().payload, Some(1));
+        assert_eq!(root.find_pattern("/*filepath").unwrap().payload, Some(2));
+        assert_eq!(root.find_pattern("/products/").unwrap().payload, None);
+        assert!(root.find_pattern("/products/10").is_none());
+        assert!(root.find_pattern("/products/:id/edit").is_none());
+    }
+
+    #[test]
+    fn find_prefix() {
+        let mut root = Node::<i32>::new("/", None, false);
+        let mut products = Node::<i32>::new("products/", None, false);
+        products.children = vec![Node::<i32>::new(":id", Some(1), false)];
+        root.children = vec![products, Node::<i32>::new("*filepath", Some(2), false)];
+
+        let (node, path) = root.find_prefix("/prod", String::new()).unwrap();
+        assert_eq!((&*node.key, path.as_str()), ("products/", "/products/"));
+        let (node, path) = root.find_prefix("/products/:", String::new()).unwrap();
+        assert_eq!((&*node.key, path.as_str()), (":id", "/products/:id"));
+        assert!(root.find_prefix("/products/10", String::new()).is_none());
+
+        let mut routes = Vec::new();
+        root.collect_routes("/", &mut routes);
+        let routes: Vec<_> = routes
+            .into_iter()
+            .map(|(path, node)| (path, node.payload))
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                ("/products/:id".to_string(), Some(1)),
+                ("/*filepath".to_string(), Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn for_each_route() {
+        let mut root = Node::<i32>::new("/", None, false);
+        let mut products = Node::<i32>::new("products/", Some(1), false);
+        products.children = vec![Node::<i32>::new(":id", Some(2), false)];
+        root.children = vec![products, Node::<i32>::new("*filepath", Some(3), false)];
+
+        let mut syntax = Syntax::default();
+        syntax.set_separator("::");
+        let mut buf = String::from("prefix");
+        let mut routes = Vec::new();
+        root.for_each_route(&mut buf, &syntax, &mut |path, payload| {
+            routes.push((path.to_string(), *payload))
+        });
+        assert_eq!(
+            routes,
+            vec![
+                ("prefix::products::".to_string(), 1),
+                ("prefix::products:::id".to_string(), 2),
+                ("prefix::*filepath".to_string(), 3),
+            ]
+        );
+        assert_eq!(buf, "prefix");
+    }
+
+    #[test]
+    fn insert_child() {
+        let mut root = Node::<i32>::new("/", None, false);
+        for key in ["products", "*filepath", "about", ":id", "users"] {
+            root.insert_child(Node::<i32>::new(key, None, false));
+        }
+        let mut sorted = root.clone();
+        sorted.sort_children();
+        assert_eq!(root.children, sorted.children);
+        assert_eq!(root.indices, sorted.indices);
+
+        // shortening the key of a child lowers its priority
+        let pos = root.child_pos('p').unwrap();
+        root.children[pos].set_key("p".to_string());
+        root.reposition_child(pos);
+        let mut sorted = root.clone();
+        sorted.sort_children();
+        assert_eq!(root.children, sorted.children);
+        assert_eq!(root.indices, sorted.indices);
+    }
+
+    #[test]
+    fn child_for() {
+        let mut root = Node::<i32>::new("/", None, false);
+        root.children = vec![
+            Node::<i32>::new("*filepath", Some(1), false),
+            Node::<i32>::new("products", Some(2), false),
+            Node::<i32>::new("about", Some(3), false),
+        ];
+        root.sort_children();
+        assert_eq!(root.indices, vec![('*', 2), ('a', 1), ('p', 0)]);
+
+        assert_eq!(
+            root.child_for("products/10", false, false).unwrap().key,
+            "products"
+        );
+        assert_eq!(root.child_for("about", false, false).unwrap().key, "about");
+        assert_eq!(
+            root.child_for("abc", false, false).unwrap().key,
+            "*filepath"
+        );
+        assert_eq!(root.child_for("x", false, false).unwrap().key, "*filepath");
+        assert_eq!(
+            root.child_for("About", false, false).unwrap().key,
+            "*filepath"
+        );
+        assert_eq!(root.child_for("About", true, false).unwrap().key, "about");
+        assert_eq!(
+            root.child_for("about", false, true).unwrap().key,
+            "*filepath"
+        );
+
+        root.children.pop();
+        root.sort_children();
+        assert!(root.child_for("x", false, false).is_none());
+
+        // a static child whose key holds a parameter sorts after named children
+        root.children = vec![
+            Node::<i32>::new(":id/edit", Some(4), false),
+            Node::<i32>::new("io/*rest", Some(5), false),
+        ];
+        root.sort_children();
+        assert_eq!(
+            root.child_for("10/edit", false, false).unwrap().key,
+            ":id/edit"
+        );
+        assert_eq!(
+            root.child_for("io/a", false, false).unwrap().key,
+            "io/*rest"
+        );
+    }
+
+    #[test]
+    fn compact() {
+        let mut root = Node::<i32>::new("/", None, false);
+        let mut api = Node::<i32>::new("api/", None, false);
+        let mut v1 = Node::<i32>::new("v1/", None, false);
+        v1.children = vec![
+            Node::<i32>::new("users", Some(1), false),
+            Node::<i32>::new(":id", Some(2), false),
+        ];
+        api.children = vec![v1];
+        root.children = vec![api, Node::<i32>::new("about", Some(3), false)];
+
+        root.compact();
+        assert_eq!(root.key, "/");
+        assert_eq!(root.children[0].key, "api/v1/");
+        assert_eq!(root.children[0].children.len(), 2);
+        assert_eq!(root.children[1].key, "about");
+
+        // named children and nodes holding a payload are kept
+        let mut users = Node::<i32>::new("users/", Some(1), false);
+        users.children = vec![Node::<i32>::new("all", Some(2), false)];
+        let mut named = Node::<i32>::new("x/", None, false);
+        named.children = vec![Node::<i32>::new(":id", Some(3), false)];
+        root.children = vec![users, named];
+        root.compact();
+        assert_eq!(root.children[0].key, "users/");
+        assert_eq!(root.children[1].key, "x/");
+
+        // the merged node keeps the boost of the route it now holds
+        let mut boosted = Node::<i32>::new("b", Some(4), false);
+        boosted.boost = 10;
+        let mut chain = Node::<i32>::new("a/", None, false);
+        chain.children = vec![boosted];
+        root.children = vec![Node::<i32>::new("longer", Some(5), false), chain];
+        root.compact();
+        assert_eq!(root.children[0].key, "a/b");
+        assert_eq!(root.children[0].boost, 10);
+        assert_eq!(root.children[1].key, "longer");
+    }
+
+    #[test]
+    fn excluded() {
+        let mut node = Node::<()>::new("*filepath", None, false);
+        node.excluded = vec![".php".to_string()];
+        assert!(node.is_excluded("admin/index.php"));
+        assert!(!node.is_excluded("index.html"));
+    }
+
     #[test]
     fn sort() {
         let mut root = Node::<i32>::new("/", None, true);
@@ -146,6 +1032,51 @@ mod test {
         assert_eq!(root.children[2].key, "a");
     }
 
+    #[test]
+    fn sort_unicode() {
+        let mut root = Node::<i32>::new("/", None, true);
+        let node1 = Node::<i32>::new("あいう", None, true);
+        let node2 = Node::<i32>::new("abcd", None, true);
+        let node3 = Node::<i32>::new("か", None, true);
+        root.children = vec![node1, node2, node3];
+        root.sort_children();
+
+        assert_eq!(root.children[0].key, "abcd");
+        assert_eq!(root.children[1].key, "あいう");
+        assert_eq!(root.children[2].key, "か");
+    }
+
+    #[test]
+    fn sort_same_priority() {
+        let mut root = Node::<i32>::new("/", None, true);
+        let node1 = Node::<i32>::new("b", None, true);
+        let node2 = Node::<i32>::new("c", None, true);
+        let node3 = Node::<i32>::new("a", None, true);
+        root.children = vec![node1, node2, node3];
+        root.sort_children();
+
+        assert_eq!(root.children[0].key, "a");
+        assert_eq!(root.children[1].key, "b");
+        assert_eq!(root.children[2].key, "c");
+    }
+
+    #[test]
+    fn sort_boosted() {
+        let mut root = Node::<i32>::new("/", None, true);
+        let node1 = Node::<i32>::new("abc", None, true);
+        let mut node2 = Node::<i32>::new("x", None, true);
+        node2.boost = 1;
+        let mut node3 = Node::<i32>::new(":query", None, true);
+        node3.boost = 10;
+        root.children = vec![node1, node2, node3];
+        root.sort_children();
+
+        // boost only orders nodes of the same kind
+        assert_eq!(root.children[0].key, "x");
+        assert_eq!(root.children[1].key, "abc");
+        assert_eq!(root.children[2].key, ":query");
+    }
+
     #[test]
     fn sort_named_and_glob() {
         let mut root = Node::<i32>::new("/", None, true);
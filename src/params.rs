@@ -0,0 +1,41 @@
+use crate::error::ParamError;
+use crate::result::Result;
+
+/// A type built from the parameters captured by a route, e.g. a struct with one
+/// field per parameter, see `Result::params_as` and `Tree::add_typed`.
+///
+/// # Examples
+///
+/// ```
+/// use patricia_router::{FromParams, ParamError, Result, Tree};
+///
+/// struct UserPost {
+///     user_id: u64,
+///     slug: String,
+/// }
+///
+/// impl FromParams for UserPost {
+///     const NAMES: &'static [&'static str] = &["user_id", "slug"];
+///
+///     fn from_params<T>(result: &Result<'_, T>) -> std::result::Result<Self, ParamError> {
+///         Ok(UserPost {
+///             user_id: result.require("user_id")?,
+///             slug: result.require("slug")?,
+///         })
+///     }
+/// }
+///
+/// let mut tree = Tree::<&str>::new();
+/// tree.add_typed::<UserPost>("/users/:user_id/posts/:slug", "post");
+/// let post: UserPost = tree.find("/users/42/posts/hello").params_as().unwrap();
+/// assert_eq!(post.user_id, 42);
+/// assert_eq!(post.slug, "hello");
+/// ```
+pub trait FromParams: Sized {
+    /// Names of the parameters read by `from_params`.
+    const NAMES: &'static [&'static str];
+
+    /// Builds the value from the parameters of *result*, usually through
+    /// `Result::require`.
+    fn from_params<T>(result: &Result<'_, T>) -> std::result::Result<Self, ParamError>;
+}
@@ -0,0 +1,562 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::error::PatternError;
+use crate::node::Kind;
+use crate::tree::UNNAMED_CATCH_ALL;
+use crate::utils::{split_constraint, split_default, split_format};
+
+/// A named (`:`) or catch all (`*`) parameter declared by a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Param<'p> {
+    /// Position (in characters) of the marker in the pattern.
+    pub(crate) pos: usize,
+    pub(crate) kind: Kind,
+    /// Name following the marker, up to the next separator for named
+    /// parameters (including a `{a|b}` constraint) and catch all parameters
+    /// limited to a segment (including the `?`), up to the end of the pattern
+    /// for other catch all parameters.
+    pub(crate) name: &'p str,
+}
+
+impl<'p> Param<'p> {
+    /// Name of the parameter without the constraint and default value of a
+    /// named parameter or the `?` of a catch all parameter limited to a segment.
+    fn bare_name(&self) -> &'p str {
+        match self.kind {
+            Kind::Named => split_constraint(split_format(split_default(self.name).0).0).0,
+            _ => self.name.strip_prefix('?').unwrap_or(self.name),
+        }
+    }
+
+    /// Name of the parameter capturing the optional extension following a
+    /// named parameter (e.g. `format` for `id.:format?`).
+    fn format(&self) -> Option<&'p str> {
+        match self.kind {
+            Kind::Named => split_format(split_default(self.name).0).1,
+            _ => None,
+        }
+    }
+}
+
+/// A named (`:`) or catch all (`*`) parameter of a route, as yielded by
+/// `Tree::dynamic_routes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamSpec {
+    /// Name the parameter is captured as, without constraint.
+    pub name: String,
+    pub kind: Kind,
+}
+
+/// Extracts the parameters declared by *pattern*, in order.
+pub(crate) fn parse_params(pattern: &str) -> Vec<Param<'_>> {
+    let mut params = Vec::new();
+    let mut chars = pattern.char_indices().enumerate();
+    while let Some((pos, (offset, ch))) = chars.next() {
+        let kind = match Kind::from_marker(ch) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let rest = &pattern[offset + ch.len_utf8()..];
+        let name = match kind {
+            Kind::Glob if !rest.starts_with('?') => rest,
+            _ => rest.split('/').next().unwrap_or(""),
+        };
+        params.push(Param { pos, kind, name });
+        // skip the name, a separator following it is not part of the parameter
+        for _ in 0..name.chars().count() {
+            chars.next();
+        }
+    }
+    params
+}
+
+/// Returns the names of the parameters *pattern* captures, in order. Catch all
+/// parameters without a name are captured as `UNNAMED_CATCH_ALL`.
+pub(crate) fn param_names(pattern: &str) -> Vec<String> {
+    parse_params(pattern)
+        .iter()
+        .flat_map(|param| {
+            let name = match param.bare_name() {
+                "" => UNNAMED_CATCH_ALL,
+                name => name,
+            };
+            std::iter::once(name).chain(param.format())
+        })
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Returns the parameters *pattern* captures, in order, with the names they
+/// are captured as (see `param_names`).
+pub(crate) fn param_specs(pattern: &str) -> Vec<ParamSpec> {
+    parse_params(pattern)
+        .into_iter()
+        .flat_map(|param| {
+            let name = match param.bare_name() {
+                "" => UNNAMED_CATCH_ALL,
+                name => name,
+            };
+            let format = param.format().map(|format| ParamSpec {
+                name: format.to_string(),
+                kind: Kind::Named,
+            });
+            let spec = ParamSpec {
+                name: name.to_string(),
+                kind: param.kind,
+            };
+            std::iter::once(spec).chain(format)
+        })
+        .collect()
+}
+
+/// Checks *pattern* for mistakes that would make the route behave unexpectedly:
+///
+/// - A named parameter without name (e.g. `/:/edit`).
+/// - A catch all parameter followed by more segments (e.g. `/*path/edit`).
+/// - A marker inside the name of a parameter (e.g. `/:id:slug`).
+/// - Two parameters with the same name (e.g. `/:id/:id`).
+///
+/// # Examples
+///
+/// ```
+/// use patricia_router::{validate_pattern, PatternError};
+///
+/// assert_eq!(validate_pattern("/products/:id/edit"), Ok(()));
+/// assert_eq!(validate_pattern("/files/*path/edit"), Err(PatternError::CatchAllNotLast(7)));
+/// ```
+pub fn validate_pattern(pattern: &str) -> Result<(), PatternError> {
+    let mut names = HashSet::new();
+    for mut param in parse_params(pattern) {
+        let format = param.format();
+        param.name = param.bare_name();
+        if param.kind == Kind::Glob && param.name.contains('/') {
+            return Err(PatternError::CatchAllNotLast(param.pos));
+        }
+        if param.kind == Kind::Named && (param.name.is_empty() || format == Some("")) {
+            return Err(PatternError::EmptyParamName(param.pos));
+        }
+        if let Some(stray) = param
+            .name
+            .chars()
+            .position(|ch| Kind::from_marker(ch).is_some())
+        {
+            return Err(PatternError::StrayMarker(param.pos + 1 + stray));
+        }
+        let name = match param.name {
+            "" => UNNAMED_CATCH_ALL,
+            name => name,
+        };
+        for name in std::iter::once(name).chain(format) {
+            if !names.insert(name) {
+                return Err(PatternError::DuplicateParamName(name.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compares two routes in the order `Tree::from_sorted` expects them:
+/// character by character, with the named (`:`) and then the catch all (`*`)
+/// markers sorting after every other character. A route thus comes after its
+/// prefixes, and static routes come before the dynamic ones sharing their
+/// prefix.
+///
+/// # Examples
+///
+/// ```
+/// use std::cmp::Ordering;
+/// use patricia_router::cmp_routes;
+///
+/// assert_eq!(cmp_routes("/products/new", "/products/:id"), Ordering::Less);
+/// assert_eq!(cmp_routes("/products/:id", "/products/:id/edit"), Ordering::Less);
+/// assert_eq!(cmp_routes("/*filepath", "/about"), Ordering::Greater);
+/// ```
+pub fn cmp_routes(a: &str, b: &str) -> Ordering {
+    let rank = |ch: char| (Kind::from_marker(ch), ch);
+    a.chars().map(rank).cmp(b.chars().map(rank))
+}
+
+/// Lowercases the static parts of *pattern*, keeping the names (and allowed
+/// values) of its parameters as they are.
+pub(crate) fn normalize_pattern(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut normalized = String::with_capacity(pattern.len());
+    let mut pos = 0;
+    for param in parse_params(pattern) {
+        normalized.extend(
+            chars[pos..param.pos]
+                .iter()
+                .flat_map(|ch| ch.to_lowercase()),
+        );
+        pos = param.pos + 1 + param.name.chars().count();
+        normalized.extend(&chars[param.pos..pos]);
+    }
+    normalized.extend(chars[pos..].iter().flat_map(|ch| ch.to_lowercase()));
+    normalized
+}
+
+/// Piece of a pattern matching part of a path, see `patterns_overlap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    /// A static character.
+    Char(char),
+    /// Any character but the separator, once.
+    Segment,
+    /// Any character but the separator, any number of times.
+    SegmentRest,
+    /// Any character, any number of times.
+    Rest,
+}
+
+impl Token {
+    fn repeats(self) -> bool {
+        matches!(self, Token::SegmentRest | Token::Rest)
+    }
+
+    /// Whether some character is matched by both tokens.
+    fn intersects(self, other: Token) -> bool {
+        match (self, other) {
+            (Token::Char(a), Token::Char(b)) => a == b,
+            (Token::Char(ch), Token::Segment | Token::SegmentRest)
+            | (Token::Segment | Token::SegmentRest, Token::Char(ch)) => ch != '/',
+            _ => true,
+        }
+    }
+}
+
+/// Expands *pattern* into the token sequences it matches, one per value of
+/// its constrained named parameters. A trailing `/*` also matches without
+/// the separator, like `find` does, and so does a trailing parameter with a
+/// default value.
+fn tokenize(pattern: &str) -> Vec<Vec<Token>> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut alternatives = vec![Vec::new()];
+    let mut pos = 0;
+    for param in parse_params(pattern) {
+        let literal: Vec<Token> = chars[pos..param.pos]
+            .iter()
+            .map(|&ch| Token::Char(ch))
+            .collect();
+        for tokens in alternatives.iter_mut() {
+            tokens.extend(&literal);
+        }
+        pos = param.pos + 1 + param.name.chars().count();
+        let format = param.format();
+        let optional = if param.kind == Kind::Named
+            && pos == chars.len()
+            && split_default(param.name).1.is_some()
+        {
+            alternatives.clone()
+        } else {
+            Vec::new()
+        };
+        match (
+            param.kind,
+            split_constraint(split_format(split_default(param.name).0).0).1,
+        ) {
+            (Kind::Named, Some(values)) => {
+                alternatives = alternatives
+                    .iter()
+                    .flat_map(|tokens| {
+                        values.iter().map(move |value| {
+                            let mut tokens = tokens.clone();
+                            tokens.extend(value.chars().map(Token::Char));
+                            tokens
+                        })
+                    })
+                    .collect();
+                if format.is_some() {
+                    let extended: Vec<Vec<Token>> = alternatives
+                        .iter()
+                        .map(|tokens| {
+                            let mut tokens = tokens.clone();
+                            tokens.extend([Token::Char('.'), Token::SegmentRest]);
+                            tokens
+                        })
+                        .collect();
+                    alternatives.extend(extended);
+                }
+            }
+            (Kind::Glob, _) if param.name.starts_with('?') => {
+                for tokens in alternatives.iter_mut() {
+                    tokens.extend([Token::Segment, Token::SegmentRest]);
+                }
+            }
+            (Kind::Named, None) => {
+                for tokens in alternatives.iter_mut() {
+                    tokens.extend([Token::Segment, Token::SegmentRest]);
+                }
+            }
+            _ => {
+                for tokens in alternatives.iter_mut() {
+                    tokens.push(Token::Rest);
+                }
+            }
+        }
+        for tokens in optional {
+            if let [init @ .., Token::Char('/')] = tokens.as_slice() {
+                alternatives.push(init.to_vec());
+            }
+            alternatives.push(tokens);
+        }
+    }
+    let literal: Vec<Token> = chars[pos..].iter().map(|&ch| Token::Char(ch)).collect();
+    for tokens in alternatives.iter_mut() {
+        tokens.extend(&literal);
+    }
+    let bare: Vec<Vec<Token>> = alternatives
+        .iter()
+        .filter_map(|tokens| match tokens.as_slice() {
+            [init @ .., Token::Char('/'), Token::Rest] => Some(init.to_vec()),
+            _ => None,
+        })
+        .collect();
+    alternatives.extend(bare);
+    alternatives
+}
+
+/// Whether some path is matched by both token sequences.
+fn tokens_overlap(a: &[Token], b: &[Token]) -> bool {
+    let mut memo = vec![None; (a.len() + 1) * (b.len() + 1)];
+    overlap_from(a, b, 0, 0, &mut memo)
+}
+
+/// Whether some path is matched by both `a[i..]` and `b[j..]`, remembering
+/// the answer for every `(i, j)` in *memo* so that repetitions don't explore
+/// the same suffixes again.
+fn overlap_from(a: &[Token], b: &[Token], i: usize, j: usize, memo: &mut [Option<bool>]) -> bool {
+    let slot = i * (b.len() + 1) + j;
+    if let Some(overlap) = memo[slot] {
+        return overlap;
+    }
+    let overlap = match (a.get(i), b.get(j)) {
+        (None, None) => true,
+        (Some(&x), _) if x.repeats() => {
+            // the repetition either ends here or takes the next character of b
+            overlap_from(a, b, i + 1, j, memo)
+                || b.get(j)
+                    .is_some_and(|&y| x.intersects(y) && overlap_from(a, b, i, j + 1, memo))
+        }
+        (_, Some(&y)) if y.repeats() => {
+            overlap_from(a, b, i, j + 1, memo)
+                || a.get(i)
+                    .is_some_and(|&x| x.intersects(y) && overlap_from(a, b, i + 1, j, memo))
+        }
+        (Some(&x), Some(&y)) => x.intersects(y) && overlap_from(a, b, i + 1, j + 1, memo),
+        _ => false,
+    };
+    memo[slot] = Some(overlap);
+    overlap
+}
+
+/// Checks whether some path would be matched by both *a* and *b*, e.g. to
+/// detect a route shadowed by another one. See `Tree::patterns_overlap`.
+pub(crate) fn patterns_overlap(a: &str, b: &str) -> bool {
+    let b = tokenize(b);
+    tokenize(a)
+        .iter()
+        .any(|a| b.iter().any(|b| tokens_overlap(a, b)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_params() {
+        assert!(parse_params("/products").is_empty());
+
+        let params = parse_params("/:section/:page/*rest");
+        assert_eq!(params.len(), 3);
+        assert_eq!(
+            params[0],
+            Param {
+                pos: 1,
+                kind: Kind::Named,
+                name: "section"
+            }
+        );
+        assert_eq!(
+            params[1],
+            Param {
+                pos: 10,
+                kind: Kind::Named,
+                name: "page"
+            }
+        );
+        assert_eq!(
+            params[2],
+            Param {
+                pos: 16,
+                kind: Kind::Glob,
+                name: "rest"
+            }
+        );
+
+        let params = parse_params("/あ/:い/*");
+        assert_eq!(
+            params[0],
+            Param {
+                pos: 3,
+                kind: Kind::Named,
+                name: "い"
+            }
+        );
+        assert_eq!(
+            params[1],
+            Param {
+                pos: 6,
+                kind: Kind::Glob,
+                name: ""
+            }
+        );
+    }
+
+    #[test]
+    fn test_param_names() {
+        assert!(param_names("/products").is_empty());
+        assert_eq!(
+            param_names("/posts/:status{draft|published}/*?dir/:id/*"),
+            vec!["status", "dir", "id", UNNAMED_CATCH_ALL]
+        );
+        assert_eq!(
+            param_names("/posts/:id.:format?/comments"),
+            vec!["id", "format"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_pattern() {
+        assert_eq!(
+            normalize_pattern("/Products/Featured"),
+            "/products/featured"
+        );
+        assert_eq!(normalize_pattern("/Users/:Id/Edit"), "/users/:Id/edit");
+        assert_eq!(
+            normalize_pattern("/Posts/:Status{Draft|Live}/*FilePath"),
+            "/posts/:Status{Draft|Live}/*FilePath"
+        );
+        assert_eq!(normalize_pattern("/ÜBER/Straße"), "/über/straße");
+        assert_eq!(normalize_pattern("/File.:Ext"), "/file.:Ext");
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("/a"),
+            vec![vec![Token::Char('/'), Token::Char('a')]]
+        );
+        assert_eq!(
+            tokenize("/:id"),
+            vec![vec![Token::Char('/'), Token::Segment, Token::SegmentRest]]
+        );
+        assert_eq!(
+            tokenize("/:v{1|2}"),
+            vec![
+                vec![Token::Char('/'), Token::Char('1')],
+                vec![Token::Char('/'), Token::Char('2')],
+            ]
+        );
+        assert_eq!(
+            tokenize("/*"),
+            vec![vec![Token::Char('/'), Token::Rest], vec![]]
+        );
+    }
+
+    #[test]
+    fn test_patterns_overlap() {
+        assert!(patterns_overlap("/users/:id", "/users/42"));
+        assert!(patterns_overlap(
+            "/users/:id{1|2}.:format?",
+            "/users/1.json"
+        ));
+        assert!(!patterns_overlap(
+            "/users/:id{1|2}.:format?",
+            "/users/3.json"
+        ));
+        assert!(patterns_overlap("/users/:id", "/users/:name"));
+        assert!(!patterns_overlap("/users/:id", "/posts/:id"));
+        assert!(!patterns_overlap("/users/:id", "/users/42/edit"));
+        assert!(!patterns_overlap("/users/:id", "/users/"));
+        assert!(patterns_overlap("/users/:id/edit", "/users/42/:action"));
+        assert!(patterns_overlap("/*path", "/users/:id/edit"));
+        assert!(patterns_overlap("/files/*path", "/files"));
+        assert!(!patterns_overlap("/files/*path", "/file"));
+        assert!(patterns_overlap("/x:fmt", "/:name"));
+        assert!(!patterns_overlap("/:status{draft|published}", "/archived"));
+        assert!(!patterns_overlap("/files/*?dir", "/files/a/b"));
+        assert!(patterns_overlap("/files/*?dir/b", "/files/:name/:file"));
+        assert!(patterns_overlap(
+            "/:status{draft|published}",
+            "/:state{published}"
+        ));
+        assert!(patterns_overlap("/posts/:page=1", "/posts"));
+        assert!(patterns_overlap("/posts/:page=1", "/posts/"));
+        assert!(patterns_overlap("/posts/:page=1", "/posts/2"));
+        assert!(!patterns_overlap("/posts/:page=1", "/post"));
+        assert!(patterns_overlap(
+            "/archive/:status{draft|published}=draft",
+            "/archive"
+        ));
+    }
+
+    #[test]
+    fn test_patterns_overlap_many_params() {
+        let a = "/:p".repeat(24);
+        let b = format!("{}/x", "/:q".repeat(24));
+        assert!(!patterns_overlap(&a, &b));
+        assert!(!patterns_overlap(&b, &a));
+        assert!(patterns_overlap(&format!("{}/*rest", a), &b));
+    }
+
+    #[test]
+    fn test_validate_pattern() {
+        assert_eq!(validate_pattern(""), Ok(()));
+        assert_eq!(validate_pattern("/"), Ok(()));
+        assert_eq!(validate_pattern("/products/:id/edit"), Ok(()));
+        assert_eq!(validate_pattern("/files/*"), Ok(()));
+        assert_eq!(validate_pattern("/members*trailing"), Ok(()));
+        assert_eq!(validate_pattern("/posts/:status{draft|published}"), Ok(()));
+        assert_eq!(validate_pattern("/files/*?dir/*path"), Ok(()));
+        assert_eq!(validate_pattern("/files/*?/edit"), Ok(()));
+        assert_eq!(validate_pattern("/posts/:id{1|2}.:format?"), Ok(()));
+
+        assert_eq!(
+            validate_pattern("/:/edit"),
+            Err(PatternError::EmptyParamName(1))
+        );
+        assert_eq!(
+            validate_pattern("/users/:"),
+            Err(PatternError::EmptyParamName(7))
+        );
+        assert_eq!(
+            validate_pattern("/*path/edit"),
+            Err(PatternError::CatchAllNotLast(1))
+        );
+        assert_eq!(
+            validate_pattern("/:id:slug"),
+            Err(PatternError::StrayMarker(4))
+        );
+        assert_eq!(
+            validate_pattern("/*path*"),
+            Err(PatternError::StrayMarker(6))
+        );
+        assert_eq!(
+            validate_pattern("/posts/:id.:?"),
+            Err(PatternError::EmptyParamName(7))
+        );
+        assert_eq!(
+            validate_pattern("/:format/:id.:format?"),
+            Err(PatternError::DuplicateParamName("format".to_string()))
+        );
+        assert_eq!(
+            validate_pattern("/:id/x/:id"),
+            Err(PatternError::DuplicateParamName("id".to_string()))
+        );
+        assert_eq!(
+            validate_pattern("/:splat/*"),
+            Err(PatternError::DuplicateParamName("splat".to_string()))
+        );
+    }
+}
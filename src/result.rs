@@ -1,13 +1,111 @@
+use std::cell::OnceCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
-use crate::node::Node;
+use crate::error::ParamError;
+#[cfg(feature = "debug")]
+use crate::explain::RejectionReason;
+use crate::node::{Kind, Node};
+use crate::params::FromParams;
+#[cfg(feature = "instrumentation")]
+use crate::stats::FindStats;
+use crate::syntax::Syntax;
+use crate::tree::{MatchPolicy, TrailingSlash};
+use crate::utils::percent_decode;
+
+// Storage of the captured values. The `smallstr` feature keeps short values
+// inline, the `String`s handed out by `Result::params` being then built on
+// first use; `Result::param` reads the storage either way.
+#[cfg(not(feature = "smallstr"))]
+type ParamValue = String;
+#[cfg(feature = "smallstr")]
+type ParamValue = smol_str::SmolStr;
+
+// without `smallstr` the conversion is a no-op
+#[allow(clippy::useless_conversion)]
+fn into_param_value(value: String) -> ParamValue {
+    value.into()
+}
+
+#[cfg(not(feature = "smallstr"))]
+fn into_strings(params: HashMap<String, ParamValue>) -> HashMap<String, String> {
+    params
+}
+
+#[cfg(feature = "smallstr")]
+fn into_strings(params: HashMap<String, ParamValue>) -> HashMap<String, String> {
+    params
+        .into_iter()
+        .map(|(name, value)| (name, value.to_string()))
+        .collect()
+}
+
+/// Receives parameters while walking the tree instead of the params of a Result.
+pub(crate) type Sink<'s> = Option<&'s mut dyn FnMut(&str, &str)>;
+
+/// What produced the payload of a Result, see `Result::matched`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matched {
+    /// A route without catch all parameter.
+    Route,
+    /// A route ending in a catch all (`*name`), capturing the rest of the path.
+    CatchAll,
+    /// No route, the fallback set through `Tree::set_fallback`.
+    NotFound,
+    /// Nothing, the payload is `None`.
+    Nothing,
+}
+
+/// State of a Result when it went through a payload-bearing node.
+#[derive(Debug, PartialEq, Eq)]
+struct Ancestor<'a, T> {
+    depth: usize,
+    node: &'a Node<T>,
+    params: HashMap<String, ParamValue>,
+    ranges: usize,
+}
 
 /// A Result is the comulative output of walking our [Radix tree](https://en.wikipedia.org/wiki/Radix_tree)
 #[derive(Debug, PartialEq, Eq)]
 pub struct Result<'a, T> {
-    key: Option<String>,
+    key: OnceCell<String>,
     nodes: Vec<&'a Node<T>>,
-    pub(crate) params: HashMap<String, String>,
+    pub(crate) params: HashMap<String, ParamValue>,
+    #[cfg(feature = "smallstr")]
+    strings: OnceCell<HashMap<String, String>>,
+    pub(crate) catch_all_start: Option<usize>,
+    pub(crate) borrowed: bool,
+    pub(crate) track_ancestor: bool,
+    /// Whether the node the path ended at is kept even without payload, for
+    /// `Tree::allowed_at` to list its children.
+    pub(crate) keep_stop: bool,
+    pub(crate) ignore_case: bool,
+    pub(crate) track_ranges: bool,
+    pub(crate) policy: MatchPolicy,
+    pub(crate) trailing_slash: TrailingSlash,
+    /// Whether the route only matched by adding or dropping a trailing slash.
+    pub(crate) slash_mismatch: bool,
+    /// Whether a catch all match falls through to the fallback.
+    pub(crate) catch_all_not_found: bool,
+    /// Characters ending segments besides `/`, see `Tree::with_separators`.
+    pub(crate) separators: &'a [char],
+    syntax: Option<&'a Syntax>,
+    ranges: Vec<(String, Range<usize>)>,
+    #[cfg(feature = "instrumentation")]
+    pub(crate) stats: FindStats,
+    /// Reasons collected by `Tree::find_explain`, `None` for other lookups.
+    #[cfg(feature = "debug")]
+    pub(crate) rejections: Option<Vec<RejectionReason>>,
+    fallback: bool,
+    /// Whether to look for rivals of the children walked into, see `Tree::find_strict`.
+    pub(crate) strict: bool,
+    /// Pattern of the first rival route matching the path as well.
+    pub(crate) rival: Option<String>,
+    /// Path given to `Tree::find`, kept when a route matched it.
+    pub(crate) matched_path: Option<String>,
+    ancestor: Option<Ancestor<'a, T>>,
     pub payload: &'a Option<T>,
 }
 
@@ -15,9 +113,33 @@ impl<'a, T> Result<'a, T> {
     #[doc(hidden)]
     pub(crate) fn new() -> Self {
         Self {
-            key: None,
+            key: OnceCell::new(),
             nodes: Vec::<&'a Node<T>>::new(),
             params: HashMap::new(),
+            #[cfg(feature = "smallstr")]
+            strings: OnceCell::new(),
+            catch_all_start: None,
+            borrowed: false,
+            track_ancestor: false,
+            keep_stop: false,
+            ignore_case: false,
+            track_ranges: false,
+            policy: MatchPolicy::default(),
+            trailing_slash: TrailingSlash::default(),
+            slash_mismatch: false,
+            catch_all_not_found: false,
+            separators: &[],
+            syntax: None,
+            ranges: Vec::new(),
+            #[cfg(feature = "instrumentation")]
+            stats: FindStats::default(),
+            #[cfg(feature = "debug")]
+            rejections: None,
+            fallback: false,
+            strict: false,
+            rival: None,
+            matched_path: None,
+            ancestor: None,
             payload: &None,
         }
     }
@@ -31,33 +153,484 @@ impl<'a, T> Result<'a, T> {
         self
     }
 
-    /// Returns a String built based on the nodes used in the result.
-    pub fn key(&mut self) -> String {
-        match &self.key {
-            Some(k) => k.to_string(),
+    pub(crate) fn capture(&mut self, name: &str, value: &str, start: usize, sink: &mut Sink) {
+        if self.track_ranges {
+            self.ranges
+                .push((name.to_string(), start..start + value.len()));
+        }
+        match sink {
+            Some(sink) => sink(name, value),
             None => {
-                let result = self.compute_key();
-                self.key = Some(result.clone());
-                result
+                self.params
+                    .insert(name.to_string(), ParamValue::from(value));
             }
         }
     }
 
-    fn compute_key(&self) -> String {
+    /// Captures the named parameter *name* the path ended before with its
+    /// *default* value, whose range is empty at *start* since it isn't part
+    /// of the path.
+    pub(crate) fn capture_default(
+        &mut self,
+        name: &str,
+        default: &str,
+        start: usize,
+        sink: &mut Sink,
+    ) {
+        self.capture(name, default, start, sink);
+        if self.track_ranges {
+            if let Some((_, range)) = self.ranges.last_mut() {
+                range.end = start;
+            }
+        }
+    }
+
+    /// Calls *f* with the pattern walked so far followed by the key of *node*
+    /// when collecting rejections for `Tree::find_explain`.
+    #[cfg(feature = "debug")]
+    pub(crate) fn explain<F>(&mut self, node: &Node<T>, f: F)
+    where
+        F: FnOnce(String, &mut Vec<RejectionReason>),
+    {
+        if self.rejections.is_none() {
+            return;
+        }
+        let pattern = self.pattern_through(node);
+        if let Some(rejections) = self.rejections.as_mut() {
+            f(pattern, rejections);
+        }
+    }
+
+    /// Returns the pattern walked so far followed by the key of *node*.
+    pub(crate) fn pattern_through(&self, node: &Node<T>) -> String {
         self.nodes
             .iter()
             .fold(String::new(), |acc, &node| acc + &node.key)
+            + &node.key
+    }
+
+    pub(crate) fn leaf(&self) -> Option<&'a Node<T>> {
+        self.nodes.last().copied()
+    }
+
+    pub(crate) fn mark_ancestor(&mut self, node: &'a Node<T>) {
+        if self.track_ancestor {
+            self.ancestor = Some(Ancestor {
+                depth: self.nodes.len(),
+                node,
+                params: self.params.clone(),
+                ranges: self.ranges.len(),
+            });
+        }
+    }
+
+    pub(crate) fn into_ancestor(mut self) -> Self {
+        if self.payload.is_some() {
+            return self;
+        }
+        match self.ancestor.take() {
+            Some(ancestor) => {
+                self.nodes.truncate(ancestor.depth);
+                self.params = ancestor.params;
+                self.ranges.truncate(ancestor.ranges);
+                self.catch_all_start = None;
+                self.add(ancestor.node, true)
+            }
+            None => self,
+        }
+    }
+
+    pub(crate) fn or_fallback(mut self, fallback: &'a Option<T>) -> Self {
+        if self.payload.is_some() && !(self.catch_all_not_found && self.catch_all_start.is_some()) {
+            return self;
+        }
+        self.payload = &None;
+        self.matched_path = None;
+        // drop what the walk collected before failing to match
+        self.key = OnceCell::new();
+        self.nodes.clear();
+        self.params.clear();
+        self.ranges.clear();
+        self.catch_all_start = None;
+        self.slash_mismatch = false;
+        if fallback.is_some() {
+            self.fallback = true;
+            self.payload = fallback;
+        }
+        self
+    }
+
+    /// Translates the separator (`/`) and markers (`:` and `*`) of the nodes
+    /// and of *path*, the path the walk went through, into the ones of *syntax*.
+    pub(crate) fn with_syntax(mut self, path: &str, syntax: &'a Syntax) -> Self {
+        // positions move by the extra length of every separator before them,
+        // markers being ASCII like the ones they replace
+        let extra = syntax.separator().len() - 1;
+        let external = |offset: usize| offset + path[..offset].matches('/').count() * extra;
+        for value in self.params.values_mut() {
+            *value = into_param_value(syntax.external(value));
+        }
+        for (_, range) in self.ranges.iter_mut() {
+            *range = external(range.start)..external(range.end);
+        }
+        self.catch_all_start = self.catch_all_start.map(external);
+        self.syntax = Some(syntax);
+        self
+    }
+
+    /// Percent-decodes the captured parameters. Runs once the walk is over so
+    /// an encoded separator (`%2F`) never splits a segment.
+    pub(crate) fn decode_params(mut self) -> Self {
+        for value in self.params.values_mut() {
+            *value = into_param_value(percent_decode(value));
+        }
+        self
+    }
+
+    /// Returns whether no route matched and the payload is the fallback set
+    /// through `Tree::set_fallback`.
+    pub fn is_fallback(&self) -> bool {
+        self.fallback
+    }
+
+    /// Returns whether the payload comes from a route, a catch all route or the
+    /// fallback.
+    pub fn matched(&self) -> Matched {
+        if self.fallback {
+            Matched::NotFound
+        } else if self.payload.is_none() {
+            Matched::Nothing
+        } else if self.catch_all_start.is_some() {
+            Matched::CatchAll
+        } else {
+            Matched::Route
+        }
+    }
+
+    /// Returns the path given to `Tree::find` when it matched a route, e.g. to
+    /// log it next to `key`. Returns `None` for the fallback and for Results of
+    /// other lookups.
+    pub fn matched_path(&self) -> Option<&str> {
+        self.matched_path.as_deref()
+    }
+
+    /// Returns the HTTP status to redirect with when the route only matched by
+    /// adding or dropping a trailing slash, as configured through
+    /// `Tree::set_trailing_slash`: `308` for `TrailingSlash::RedirectPermanent`,
+    /// `307` for `TrailingSlash::RedirectTemporary`, `301` for
+    /// `TrailingSlash::RedirectMovedPermanently` and `302` for
+    /// `TrailingSlash::RedirectFound`. Unlike `301` and `302`, `308` and `307`
+    /// keep the method and body of the request.
+    pub fn redirect_status(&self) -> Option<u16> {
+        if !self.slash_mismatch || self.fallback || self.payload.is_none() {
+            return None;
+        }
+        match self.trailing_slash {
+            TrailingSlash::Match => None,
+            TrailingSlash::RedirectPermanent => Some(308),
+            TrailingSlash::RedirectTemporary => Some(307),
+            TrailingSlash::RedirectMovedPermanently => Some(301),
+            TrailingSlash::RedirectFound => Some(302),
+        }
+    }
+
+    /// Returns a String built based on the nodes used in the result.
+    pub fn key(&self) -> String {
+        self.key.get_or_init(|| self.compute_key()).clone()
+    }
+
+    /// Returns the key of the last node walked through, the end of the pattern
+    /// returned by `key` (e.g. `:id` or `/:id` for `/users/:id`, depending on
+    /// the other routes of the Tree). Unlike `key`, it keeps the separator and
+    /// markers of the nodes (`/`, `:` and `*`). Returns `None` when no route
+    /// matched.
+    pub fn leaf_key(&self) -> Option<&'a str> {
+        self.leaf().map(|node| &*node.key)
+    }
+
+    /// Returns the nodes walked through and the payload.
+    pub(crate) fn into_nodes(self) -> (Vec<&'a Node<T>>, &'a Option<T>) {
+        (self.nodes, self.payload)
+    }
+
+    /// Returns the key and the payload, computing the key unless cached.
+    pub(crate) fn into_key(mut self) -> (&'a Option<T>, String) {
+        let key = self.key.take().unwrap_or_else(|| self.compute_key());
+        (self.payload, key)
+    }
+
+    fn compute_key(&self) -> String {
+        let key = self
+            .nodes
+            .iter()
+            .fold(String::new(), |acc, &node| acc + &node.key);
+        match self.syntax {
+            Some(syntax) => syntax.external(&key),
+            None => key,
+        }
+    }
+
+    /// Returns the payload of the deepest route above the matched one whose
+    /// last node holds a named or catch all parameter, e.g. `/users/:id` for
+    /// `/users/:id/posts`.
+    pub fn nearest_dynamic_ancestor(&self) -> Option<&'a T> {
+        let (_, ancestors) = self.nodes.split_last()?;
+        ancestors
+            .iter()
+            .rev()
+            .filter(|node| *node.kind() != Kind::Normal)
+            .find_map(|node| node.payload.as_ref())
+    }
+
+    /// Returns the catch-all parameter as a slice of *path*, which must be the
+    /// path given to `Tree::find` or `Tree::find_borrowed`. Like the captured
+    /// parameter, it leaves out the trailing separator of *path*.
+    pub fn catch_all<'p>(&self, path: &'p str) -> Option<&'p str> {
+        let value = path.get(self.catch_all_start?..)?;
+        if !self.slash_mismatch {
+            return Some(value);
+        }
+        Some(
+            value
+                .strip_suffix(self.syntax.map_or("/", Syntax::separator))
+                .unwrap_or(value),
+        )
     }
 
     /// Returns named or catch-all parameter in the result.
     pub fn params(&'a self, index: impl Into<String>) -> &'a String {
+        &self.params_map()[&index.into()]
+    }
+
+    /// Returns the parameter *name*, or `None` when the result didn't capture
+    /// it. Unlike `params`, it never builds a `String` for values the
+    /// `smallstr` feature keeps inline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "user");
+    /// let result = tree.find("/users/42");
+    /// assert_eq!(result.param("id"), Some("42"));
+    /// assert_eq!(result.param("name"), None);
+    /// ```
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|value| &**value)
+    }
+
+    /// Returns every named or catch-all parameter in the result, keyed by name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "user");
+    /// let result = tree.find("/users/42");
+    /// assert_eq!(result.params_map().len(), 1);
+    /// assert!(!result.params_map().contains_key("name"));
+    /// ```
+    pub fn params_map(&self) -> &HashMap<String, String> {
+        #[cfg(not(feature = "smallstr"))]
+        return &self.params;
+        #[cfg(feature = "smallstr")]
+        return self
+            .strings
+            .get_or_init(|| into_strings(self.params.clone()));
+    }
+
+    /// Parses the parameter *name*, telling apart a missing parameter from a
+    /// value that doesn't parse.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{ParamError, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "user");
+    /// let result = tree.find("/users/42");
+    /// let id: u32 = result.require("id").unwrap();
+    /// assert_eq!(id, 42);
+    /// assert!(matches!(result.require::<u32>("name"), Err(ParamError::Missing(_))));
+    ///
+    /// let result = tree.find("/users/me");
+    /// assert!(matches!(result.require::<u32>("id"), Err(ParamError::Parse { .. })));
+    /// ```
+    pub fn require<F>(&self, name: &str) -> std::result::Result<F, ParamError>
+    where
+        F: FromStr,
+        F::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let value = self
+            .params
+            .get(name)
+            .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+        value.parse().map_err(|err: F::Err| ParamError::Parse {
+            name: name.to_string(),
+            source: Box::new(err),
+        })
+    }
+
+    /// Builds *P* from the parameters, see `FromParams`.
+    pub fn params_as<P: FromParams>(&self) -> std::result::Result<P, ParamError> {
+        P::from_params(self)
+    }
+
+    /// Returns the captured parameters as a JSON object mapping each name to
+    /// its value as a string, e.g. `{"id": "42"}`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id/*rest", "user");
+    /// let json = tree.find("/users/42/a/b").params_json();
+    /// assert_eq!(json, serde_json::json!({"id": "42", "rest": "a/b"}));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn params_json(&self) -> serde_json::Value {
+        let params = self
+            .params
+            .iter()
+            .map(|(name, value)| (name.clone(), serde_json::Value::from(value.as_str())))
+            .collect();
+        serde_json::Value::Object(params)
+    }
+
+    /// Returns the byte range of the parameter *name* inside the path given to
+    /// `Tree::find_with_ranges`, or `None` for Results of other lookups.
+    pub fn param_range(&self, name: &str) -> Option<Range<usize>> {
+        self.ranges
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, range)| range.clone())
+    }
+
+    /// Returns the name and byte range of every parameter, in path order, see
+    /// `param_range`.
+    pub fn param_ranges(&self) -> &[(String, Range<usize>)] {
+        &self.ranges
+    }
+
+    /// Splits the catch-all parameter *name* into its segments, ignoring the
+    /// separator (`/` or the one of the Tree) at either end of the capture.
+    /// Returns an empty Vec when the parameter is missing or empty.
+    pub fn param_segments(&self, name: impl Into<String>) -> Vec<&str> {
+        let value = match self.params.get(&name.into()) {
+            Some(value) => value.as_str(),
+            None => return Vec::new(),
+        };
+        let separator = self.syntax.map_or("/", Syntax::separator);
+        let value = value.strip_prefix(separator).unwrap_or(value);
+        let value = value.strip_suffix(separator).unwrap_or(value);
+        if value.is_empty() {
+            return Vec::new();
+        }
+        value.split(separator).collect()
+    }
+}
+
+impl<'a, T: Clone> Result<'a, T> {
+    /// Converts the result into an `OwnedResult`, cloning the payload so the
+    /// result no longer borrows the Tree.
+    pub fn into_owned(self) -> OwnedResult<T> {
+        OwnedResult {
+            key: self.key(),
+            params: into_strings(self.params),
+            fallback: self.fallback,
+            payload: self.payload.clone(),
+        }
+    }
+
+    /// Converts the result into a `ResolvedRoute`, cloning the payload, or
+    /// `None` when no route matched (including the fallback).
+    pub(crate) fn into_resolved(self) -> Option<ResolvedRoute<T>> {
+        if self.fallback {
+            return None;
+        }
+        let payload = self.payload.clone()?;
+        Some(ResolvedRoute {
+            pattern: self.key(),
+            params: into_strings(self.params),
+            payload,
+        })
+    }
+}
+
+impl<'a, T> fmt::Display for Result<'a, T> {
+    /// Writes the key built based on the nodes used in the result.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.key.get_or_init(|| self.compute_key()))
+    }
+}
+
+/// A Result holding its own copy of the payload, as returned by `Tree::find_owned`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedResult<T> {
+    key: String,
+    params: HashMap<String, String>,
+    fallback: bool,
+    pub payload: Option<T>,
+}
+
+impl<T> OwnedResult<T> {
+    /// Returns the key of the matched route, see `Result::key`.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns named or catch-all parameter in the result.
+    pub fn params(&self, index: impl Into<String>) -> &String {
         &self.params[&index.into()]
     }
+
+    /// Returns every parameter in the result, see `Result::params_map`.
+    pub fn params_map(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
+    /// Returns whether the payload is the fallback, see `Result::is_fallback`.
+    pub fn is_fallback(&self) -> bool {
+        self.fallback
+    }
+}
+
+/// A matched route owning its pattern, parameters and a clone of its payload,
+/// as returned by `Tree::resolve`, e.g. to be stored in a route cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRoute<T> {
+    pattern: String,
+    params: HashMap<String, String>,
+    pub payload: T,
+}
+
+impl<T> ResolvedRoute<T> {
+    /// Returns the pattern of the matched route, see `Result::key`.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns named or catch-all parameter in the route.
+    pub fn params(&self, index: impl Into<String>) -> &String {
+        &self.params[&index.into()]
+    }
+
+    /// Returns every parameter in the route, see `Result::params_map`.
+    pub fn params_map(&self) -> &HashMap<String, String> {
+        &self.params
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Result;
+    use super::{Matched, Result};
     use crate::node::Node;
 
     #[test]
@@ -78,6 +651,136 @@ mod test {
         result = result.add(&node1, true);
         result = result.add(&node2, true);
         assert_eq!(result.key(), "/about".to_string());
+        assert_eq!(result.leaf_key(), Some("about"));
+    }
+
+    #[test]
+    fn into_ancestor() {
+        let node1 = Node::<&str>::new("/users", Some("users"), true);
+        let node2 = Node::<&str>::new("/:id", None, true);
+        let mut result = Result::<&str>::new();
+        result.track_ancestor = true;
+        result.mark_ancestor(&node1);
+        result = result.add(&node1, false).add(&node2, true);
+        result.params.insert("id".to_string(), "42".into());
+
+        let result = result.into_ancestor();
+        assert_eq!(result.key(), "/users");
+        assert_eq!(result.payload, &Some("users"));
+        assert!(result.params.is_empty());
+    }
+
+    #[test]
+    fn into_key() {
+        let node1 = Node::<&str>::new("/users", None, true);
+        let node2 = Node::<&str>::new("/:id", Some("user"), true);
+        let result = Result::<&str>::new().add(&node1, true).add(&node2, true);
+        assert_eq!(result.into_key(), (&Some("user"), "/users/:id".to_string()));
+
+        // a cached key is reused
+        let result = Result::<&str>::new().add(&node1, true);
+        assert_eq!(result.key(), "/users");
+        assert_eq!(result.into_key(), (&None, "/users".to_string()));
+    }
+
+    #[test]
+    fn nearest_dynamic_ancestor() {
+        let users = Node::<&str>::new("/users/", Some("users"), true);
+        let id = Node::<&str>::new(":id", Some("user"), true);
+        let posts = Node::<&str>::new("/posts", None, true);
+        let slug = Node::<&str>::new("/:slug", Some("post"), true);
+
+        let result = Result::<&str>::new().add(&users, true).add(&id, true);
+        assert_eq!(result.nearest_dynamic_ancestor(), None);
+
+        let result = result.add(&posts, true).add(&slug, true);
+        assert_eq!(result.nearest_dynamic_ancestor(), Some(&"user"));
+    }
+
+    #[test]
+    fn param_segments() {
+        let mut result = Result::<&str>::new();
+        for (name, value) in &[("path", "a/b/c"), ("slash", "/a//b/"), ("empty", "")] {
+            result.params.insert(name.to_string(), (*value).into());
+        }
+        assert_eq!(result.param_segments("path"), vec!["a", "b", "c"]);
+        assert_eq!(result.param_segments("slash"), vec!["a", "", "b"]);
+        assert!(result.param_segments("empty").is_empty());
+        assert!(result.param_segments("missing").is_empty());
+    }
+
+    #[test]
+    fn or_fallback() {
+        let fallback = Some("not found");
+        let node = Node::<&str>::new("/users/:id", None, true);
+        let mut result = Result::<&str>::new().add(&node, true);
+        result.params.insert("id".to_string(), "42".into());
+
+        let result = result.or_fallback(&fallback);
+        assert!(result.is_fallback());
+        assert_eq!(result.payload, &Some("not found"));
+        assert_eq!(result.key(), "");
+        assert!(result.params.is_empty());
+
+        let node = Node::<&str>::new("/", Some("root"), true);
+        let result = Result::<&str>::new()
+            .add(&node, true)
+            .or_fallback(&fallback);
+        assert!(!result.is_fallback());
+        assert_eq!(result.payload, &Some("root"));
+
+        // without fallback, the nodes walked through are dropped all the same
+        let node = Node::<&str>::new("/users/", None, true);
+        let result = Result::<&str>::new().add(&node, false).or_fallback(&None);
+        assert!(!result.is_fallback());
+        assert_eq!(result.payload, &None);
+        assert_eq!(result.key(), "");
+        assert_eq!(result.leaf_key(), None);
+    }
+
+    #[test]
+    fn or_fallback_excluding_catch_all() {
+        let fallback = Some("not found");
+        let node = Node::<&str>::new("/*filepath", Some("static"), true);
+        let mut result = Result::<&str>::new().add(&node, true);
+        result.catch_all_start = Some(1);
+        assert_eq!(result.matched(), Matched::CatchAll);
+
+        result.catch_all_not_found = true;
+        let result = result.or_fallback(&fallback);
+        assert_eq!(result.matched(), Matched::NotFound);
+        assert_eq!(result.payload, &Some("not found"));
+        assert_eq!(result.catch_all(""), None);
+
+        let mut result = Result::<&str>::new().add(&node, true);
+        result.catch_all_start = Some(1);
+        result.catch_all_not_found = true;
+        let result = result.or_fallback(&None);
+        assert_eq!(result.matched(), Matched::Nothing);
+        assert_eq!(result.payload, &None);
+    }
+
+    #[test]
+    fn into_owned() {
+        let node1 = Node::<String>::new("/users/", None, true);
+        let node2 = Node::<String>::new(":id", Some("user".to_string()), true);
+        let mut result = Result::<String>::new().add(&node1, false).add(&node2, true);
+        result.params.insert("id".to_string(), "42".into());
+
+        let owned = result.into_owned();
+        assert_eq!(owned.key(), "/users/:id");
+        assert_eq!(owned.params("id"), "42");
+        assert_eq!(owned.payload, Some("user".to_string()));
+        assert!(!owned.is_fallback());
+    }
+
+    #[test]
+    fn display() {
+        let node1 = Node::<&str>::new("/products/", None, true);
+        let node2 = Node::<&str>::new(":id", Some("product"), true);
+        let result = Result::<&str>::new().add(&node1, false).add(&node2, true);
+        assert_eq!(format!("{}", result), "/products/:id");
+        assert_eq!(result.key(), "/products/:id");
     }
 
     #[test]
@@ -0,0 +1,8 @@
+/// Work done by a single lookup, as returned by `Tree::find_with_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FindStats {
+    /// Number of nodes the walk went through.
+    pub nodes: usize,
+    /// Number of characters of the path compared against node keys.
+    pub comparisons: usize,
+}
@@ -0,0 +1,139 @@
+use std::borrow::Cow;
+
+/// How paths given to a Tree are written when it differs from the nodes,
+/// which always use `/` as separator, `:` for named and `*` for catch-all
+/// parameters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Syntax {
+    /// Separator used in paths given to the Tree, `None` for `/`.
+    separator: Option<String>,
+    /// Markers of named and catch-all parameters, `None` for `:` and `*`.
+    markers: Option<(char, char)>,
+    /// Characters ending segments besides `/`, written the same way in the
+    /// paths given to the Tree and in the nodes.
+    separators: Vec<char>,
+}
+
+impl Syntax {
+    pub(crate) fn is_default(&self) -> bool {
+        self.separator.is_none() && self.markers.is_none()
+    }
+
+    /// Returns the separator used in paths given to the Tree.
+    pub(crate) fn separator(&self) -> &str {
+        self.separator.as_deref().unwrap_or("/")
+    }
+
+    pub(crate) fn set_separator(&mut self, separator: &str) {
+        self.separator = (separator != "/").then(|| separator.to_string());
+    }
+
+    /// Returns the characters ending segments besides `/`.
+    pub(crate) fn extra_separators(&self) -> &[char] {
+        &self.separators
+    }
+
+    /// Ends segments at any of *separators* besides `/`, panicking when one
+    /// of them is a marker of the nodes. Set before the markers, which are
+    /// checked against them.
+    pub(crate) fn set_extra_separators(&mut self, separators: &[char]) {
+        self.separators.clear();
+        for &separator in separators {
+            if [':', '*'].contains(&separator) {
+                panic!("ambiguous separator '{}'", separator);
+            }
+            if separator != '/' && !self.separators.contains(&separator) {
+                self.separators.push(separator);
+            }
+        }
+    }
+
+    /// Uses *named* and *glob* as markers, panicking when they can't be told
+    /// apart from each other, from the separators or from the default markers
+    /// they swap places with. Markers must be ASCII, so that positions in the
+    /// path are the same for the Tree and for its nodes.
+    pub(crate) fn set_markers(&mut self, named: char, glob: char) {
+        if !named.is_ascii() || !glob.is_ascii() {
+            panic!("markers must be ASCII");
+        }
+        let separator = |ch: char| ch == '/' || self.separators.contains(&ch);
+        if named == glob || separator(named) || separator(glob) || named == '*' || glob == ':' {
+            panic!("ambiguous markers '{}' and '{}'", named, glob);
+        }
+        self.markers = (named != ':' || glob != '*').then_some((named, glob));
+    }
+
+    /// Swaps the markers of the Tree with the ones of the nodes, so `:` and
+    /// `*` written in paths given to the Tree stay literal. Swapping twice
+    /// gives back *ch*.
+    fn swap(&self, ch: char) -> char {
+        match self.markers {
+            Some((named, _)) if ch == named => ':',
+            Some((named, _)) if ch == ':' => named,
+            Some((_, glob)) if ch == glob => '*',
+            Some((_, glob)) if ch == '*' => glob,
+            _ => ch,
+        }
+    }
+
+    /// Rewrites *path* with the syntax used by the nodes.
+    pub(crate) fn internal<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        if self.is_default() {
+            return Cow::Borrowed(path);
+        }
+        let path = match &self.separator {
+            Some(separator) => path.replace(separator.as_str(), "/"),
+            None => path.to_string(),
+        };
+        match self.markers {
+            Some(_) => Cow::Owned(path.chars().map(|ch| self.swap(ch)).collect()),
+            None => Cow::Owned(path),
+        }
+    }
+
+    /// Rewrites *path*, using the syntax of the nodes, with the syntax used by
+    /// the Tree.
+    pub(crate) fn external(&self, path: &str) -> String {
+        let mut buf = String::with_capacity(path.len());
+        self.push_external(&mut buf, path);
+        buf
+    }
+
+    /// Appends *path*, using the syntax of the nodes, to *buf* with the syntax
+    /// used by the Tree.
+    pub(crate) fn push_external(&self, buf: &mut String, path: &str) {
+        if self.is_default() {
+            buf.push_str(path);
+            return;
+        }
+        for ch in path.chars() {
+            match ch {
+                '/' => buf.push_str(self.separator()),
+                ch => buf.push(self.swap(ch)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Syntax;
+
+    #[test]
+    fn markers_round_trip() {
+        let mut syntax = Syntax::default();
+        syntax.set_separator("::");
+        syntax.set_markers('$', '%');
+        let external = "::at::12:30::$id::%rest*";
+        let internal = syntax.internal(external);
+        assert_eq!(internal, "/at/12$30/:id/*rest%");
+        assert_eq!(syntax.external(&internal), external);
+    }
+
+    #[test]
+    fn default_markers_are_no_markers() {
+        let mut syntax = Syntax::default();
+        syntax.set_markers(':', '*');
+        assert!(syntax.is_default());
+    }
+}
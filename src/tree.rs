@@ -1,20 +1,2146 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::error::*;
+#[cfg(feature = "debug")]
+use crate::explain::RejectionReason;
+#[cfg(feature = "debug")]
+use crate::insert::*;
+use crate::iter::*;
+use crate::lazy::LazyResult;
 use crate::node::*;
+use crate::params::FromParams;
+use crate::pattern::{
+    cmp_routes, normalize_pattern, param_names, param_specs, validate_pattern, ParamSpec,
+};
 use crate::result::*;
+#[cfg(feature = "instrumentation")]
+use crate::stats::FindStats;
+use crate::syntax::Syntax;
 use crate::utils::*;
+use crate::view::*;
+
+/// Where an insertion placed a path, only described with the `debug` feature
+/// (see `Tree::add_with_info`) so that `add` doesn't build it otherwise.
+#[cfg(feature = "debug")]
+type Placement = InsertInfo;
+#[cfg(not(feature = "debug"))]
+type Placement = ();
+
+/// Returns `Ok` with the `Placement` of a path placed by the given
+/// `InsertBranch` after *pos* shared characters.
+macro_rules! placed {
+    ($branch:ident, $pos:expr) => {{
+        #[cfg(feature = "debug")]
+        let placed = Ok(InsertInfo::new(InsertBranch::$branch, $pos));
+        #[cfg(not(feature = "debug"))]
+        let placed = Ok(());
+        placed
+    }};
+}
+
+/// Parameter name used for catch all parameters registered without a name (e.g. `/*`).
+pub const UNNAMED_CATCH_ALL: &str = "splat";
+
+/// Precedence between siblings matching the same path, see `Tree::set_match_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MatchPolicy {
+    /// Static routes win over named parameters, which win over catch all parameters.
+    #[default]
+    SpecificFirst,
+    /// Catch all parameters win over static routes and named parameters.
+    GlobFirst,
+}
+
+/// What adding a path registered with a payload already does, see `Tree::set_duplicate_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DuplicatePolicy {
+    /// `add` panics with `duplicate error`, `try_add` returns `ConflictKind::Duplicate`.
+    #[default]
+    Panic,
+    /// The payload added first is kept, later ones are dropped.
+    FirstWins,
+    /// The payload added last replaces the previous one.
+    LastWins,
+    /// `try_add` returns `ConflictKind::Duplicate`, while `add`, which can't
+    /// return it, keeps the payload added first.
+    Error,
+}
+
+/// What `find` returns when *path* doesn't match a route, see `Tree::set_partial_match`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PartialMatch {
+    /// Only routes matching the whole path are returned.
+    #[default]
+    Strict,
+    /// The deepest route the walk went through is returned, like `Tree::find_ancestor`.
+    LongestPrefix,
+}
+
+/// How `find` treats a path differing from a route only by a trailing slash,
+/// see `Tree::set_trailing_slash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TrailingSlash {
+    /// The route matches as if the path was written like it.
+    #[default]
+    Match,
+    /// The route matches, and `Result::redirect_status` recommends a `308
+    /// Permanent Redirect` to its canonical path.
+    RedirectPermanent,
+    /// The route matches, and `Result::redirect_status` recommends a `307
+    /// Temporary Redirect` to its canonical path.
+    RedirectTemporary,
+    /// The route matches, and `Result::redirect_status` recommends a `301
+    /// Moved Permanently` to its canonical path.
+    RedirectMovedPermanently,
+    /// The route matches, and `Result::redirect_status` recommends a `302
+    /// Found` to its canonical path.
+    RedirectFound,
+}
+
+/// A [Radix tree](https://en.wikipedia.org/wiki/Radix_tree) implementation.
+///
+/// Paths are not required to start with a separator (`/`). Routes sharing no
+/// prefix with the current root are kept side by side under an empty root key.
+///
+/// Two Trees compare equal when they hold the same nodes and payloads, which
+/// doesn't depend on the order routes were added in.
+#[derive(Debug, Clone)]
+pub struct Tree<T> {
+    root: Node<T>,
+    /// Whether the root keeps its empty key, see `Tree::new_rooted`.
+    rooted: bool,
+    /// Number of changes to the routes, see `Tree::generation`.
+    generation: u64,
+    fallback: Option<T>,
+    /// Whether paths only matched by a catch all get the fallback instead.
+    catch_all_not_found: bool,
+    policy: MatchPolicy,
+    partial: PartialMatch,
+    trailing_slash: TrailingSlash,
+    duplicates: DuplicatePolicy,
+    /// Paths longer than this many characters match no route.
+    max_path_len: Option<usize>,
+    /// Paths with more segments than this match no route.
+    max_segments: Option<usize>,
+    /// Separator and markers used in paths given to the Tree.
+    syntax: Syntax,
+    /// Shortcut to the nodes continuing the first segment of paths, see
+    /// `Tree::set_segment_index`.
+    segment_index: SegmentIndex,
+    /// Whether paths are normalized to NFC when added and looked up.
+    #[cfg(feature = "nfc")]
+    nfc: bool,
+}
+
+/// Nodes holding the end of the first segment of routes, keyed by that
+/// segment, see `Tree::set_segment_index`.
+#[derive(Debug, Clone, Default)]
+struct SegmentIndex {
+    enabled: bool,
+    /// Generation of the Tree the index was built for, a stale index is not used.
+    generation: u64,
+    /// Position of the child to walk into at every node from the root, and
+    /// position (in bytes) of the key of the last one in paths.
+    nodes: HashMap<String, (Vec<usize>, usize)>,
+}
+
+/// Copy of the state of a Tree, taken by `Tree::snapshot` and put back by `Tree::restore`.
+#[derive(Debug)]
+pub struct TreeSnapshot<T> {
+    tree: Tree<T>,
+}
+
+/// Patterns told apart by `Tree::diff`, each list sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteDiff {
+    /// Routes of the other Tree only.
+    pub added: Vec<String>,
+    /// Routes of this Tree only.
+    pub removed: Vec<String>,
+    /// Routes of both Trees whose payloads differ.
+    pub changed: Vec<String>,
+}
+
+impl RouteDiff {
+    /// Returns whether both Trees hold the same routes and payloads.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Route of a Tree flattened by `Tree::to_flat`, addressing its payload by
+/// index. It holds a Rust `String` and isn't meant to cross an FFI boundary
+/// as is: callers marshal its fields (e.g. the pattern into a `CString`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlatRoute {
+    /// Full pattern of the route.
+    pub pattern: String,
+    /// Kind of the last node of the route (see `Tree::classify`) as a `u8`:
+    /// 0 for `Kind::Normal`, 1 for `Kind::Named` and 2 for `Kind::Glob`.
+    pub kind: u8,
+    /// Position of the payload in the Vec returned along with the routes.
+    pub payload_index: usize,
+}
+
+// the generation and the segment index depend on how the Tree was built, not
+// on the routes it holds
+impl<T: PartialEq> PartialEq for Tree<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let Tree {
+            root,
+            rooted,
+            generation: _,
+            fallback,
+            catch_all_not_found,
+            policy,
+            partial,
+            trailing_slash,
+            duplicates,
+            max_path_len,
+            max_segments,
+            syntax,
+            segment_index: _,
+            #[cfg(feature = "nfc")]
+            nfc,
+        } = self;
+        #[cfg(feature = "nfc")]
+        if *nfc != other.nfc {
+            return false;
+        }
+        *root == other.root
+            && *rooted == other.rooted
+            && *fallback == other.fallback
+            && *catch_all_not_found == other.catch_all_not_found
+            && *policy == other.policy
+            && *partial == other.partial
+            && *trailing_slash == other.trailing_slash
+            && *duplicates == other.duplicates
+            && *max_path_len == other.max_path_len
+            && *max_segments == other.max_segments
+            && *syntax == other.syntax
+    }
+}
+
+impl<T: Eq> Eq for Tree<T> {}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Tree<T> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::<T>::new("", None, true),
+            rooted: false,
+            generation: 0,
+            fallback: None,
+            catch_all_not_found: false,
+            policy: MatchPolicy::default(),
+            partial: PartialMatch::default(),
+            trailing_slash: TrailingSlash::default(),
+            duplicates: DuplicatePolicy::default(),
+            max_path_len: None,
+            max_segments: None,
+            syntax: Syntax::default(),
+            segment_index: SegmentIndex::default(),
+            #[cfg(feature = "nfc")]
+            nfc: false,
+        }
+    }
+
+    /// Creates an empty Tree whose root keeps an empty key instead of taking
+    /// the first path added, so that every route is added below it. The shape
+    /// of the Tree then depends less on the order routes are added in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new_rooted();
+    /// tree.add("/user/repos", "my_repos");
+    /// tree.add("/user", "me");
+    /// assert_eq!(tree.find("/user").payload, &Some("me"));
+    /// assert_eq!(tree.find("/user/repos").payload, &Some("my_repos"));
+    /// ```
+    pub fn new_rooted() -> Self {
+        let mut tree = Self::new();
+        tree.root.placeholder = false;
+        tree.rooted = true;
+        tree
+    }
+
+    /// Creates an empty Tree whose root can hold *capacity* children without reallocating.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::with_capacity(64);
+    /// tree.add("/users", "users");
+    /// tree.add("/posts", "posts");
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut tree = Self::new();
+        tree.root.children = Vec::with_capacity(capacity);
+        tree
+    }
+
+    /// Creates an empty Tree whose paths are split by *separator* instead of `/`,
+    /// e.g. `::` for Rust-like paths.
+    ///
+    /// Named parameters end at the separator while catch all parameters span
+    /// across it. Results report keys and parameters with *separator*. Paths
+    /// holding a `/` are not supported with another separator.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `empty separator` if *separator* is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::with_separator_str("::");
+    /// tree.add("std:::module::*item", "item");
+    /// let result = tree.find("std::vec::Vec::new");
+    /// assert_eq!(result.key(), "std:::module::*item");
+    /// assert_eq!(result.params("module"), "vec");
+    /// assert_eq!(result.params("item"), "Vec::new");
+    /// ```
+    pub fn with_separator_str(separator: &str) -> Self {
+        if separator.is_empty() {
+            panic!("empty separator");
+        }
+        let mut tree = Tree::<T>::new();
+        tree.syntax.set_separator(separator);
+        tree
+    }
+
+    /// Creates an empty Tree whose named parameters start with *named* instead
+    /// of `:` and catch-all parameters with *glob* instead of `*`, so that
+    /// paths may hold `:` or `*` as literal characters (e.g. `/at/12:30`).
+    ///
+    /// # Panics
+    ///
+    /// Panics when a marker isn't ASCII or is `/`, when both markers are the
+    /// same, or when *named* is `*` or *glob* is `:`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::with_markers('$', '%');
+    /// tree.add("/at/12:30", "lunch");
+    /// tree.add("/users/$id", "user");
+    /// tree.add("/files/%path", "file");
+    /// assert_eq!(tree.find("/at/12:30").payload, &Some("lunch"));
+    /// let result = tree.find("/users/42");
+    /// assert_eq!(result.key(), "/users/$id");
+    /// assert_eq!(result.params("id"), "42");
+    /// assert_eq!(tree.find("/files/a/b").params("path"), "a/b");
+    /// ```
+    pub fn with_markers(named: char, glob: char) -> Self {
+        let mut tree = Tree::<T>::new();
+        tree.syntax.set_markers(named, glob);
+        tree
+    }
+
+    /// Creates an empty Tree ending segments at any of *separators*, `/`
+    /// being one whether given or not (e.g. `&['.']` for `/api/v1.users.show`).
+    /// Named parameters, segment catch-all parameters (`*?`) and trailing
+    /// slash tolerance stop at any of them, while catch-all parameters still
+    /// span the rest of the path.
+    ///
+    /// A `.` separator leaves no room for optional extensions (`:id.:format?`),
+    /// and parameters followed by a separator other than `/` take no
+    /// constraint.
+    ///
+    /// # Panics
+    ///
+    /// Panics when a separator is `:` or `*`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::with_separators(&['/', '.']);
+    /// tree.add("/api/:version.:resource.show", "show");
+    /// tree.add("/api/:version.:resource.list", "list");
+    /// let result = tree.find("/api/v1.users.show");
+    /// assert_eq!(result.payload, &Some("show"));
+    /// assert_eq!(result.params("version"), "v1");
+    /// assert_eq!(result.params("resource"), "users");
+    /// ```
+    pub fn with_separators(separators: &[char]) -> Self {
+        let mut tree = Tree::<T>::new();
+        tree.syntax.set_extra_separators(separators);
+        tree
+    }
+
+    /// Creates an empty Tree using *named* and *glob* as markers like
+    /// `with_markers`, and ending segments at any of *separators* like
+    /// `with_separators`.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `with_markers` and `with_separators`, and when a marker is
+    /// one of *separators*.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::with_markers_and_separators('$', '%', &['.']);
+    /// tree.add("/at/$time.$zone", "time");
+    /// let result = tree.find("/at/12:30.utc");
+    /// assert_eq!(result.params("time"), "12:30");
+    /// assert_eq!(result.params("zone"), "utc");
+    /// ```
+    pub fn with_markers_and_separators(named: char, glob: char, separators: &[char]) -> Self {
+        let mut tree = Tree::<T>::new();
+        tree.syntax.set_extra_separators(separators);
+        tree.syntax.set_markers(named, glob);
+        tree
+    }
+
+    /// Rewrites *path* with the separator (`/`) and markers (`:` and `*`)
+    /// used by the nodes, normalized to NFC when enabled (see `set_nfc`).
+    /// Every path or pattern given to the Tree goes through it.
+    fn internal_path<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        let path = self.syntax.internal(path);
+        #[cfg(feature = "nfc")]
+        if self.nfc {
+            if let Cow::Owned(normalized) = to_nfc(&path) {
+                return Cow::Owned(normalized);
+            }
+        }
+        path
+    }
+
+    /// Rewrites *path*, using the separator and markers of the nodes, with
+    /// the ones used by the Tree.
+    fn external_path(&self, path: String) -> String {
+        if self.syntax.is_default() {
+            return path;
+        }
+        self.syntax.external(&path)
+    }
+
+    /// Adds *path* into the Tree.
+    ///
+    /// Routes can be added in any order: adding a parent after its children
+    /// only sets the payload of the node created while adding the children.
+    ///
+    /// A named parameter can be constrained to a set of values by listing them
+    /// after its name (e.g. `/posts/:status{draft|published}`). Other values
+    /// don't match it and fall back to its named or catch all siblings.
+    ///
+    /// A named parameter followed by `.:format?` (e.g. `/posts/:id.:format?`)
+    /// also matches values with an extension, captured without its `.` in the
+    /// `format` parameter (empty without extension). The extension starts at
+    /// the last `.` of the segment, so `v1.2.json` gives `v1.2` and `json`.
+    ///
+    /// A named parameter ending a route can be given a default value after
+    /// its name (e.g. `/posts/:page=1`), which makes it optional: `/posts` and
+    /// `/posts/` match the route as well, capturing the default value.
+    ///
+    /// A catch all parameter written `*?name` only spans a single segment,
+    /// ending at the next separator like a named parameter. It is tried after
+    /// named parameters and static siblings, and overlaps a `*` sibling.
+    ///
+    /// A catch all parameter leaves out the trailing separator of the path:
+    /// `/*filepath` captures `src/file.png` from both `/src/file.png` and
+    /// `/src/file.png/`, the latter matching like a route tolerating a
+    /// trailing slash (see `set_trailing_slash`).
+    ///
+    /// The empty path is a route of its own, only matched by `find("")`. It
+    /// doesn't match `/`, which is added below it as another route whichever
+    /// is added first.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `duplicate error` if the exact same *path* has already been
+    /// added (unless allowed by `set_duplicate_policy`), and with `shared key
+    /// error` if a named or catch all parameter would overlap a sibling with a
+    /// different name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/abc", "root");
+    /// ```
+    pub fn add(&mut self, path: impl Into<String>, payload: T) {
+        self.add_info(path, payload, true);
+    }
+
+    /// Adds *path* into the Tree like `add`, lowercasing its static parts first
+    /// so that `find_case_insensitive` (or `find` given a lowercased path)
+    /// matches it whatever the casing of the path.
+    ///
+    /// Names of parameters and their allowed values keep their casing. The
+    /// key of Results is the normalized pattern, which is also the pattern to
+    /// give to `get_pattern` or `url_for`. Static parts are lowercased with
+    /// Unicode rules while `find_case_insensitive` only folds ASCII letters,
+    /// so lowercase paths holding other letters before looking them up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_normalized("/Users/:Id/Edit", "edit");
+    /// let result = tree.find("/users/Bob/edit");
+    /// assert_eq!(result.key(), "/users/:Id/edit");
+    /// assert_eq!(result.params("Id"), "Bob");
+    /// ```
+    pub fn add_normalized(&mut self, path: impl Into<String>, payload: T) {
+        self.add(normalize_pattern(&path.into()), payload);
+    }
+
+    /// Adds *path* into the Tree like `add`, returning the conflict it raises
+    /// instead of panicking. The Tree is left untouched on conflict.
+    ///
+    /// A path already registered with a payload conflicts under
+    /// `DuplicatePolicy::Panic` and `DuplicatePolicy::Error`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{ConflictKind, DuplicatePolicy, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.set_duplicate_policy(DuplicatePolicy::Error);
+    /// assert_eq!(tree.try_add("/users/:id", "user"), Ok(()));
+    /// assert_eq!(tree.try_add("/users/:id", "again"), Err(ConflictKind::Duplicate));
+    /// assert_eq!(tree.try_add("/users/:name", "name"), Err(ConflictKind::AmbiguousDynamic));
+    /// assert_eq!(tree.find("/users/42").payload, &Some("user"));
+    /// ```
+    pub fn try_add(
+        &mut self,
+        path: impl Into<String>,
+        payload: T,
+    ) -> std::result::Result<(), ConflictKind> {
+        self.insert_path(Cow::Owned(path.into()), payload, true, self.duplicates)
+            .map(|_| ())
+    }
+
+    /// Adds *path* into the Tree like `add`, describing which branch of the
+    /// insertion placed it.
+    ///
+    /// Only available with the `debug` feature. The feature doesn't depend on
+    /// the build profile: release builds enabling it trace insertions too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{InsertBranch, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/abc", "abc");
+    /// let info = tree.add_with_info("/axyz", "axyz");
+    /// assert_eq!(info.branch, InsertBranch::Split);
+    /// assert_eq!(info.pos, 2);
+    /// ```
+    #[cfg(feature = "debug")]
+    pub fn add_with_info(&mut self, path: impl Into<String>, payload: T) -> InsertInfo {
+        self.add_info(path, payload, true)
+    }
+
+    /// Adds *path* into the Tree like `add`, checking that it declares every
+    /// parameter read by *P*, so that `Result::params_as::<P>` only fails on
+    /// values that don't parse. See `FromParams`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `missing parameter error` if *path* doesn't declare one of
+    /// `P::NAMES`, and like `add` otherwise.
+    pub fn add_typed<P: FromParams>(&mut self, path: impl Into<String>, payload: T) {
+        let path = path.into();
+        let declared = param_names(&self.internal_path(&path));
+        if let Some(name) = P::NAMES
+            .iter()
+            .find(|name| !declared.iter().any(|d| d == *name))
+        {
+            panic!("missing parameter error: {} in {}", name, path);
+        }
+        self.add(path, payload);
+    }
+
+    /// Adds *path* into the Tree like `add`, boosting its node over siblings
+    /// of the same kind.
+    ///
+    /// Siblings are ordered by kind first (static, then named, then catch
+    /// all), then by *boost* (highest first), and finally by the automatic
+    /// priority (the length of the key before its first parameter). A boost
+    /// is kept by both parts of the node when a later insertion splits it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products/a", "a");
+    /// tree.add_with_priority("/products/b", "b", 10);
+    /// assert_eq!(tree.find("/products/b").payload, &Some("b"));
+    /// ```
+    pub fn add_with_priority(&mut self, path: impl Into<String>, payload: T, boost: i32) {
+        let path = path.into();
+        self.add_info(path.as_str(), payload, true);
+        let path = self.internal_path(&path).into_owned();
+        self.root.boost_pattern(&path, boost);
+    }
+
+    /// Adds *path* into the Tree like `add`, without sorting the children of
+    /// the nodes it touches.
+    ///
+    /// Meant for bulk loading: `find` may pick the wrong route until `sort`
+    /// is called once all paths have been added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_unsorted("/*filepath", "all");
+    /// tree.add_unsorted("/products", "products");
+    /// tree.sort();
+    /// assert_eq!(tree.find("/products").payload, &Some("products"));
+    /// ```
+    pub fn add_unsorted(&mut self, path: impl Into<String>, payload: T) {
+        self.add_info(path, payload, false);
+    }
+
+    /// Adds *path* into the Tree like `add_unsorted`, without checking whether
+    /// it has already been added, for bulk loads of routes known to be valid.
+    /// Call `sort` once all paths have been added.
+    ///
+    /// Adding the same *path* twice silently replaces its payload, leaving the
+    /// Tree inconsistent with the routes it was given. Debug builds still
+    /// validate *path* (see `validate_pattern`) and panic with `duplicate
+    /// error` when it has already been added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_unchecked("/products/:id", "product");
+    /// tree.add_unchecked("/products/featured", "featured");
+    /// tree.sort();
+    /// assert_eq!(tree.find("/products/featured").payload, &Some("featured"));
+    /// ```
+    pub fn add_unchecked(&mut self, path: impl Into<String>, payload: T) {
+        let path = path.into();
+        debug_assert!(
+            validate_pattern(&self.internal_path(&path)).is_ok(),
+            "invalid pattern {}",
+            path
+        );
+        debug_assert!(self.get_pattern(&path).is_none(), "duplicate error");
+        let inserted =
+            self.insert_path(Cow::Owned(path), payload, false, DuplicatePolicy::LastWins);
+        Tree::<T>::expect_inserted(inserted);
+    }
+
+    /// Builds a Tree from *routes* given in the order of `cmp_routes`, sorting
+    /// the nodes once at the end rather than after each insertion like `add`.
+    ///
+    /// That order places every path after its prefixes (longest prefix last)
+    /// and static paths before the dynamic ones sharing their prefix, so a
+    /// route never has to be checked against the routes following it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InsertError::Unsorted` when a path sorts before the previous one,
+    /// and `InsertError::Conflict` when a path couldn't be added with `add`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{cmp_routes, InsertError, Tree};
+    ///
+    /// let mut routes = vec![
+    ///     ("/products/:id".to_string(), "product"),
+    ///     ("/products/new".to_string(), "new"),
+    ///     ("/about".to_string(), "about"),
+    /// ];
+    /// routes.sort_by(|a, b| cmp_routes(&a.0, &b.0));
+    /// let tree = Tree::from_sorted(routes).unwrap();
+    /// assert_eq!(tree.find("/products/10").payload, &Some("product"));
+    ///
+    /// // dynamic routes come after the static ones
+    /// let routes = vec![("/:id".to_string(), "id"), ("/about".to_string(), "about")];
+    /// assert_eq!(Tree::from_sorted(routes).unwrap_err(), InsertError::Unsorted(1));
+    /// ```
+    pub fn from_sorted(routes: Vec<(String, T)>) -> std::result::Result<Tree<T>, InsertError> {
+        let mut tree = Tree::<T>::new();
+        let mut previous: Option<String> = None;
+        for (index, (path, payload)) in routes.into_iter().enumerate() {
+            if let Some(previous) = &previous {
+                if cmp_routes(&path, previous) == Ordering::Less {
+                    return Err(InsertError::Unsorted(index));
+                }
+            }
+            tree.insert_path(Cow::Owned(path.clone()), payload, false, tree.duplicates)
+                .map_err(|kind| InsertError::Conflict(index, kind))?;
+            previous = Some(path);
+        }
+        tree.sort();
+        Ok(tree)
+    }
+
+    /// Sorts the children of every node in the Tree, see `add_unsorted`.
+    pub fn sort(&mut self) {
+        self.generation += 1;
+        self.root.sort_recursive();
+        self.reindex();
+    }
+
+    /// Removes every route for which *f*, given the pattern and payload of the
+    /// route, returns `false`, like `HashMap::retain`. Nodes left without
+    /// payload are dropped or merged into their only child, laying the Tree out
+    /// as if only the remaining routes had been added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<(&str, bool)>::new();
+    /// tree.add("/users", ("users", true));
+    /// tree.add("/beta/search", ("search", false));
+    /// tree.add("/beta/:id", ("beta", true));
+    /// tree.retain(|_, &(_, enabled)| enabled);
+    /// assert_eq!(tree.find("/beta/search").payload, &Some(("beta", true)));
+    /// assert_eq!(tree.find("/users").payload, &Some(("users", true)));
+    /// ```
+    pub fn retain<F: FnMut(&str, &T) -> bool>(&mut self, mut f: F) {
+        let syntax = &self.syntax;
+        let mut keep = |pattern: &str, payload: &T| {
+            if syntax.is_default() {
+                return f(pattern, payload);
+            }
+            f(&syntax.external(pattern), payload)
+        };
+        self.root.retain(&mut String::new(), &mut keep);
+        // the root of a rooted Tree keeps its empty key
+        if !self.rooted {
+            if self.root.payload.is_none() && self.root.children.is_empty() {
+                self.root = Node::<T>::new("", None, true);
+            } else {
+                self.root.merge_only_child();
+            }
+        }
+        self.compact();
+    }
+
+    /// Merges static nodes without payload into their only static child,
+    /// reducing the depth of the Tree without changing what `find` returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/api/v1/users", "users");
+    /// tree.add("/about", "about");
+    /// tree.compact();
+    /// assert_eq!(tree.find("/api/v1/users").payload, &Some("users"));
+    /// ```
+    pub fn compact(&mut self) {
+        self.generation += 1;
+        if self.rooted {
+            self.root.children.iter_mut().for_each(Node::compact);
+        } else {
+            self.root.compact();
+        }
+        self.reindex();
+    }
+
+    /// Checks the structure of the Tree, e.g. in tests guarding code that
+    /// builds or changes Trees: siblings start with different characters, are
+    /// in the order they are tried in, and static nodes without payload have
+    /// more than one static child. Returns the first invariant broken, along
+    /// with the pattern of the node breaking it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{InvariantViolation, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/products/:id", "product");
+    /// assert_eq!(tree.check_invariants(), Ok(()));
+    ///
+    /// tree.add_unsorted("/posts", "posts");
+    /// tree.add_unsorted("/p", "p");
+    /// assert_eq!(
+    ///     tree.check_invariants(),
+    ///     Err(InvariantViolation::Unsorted("/p".to_string()))
+    /// );
+    /// ```
+    pub fn check_invariants(&self) -> std::result::Result<(), InvariantViolation> {
+        self.root
+            .check_invariants(&mut String::new(), !self.rooted)
+            .map_err(|violation| match violation {
+                InvariantViolation::SharedFirstChar(pattern, ch) => {
+                    InvariantViolation::SharedFirstChar(self.external_path(pattern), ch)
+                }
+                InvariantViolation::DynamicCollision(pattern) => {
+                    InvariantViolation::DynamicCollision(self.external_path(pattern))
+                }
+                InvariantViolation::Unsorted(pattern) => {
+                    InvariantViolation::Unsorted(self.external_path(pattern))
+                }
+                InvariantViolation::Uncompacted(pattern) => {
+                    InvariantViolation::Uncompacted(self.external_path(pattern))
+                }
+            })
+    }
+
+    /// Adds *path* into the Tree like `add`, preventing its catch all
+    /// parameter from matching values ending with any of *suffixes*.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_excluding("/*filepath", "static", &[".php"]);
+    /// assert_eq!(tree.find("/index.html").payload, &Some("static"));
+    /// assert_eq!(tree.find("/admin/index.php").payload, &None);
+    /// ```
+    pub fn add_excluding(&mut self, path: impl Into<String>, payload: T, suffixes: &[&str]) {
+        let path = path.into();
+        self.add_info(path.as_str(), payload, true);
+        let path = self.internal_path(&path).into_owned();
+        // suffixes are compared with the path as the nodes write it
+        let suffixes = suffixes
+            .iter()
+            .map(|suffix| self.internal_path(suffix).into_owned())
+            .collect();
+        if let Some(node) = self.root.find_pattern_mut(&path) {
+            node.excluded = suffixes;
+        }
+    }
+
+    /// Adds *path* into the Tree like `add`, prefixed by *host* as its first
+    /// segment (e.g. `example.com/users/:id`), to route several hosts with a
+    /// single Tree. Paths are then looked up with their host as well.
+    ///
+    /// The `.` of *host* has no special meaning. *host* must not hold a port
+    /// though, its `:` would start a named parameter: a named parameter only
+    /// makes sense as the whole host (e.g. `:tenant`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_host_route("example.com", "/users/:id", "users");
+    /// tree.add_host_route("api.example.com", "/users/:id", "api users");
+    /// assert_eq!(tree.find("example.com/users/42").payload, &Some("users"));
+    /// assert_eq!(tree.find("api.example.com/users/42").payload, &Some("api users"));
+    /// ```
+    pub fn add_host_route(&mut self, host: &str, path: &str, payload: T) {
+        let separator = self.syntax.separator();
+        if path.starts_with(separator) {
+            self.add(format!("{}{}", host, path), payload);
+        } else {
+            self.add(format!("{}{}{}", host, separator, path), payload);
+        }
+    }
+
+    /// Adds the path made of *segments*, each one preceded by the separator,
+    /// into the Tree like `add` (e.g. `["users", ":id"]` adds `/users/:id`, no
+    /// segment adds `/`). The path is built in a single allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_segments(&["users", ":id"], "user");
+    /// assert_eq!(tree.find("/users/42").params("id"), "42");
+    /// ```
+    pub fn add_segments(&mut self, segments: &[&str], payload: T) {
+        let separator = self.syntax.separator();
+        let len: usize = segments
+            .iter()
+            .map(|segment| separator.len() + segment.len())
+            .sum();
+        let mut path = String::with_capacity(len.max(separator.len()));
+        if segments.is_empty() {
+            path.push_str(separator);
+        }
+        for segment in segments {
+            path.push_str(separator);
+            path.push_str(segment);
+        }
+        self.add(path, payload);
+    }
+
+    /// Adds *path* into the Tree like `add`, borrowing the static string
+    /// instead of copying it into the nodes. Keys only get allocated when
+    /// nodes are merged by `compact` or when the Tree uses another separator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_static("/products", "products");
+    /// tree.add_static("/products/:id", "product");
+    /// assert_eq!(tree.find("/products/10").payload, &Some("product"));
+    /// ```
+    pub fn add_static(&mut self, path: &'static str, payload: T) {
+        let inserted = self.insert_path(Cow::Borrowed(path), payload, true, self.duplicates);
+        Tree::<T>::expect_inserted(inserted);
+    }
+
+    fn add_info(&mut self, path: impl Into<String>, payload: T, sorted: bool) -> Placement {
+        // only try_add can report the duplicates DuplicatePolicy::Error rejects
+        let duplicates = match self.duplicates {
+            DuplicatePolicy::Error => DuplicatePolicy::FirstWins,
+            duplicates => duplicates,
+        };
+        let inserted = self.insert_path(Cow::Owned(path.into()), payload, sorted, duplicates);
+        Tree::<T>::expect_inserted(inserted)
+    }
+
+    /// Panics with the message `add` documents for the conflict *inserted* raised.
+    fn expect_inserted(inserted: std::result::Result<Placement, ConflictKind>) -> Placement {
+        match inserted {
+            Ok(placement) => placement,
+            Err(ConflictKind::Duplicate) => panic!("duplicate error"),
+            Err(ConflictKind::AmbiguousDynamic) => panic!("shared key error"),
+        }
+    }
+
+    /// Adds *path* into the Tree, leaving it untouched when *path* conflicts
+    /// with the routes already added.
+    fn insert_path(
+        &mut self,
+        path: Cow<'static, str>,
+        payload: T,
+        sorted: bool,
+        duplicates: DuplicatePolicy,
+    ) -> std::result::Result<Placement, ConflictKind> {
+        let path = match path {
+            Cow::Borrowed(path) => self.internal_path(path),
+            Cow::Owned(path) => match self.internal_path(&path) {
+                Cow::Owned(internal) => Cow::Owned(internal),
+                Cow::Borrowed(_) => Cow::Owned(path),
+            },
+        };
+        let inserted = if self.root.placeholder {
+            // keep the children allocated by with_capacity
+            let children = std::mem::take(&mut self.root.children);
+            self.root = Node::<T>::new(path, Some(payload), false);
+            self.root.children = children;
+            placed!(Root, 0)
+        } else {
+            let separators = self.syntax.extra_separators();
+            let root = &mut self.root;
+            Tree::<T>::add_internal(path, Some(payload), root, sorted, duplicates, separators)
+        };
+        if inserted.is_ok() {
+            self.generation += 1;
+            // unsorted children get indexed once sorted
+            if sorted {
+                self.reindex();
+            }
+        }
+        inserted
+    }
+
+    fn add_internal(
+        path: Cow<'static, str>,
+        payload: Option<T>,
+        node: &mut Node<T>,
+        sorted: bool,
+        duplicates: DuplicatePolicy,
+        separators: &[char],
+    ) -> std::result::Result<Placement, ConflictKind> {
+        // conflicts are detected before changing any node, so that an error
+        // leaves the Tree as it was
+        let mut rest_path_peekable = path.chars().peekable();
+        let mut rest_key_peekable = node.key.chars().peekable();
+        let mut pos = 0;
+        // length in bytes of the part shared by path and key
+        let mut shared_len = 0;
+
+        // move cursor position to last shared character between key and path
+        loop {
+            let p = rest_path_peekable.peek();
+            let k = rest_key_peekable.peek();
+            if p.is_none() || k.is_none() || p != k {
+                break;
+            }
+            shared_len += p.map_or(0, |ch| ch.len_utf8());
+            rest_path_peekable.next();
+            rest_key_peekable.next();
+            pos += 1;
+        }
+
+        // slices of a static path stay borrowed in the new nodes
+        let rest_path = cow_slice(&path, shared_len..path.len());
+        let key_size = node.key.chars().count();
+        let path_size = path.chars().count();
+
+        // determine split point difference between path and key
+        // compare if path is larger than key
+        if key_size <= pos && pos < path_size {
+            if splits_dynamic(&node.key, None, rest_path.chars().next(), separators) {
+                return Err(ConflictKind::AmbiguousDynamic);
+            }
+            // determine if a child of this node contains the remaining part
+            // of the path
+            let new_key = rest_path;
+            let indexed = new_key
+                .chars()
+                .next()
+                .filter(|&ch| Kind::from_marker(ch).is_none())
+                .and_then(|ch| node.child_pos(ch));
+            let child_pos = match indexed {
+                Some(child_pos) => Some(child_pos),
+                None => {
+                    if node
+                        .children
+                        .iter()
+                        .any(|child| conflicting_dynamic(&new_key, &child.key, separators))
+                    {
+                        return Err(ConflictKind::AmbiguousDynamic);
+                    }
+                    let first = new_key.chars().next();
+                    node.children
+                        .iter()
+                        .position(|child| child.key.chars().next() == first)
+                }
+            };
+
+            // keep the children sorted without sorting them again: the child
+            // walked into only moves when splitting it changed its order
+            match child_pos {
+                Some(child_pos) => {
+                    let child = &mut node.children[child_pos];
+                    let inserted = Tree::<T>::add_internal(
+                        new_key, payload, child, sorted, duplicates, separators,
+                    );
+                    if inserted.is_ok() && sorted {
+                        node.reposition_child(child_pos);
+                    }
+                    #[cfg(feature = "debug")]
+                    let inserted = inserted.map(InsertInfo::descend);
+                    inserted
+                }
+                None => {
+                    let child = Node::<T>::new(new_key, payload, false);
+                    if sorted {
+                        node.insert_child(child);
+                    } else {
+                        node.children.push(child);
+                    }
+                    placed!(NewChild, pos)
+                }
+            }
+        } else if key_size == pos && pos == path_size {
+            // determine if path matches key and potentially be a duplicate
+            // and raise if is the case
+
+            match duplicates {
+                DuplicatePolicy::FirstWins if node.payload.is_some() => {}
+                DuplicatePolicy::Panic | DuplicatePolicy::Error if node.payload.is_some() => {
+                    return Err(ConflictKind::Duplicate);
+                }
+                _ => node.payload = payload,
+            }
+            placed!(PayloadSet, pos)
+        } else {
+            // determine if current node key needs to be split to accomodate new
+            // children nodes. when nothing is shared (only possible at the root)
+            // the node is split at 0, leaving an empty key above both routes
+            let shared = cow_slice(&node.key, 0..shared_len);
+            let rest_key = cow_slice(&node.key, shared_len..node.key.len());
+            let key_next = rest_key.chars().next();
+            if splits_dynamic(&shared, key_next, rest_path.chars().next(), separators) {
+                return Err(ConflictKind::AmbiguousDynamic);
+            }
+            let mut new_node: Node<T> = Node::<T>::new(rest_key, None, false);
+            new_node.payload = node.payload.take();
+            new_node.children = std::mem::take(&mut node.children);
+            new_node.indices = std::mem::take(&mut node.indices);
+            new_node.excluded = std::mem::take(&mut node.excluded);
+            new_node.boost = node.boost;
+            node.set_key(shared);
+            node.children.push(new_node);
+            // determine if path still continues
+            if pos < path_size {
+                node.children
+                    .push(Node::<T>::new(rest_path, payload, false));
+            } else {
+                node.payload = payload;
+            }
+            if sorted {
+                node.sort_children();
+            }
+            placed!(Split, pos)
+        }
+    }
+
+    /// Transforms every payload with *f*, keeping the exact shape of the Tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// let tree = tree.map(|payload| payload.len());
+    /// assert_eq!(tree.find("/about").payload, &Some(5));
+    /// ```
+    pub fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> Tree<U> {
+        Tree {
+            root: self.root.map(&mut f),
+            rooted: self.rooted,
+            generation: self.generation,
+            fallback: self.fallback.map(&mut f),
+            catch_all_not_found: self.catch_all_not_found,
+            policy: self.policy,
+            partial: self.partial,
+            trailing_slash: self.trailing_slash,
+            duplicates: self.duplicates,
+            max_path_len: self.max_path_len,
+            max_segments: self.max_segments,
+            syntax: self.syntax,
+            segment_index: self.segment_index,
+            #[cfg(feature = "nfc")]
+            nfc: self.nfc,
+        }
+    }
+
+    /// Compares the shape of two Trees (keys, kinds, priorities and children
+    /// order), ignoring their payloads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut a = Tree::<&str>::new();
+    /// a.add("/about", "about");
+    /// let mut b = Tree::<usize>::new();
+    /// b.add("/about", 1);
+    /// assert!(a.structurally_eq(&b));
+    /// ```
+    pub fn structurally_eq<U>(&self, other: &Tree<U>) -> bool {
+        self.root.structurally_eq(&other.root)
+    }
+
+    /// Returns the payload registered for the exact *pattern*, matching named
+    /// (`:`) and catch all (`*`) parameters literally instead of as dynamic parts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products/:id", "product");
+    /// assert_eq!(tree.get_pattern("/products/:id"), Some(&"product"));
+    /// assert_eq!(tree.get_pattern("/products/10"), None);
+    /// ```
+    pub fn get_pattern(&self, pattern: &str) -> Option<&T> {
+        self.root
+            .find_pattern(&self.internal_path(pattern))
+            .and_then(|node| node.payload.as_ref())
+    }
+
+    /// Returns the priority siblings are ordered by (after their kind and
+    /// boost) of the node ending the route registered as *pattern*, or `None`
+    /// when *pattern* is not registered.
+    ///
+    /// The priority is the number of characters before the first parameter in
+    /// the key of that node, or its whole length without parameter. The key
+    /// only holds the part of *pattern* below its parent node, so the priority
+    /// depends on the other routes sharing a prefix with *pattern*.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/u/:username", "user");
+    /// assert_eq!(tree.priority_of("/u/:username"), Some(3));
+    ///
+    /// tree.add("/users", "users");
+    /// // the node now only holds "/:username", below "/u"
+    /// assert_eq!(tree.priority_of("/u/:username"), Some(1));
+    /// assert_eq!(tree.priority_of("/users"), Some(4));
+    /// assert_eq!(tree.priority_of("/u"), None);
+    /// ```
+    pub fn priority_of(&self, pattern: &str) -> Option<i32> {
+        self.root
+            .find_pattern(&self.internal_path(pattern))
+            .filter(|node| node.payload.is_some())
+            .map(|node| node.priority())
+    }
+
+    /// Returns a snapshot of the node reached by the exact *pattern*, which may
+    /// be an intermediate node without route, to inspect the shape of the Tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{Kind, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/projects", "projects");
+    ///
+    /// let node = tree.debug_node("/pro").unwrap();
+    /// assert_eq!(node.kind, Kind::Normal);
+    /// assert!(!node.has_payload);
+    /// assert_eq!(node.children, 2);
+    /// ```
+    pub fn debug_node(&self, pattern: &str) -> Option<NodeView<'_>> {
+        self.root
+            .find_pattern(&self.internal_path(pattern))
+            .map(NodeView::new)
+    }
+
+    /// Returns every route capturing parameters, along with its named (`:`)
+    /// and catch all (`*`) parameters in order, e.g. to generate validation
+    /// code for them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{Kind, ParamSpec, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// tree.add("/users/:id/files/*path", "file");
+    /// let routes: Vec<(String, Vec<ParamSpec>)> = tree.dynamic_routes().collect();
+    /// assert_eq!(routes.len(), 1);
+    /// assert_eq!(routes[0].0, "/users/:id/files/*path");
+    /// let kinds: Vec<&Kind> = routes[0].1.iter().map(|param| &param.kind).collect();
+    /// assert_eq!(kinds, vec![&Kind::Named, &Kind::Glob]);
+    /// ```
+    pub fn dynamic_routes(&self) -> impl Iterator<Item = (String, Vec<ParamSpec>)> + '_ {
+        let mut patterns = Vec::new();
+        self.root
+            .collect_patterns(&mut String::new(), &mut patterns);
+        patterns.into_iter().filter_map(move |pattern| {
+            let params = param_specs(&pattern);
+            if params.is_empty() {
+                return None;
+            }
+            Some((self.external_path(pattern), params))
+        })
+    }
+
+    /// Returns every route whose pattern starts with *prefix* along with its
+    /// payload, in traversal order. Unlike `find`, *prefix* is compared with the
+    /// registered patterns, so parameters are not filled in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/api/v1/users", "users");
+    /// tree.add("/api/v1/users/:id", "user");
+    /// tree.add("/api/v2/users", "users v2");
+    /// assert_eq!(
+    ///     tree.routes_with_prefix("/api/v1"),
+    ///     vec![
+    ///         ("/api/v1/users".to_string(), &"users"),
+    ///         ("/api/v1/users/:id".to_string(), &"user"),
+    ///     ]
+    /// );
+    /// ```
+    pub fn routes_with_prefix(&self, prefix: &str) -> Vec<(String, &T)> {
+        let mut routes = Vec::new();
+        if let Some((node, path)) = self
+            .root
+            .find_prefix(&self.internal_path(prefix), String::new())
+        {
+            node.collect_routes(&path, &mut routes);
+        }
+        routes
+            .into_iter()
+            .filter_map(|(path, node)| Some((self.external_path(path), node.payload.as_ref()?)))
+            .collect()
+    }
+
+    /// Calls *f* with the path and payload of every route, in traversal order.
+    ///
+    /// Paths are built in *buf*, which is cleared first, so enumerating the
+    /// routes again with the same buffer doesn't allocate once it has grown to
+    /// the longest path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/products/:id", "product");
+    /// let mut buf = String::new();
+    /// let mut routes = Vec::new();
+    /// tree.for_each_route(&mut buf, |path, payload| routes.push(format!("{} {}", path, payload)));
+    /// assert_eq!(routes, vec!["/products products", "/products/:id product"]);
+    /// ```
+    pub fn for_each_route(&self, buf: &mut String, mut f: impl FnMut(&str, &T)) {
+        buf.clear();
+        self.root.for_each_route(buf, &self.syntax, &mut f);
+    }
+
+    /// Returns one line per route holding its pattern, kind and priority
+    /// separated by tabs, in traversal order, e.g. to keep the routing table in
+    /// a golden file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/products/:id", "product");
+    /// assert_eq!(
+    ///     tree.dump_routes(),
+    ///     "/products\tNormal\t9\n/products/:id\tNamed\t1\n"
+    /// );
+    /// ```
+    pub fn dump_routes(&self) -> String {
+        let mut routes = Vec::new();
+        self.root.collect_routes(&self.root.key, &mut routes);
+        routes
+            .into_iter()
+            .map(|(path, node)| {
+                format!(
+                    "{}\t{:?}\t{}\n",
+                    self.external_path(path),
+                    node.kind(),
+                    node.priority()
+                )
+            })
+            .collect()
+    }
+
+    /// Flattens the routes of the Tree, in traversal order, into a Vec of
+    /// `FlatRoute` and a parallel Vec of payloads addressed by
+    /// `FlatRoute::payload_index`. The fallback is left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{Kind, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/products/:id", "product");
+    /// let (routes, payloads) = tree.to_flat();
+    /// assert_eq!(routes[1].pattern, "/products/:id");
+    /// assert_eq!(routes[1].kind, Kind::Named as u8);
+    /// assert_eq!(payloads[routes[1].payload_index], &"product");
+    /// ```
+    pub fn to_flat(&self) -> (Vec<FlatRoute>, Vec<&T>) {
+        let mut nodes = Vec::new();
+        self.root.collect_routes(&self.root.key, &mut nodes);
+        let mut routes = Vec::with_capacity(nodes.len());
+        let mut payloads = Vec::with_capacity(nodes.len());
+        for (path, node) in nodes {
+            if let Some(payload) = &node.payload {
+                routes.push(FlatRoute {
+                    pattern: self.external_path(path),
+                    kind: node.kind().clone() as u8,
+                    payload_index: payloads.len(),
+                });
+                payloads.push(payload);
+            }
+        }
+        (routes, payloads)
+    }
+
+    /// Returns the payload registered for the exact *pattern*, first adding
+    /// *pattern* with the payload built by *f* when it isn't registered yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `add` when *pattern* cannot be added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<Vec<&str>>::new();
+    /// tree.get_or_insert_with("/users/:id", Vec::new).push("auth");
+    /// tree.get_or_insert_with("/users/:id", Vec::new).push("log");
+    /// assert_eq!(tree.get_pattern("/users/:id"), Some(&vec!["auth", "log"]));
+    /// ```
+    pub fn get_or_insert_with<F: FnOnce() -> T>(
+        &mut self,
+        pattern: impl Into<String>,
+        f: F,
+    ) -> &mut T {
+        let pattern = pattern.into();
+        if self.get_pattern(&pattern).is_none() {
+            self.add(pattern.as_str(), f());
+        } else {
+            // the caller may change the payload through the reference
+            self.payload_changed();
+        }
+        let pattern = self.internal_path(&pattern).into_owned();
+        self.root
+            .find_pattern_mut(&pattern)
+            .and_then(|node| node.payload.as_mut())
+            .expect("pattern added above")
+    }
+
+    /// Replaces the payload registered for the exact *pattern*, matching named
+    /// (`:`) and catch all (`*`) parameters literally, returning the previous
+    /// payload. Unlike `add`, it never adds a route: `None` is returned and
+    /// the Tree left as it was when *pattern* is not registered, e.g. so that
+    /// reloading a configuration can't introduce new routes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "v1");
+    /// assert_eq!(tree.replace("/users/:id", "v2"), Some("v1"));
+    /// assert_eq!(tree.find("/users/42").payload, &Some("v2"));
+    /// assert_eq!(tree.replace("/users/42", "v3"), None);
+    /// assert_eq!(tree.replace("/users", "v3"), None);
+    /// assert_eq!(tree.find("/users").payload, &None);
+    /// ```
+    pub fn replace(&mut self, pattern: &str, payload: T) -> Option<T> {
+        let pattern = self.internal_path(pattern);
+        let node = self.root.find_pattern_mut(&pattern)?;
+        let old = std::mem::replace(node.payload.as_mut()?, payload);
+        self.payload_changed();
+        Some(old)
+    }
+
+    /// Builds a path from the registered *pattern*, replacing each named (`:`)
+    /// and catch all (`*`) parameter by its value in *params*.
+    ///
+    /// Returns `None` if *pattern* is not registered or a parameter is missing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products/:id/edit", "edit");
+    /// let mut params = HashMap::new();
+    /// params.insert("id".to_string(), "10".to_string());
+    /// assert_eq!(tree.url_for("/products/:id/edit", &params), Some("/products/10/edit".to_string()));
+    /// ```
+    pub fn url_for(&self, pattern: &str, params: &HashMap<String, String>) -> Option<String> {
+        self.get_pattern(pattern)?;
+        let separators = self.syntax.extra_separators();
+        fill_pattern(&self.internal_path(pattern), params, separators)
+            .map(|path| self.external_path(path))
+    }
+
+    /// Returns the patterns of the routes `find` can never reach, because a
+    /// sibling of the route (or of one of its ancestors) is tried first and
+    /// matches every path it would.
+    ///
+    /// With the default `MatchPolicy::SpecificFirst`, static routes always
+    /// win over catch all siblings, so only catch all parameters limited to a
+    /// segment (`*?name`) next to an unconstrained named parameter are
+    /// reported. With `MatchPolicy::GlobFirst`, every sibling of a catch all
+    /// parameter is reported, along with the route right above it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{MatchPolicy, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/*all", "all");
+    /// tree.add("/admin/secret", "secret");
+    /// assert!(tree.shadowed_routes().is_empty());
+    ///
+    /// tree.set_match_policy(MatchPolicy::GlobFirst);
+    /// assert_eq!(tree.shadowed_routes(), vec!["/admin/secret".to_string()]);
+    /// ```
+    pub fn shadowed_routes(&self) -> Vec<String> {
+        let mut shadowed = Vec::new();
+        let glob_first = self.policy == MatchPolicy::GlobFirst;
+        self.root
+            .collect_shadowed(&self.root.key, glob_first, &mut shadowed);
+        shadowed
+            .into_iter()
+            .map(|path| self.external_path(path))
+            .collect()
+    }
+
+    /// Returns the names of the parameters captured by the route registered as
+    /// *pattern*, in order, e.g. to check at startup that they cover the fields
+    /// they are bound to. Returns an empty Vec when *pattern* is not registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id/files/*path", "file");
+    /// assert_eq!(tree.param_names_for("/users/:id/files/*path"), vec!["id", "path"]);
+    /// assert!(tree.param_names_for("/users/:id").is_empty());
+    /// ```
+    pub fn param_names_for(&self, pattern: &str) -> Vec<String> {
+        if self.get_pattern(pattern).is_none() {
+            return Vec::new();
+        }
+        param_names(&self.internal_path(pattern))
+    }
+
+    /// Checks whether some path would be matched by both patterns *a* and *b*,
+    /// without adding them into a Tree, e.g. to detect shadowed routes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// assert!(Tree::<()>::patterns_overlap("/users/:id", "/users/42"));
+    /// assert!(!Tree::<()>::patterns_overlap("/users/:id", "/posts/:id"));
+    /// ```
+    pub fn patterns_overlap(a: &str, b: &str) -> bool {
+        crate::pattern::patterns_overlap(a, b)
+    }
+
+    /// Returns the conflict *path* would raise if added into the Tree, without modifying it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{ConflictKind, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "user");
+    /// assert_eq!(tree.would_conflict("/users/:id"), Some(ConflictKind::Duplicate));
+    /// assert_eq!(tree.would_conflict("/users/:name"), Some(ConflictKind::AmbiguousDynamic));
+    /// assert_eq!(tree.would_conflict("/users"), None);
+    /// ```
+    pub fn would_conflict(&self, path: impl Into<String>) -> Option<ConflictKind> {
+        if self.root.placeholder {
+            return None;
+        }
+        let separators = self.syntax.extra_separators();
+        Tree::<T>::conflict_internal(&self.internal_path(&path.into()), &self.root, separators)
+    }
+
+    fn conflict_internal(path: &str, node: &Node<T>, separators: &[char]) -> Option<ConflictKind> {
+        let pos = path
+            .chars()
+            .zip(node.key.chars())
+            .take_while(|(p, k)| p == k)
+            .count();
+        let key_size = node.key.chars().count();
+        let path_size = path.chars().count();
+
+        let path_next = path.chars().nth(pos);
+
+        // same branches as add_internal, without modifying any node
+        if key_size <= pos && pos < path_size {
+            let new_key = suffix(path, pos);
+            if splits_dynamic(&node.key, None, path_next, separators)
+                || node
+                    .children
+                    .iter()
+                    .any(|child| conflicting_dynamic(&new_key, &child.key, separators))
+            {
+                return Some(ConflictKind::AmbiguousDynamic);
+            }
+            return node
+                .children
+                .iter()
+                .find(|child| child.key.chars().next() == new_key.chars().next())
+                .and_then(|child| Tree::<T>::conflict_internal(&new_key, child, separators));
+        } else if key_size == pos && pos == path_size {
+            if node.payload.is_some() {
+                return Some(ConflictKind::Duplicate);
+            }
+        } else if splits_dynamic(
+            &prefix(path, pos),
+            node.key.chars().nth(pos),
+            path_next,
+            separators,
+        ) {
+            return Some(ConflictKind::AmbiguousDynamic);
+        }
+        None
+    }
+
+    /// Sets the payload returned by `find`, `find_borrowed`, `find_ancestor` and
+    /// `find_case_insensitive` when *path* matches no route, see `Result::is_fallback`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// tree.set_fallback("not found");
+    /// let result = tree.find("/products");
+    /// assert_eq!(result.payload, &Some("not found"));
+    /// assert!(result.is_fallback());
+    /// assert!(!tree.find("/about").is_fallback());
+    /// ```
+    ///
+    /// Unlike a `/*filepath` route, the fallback captures no params and is only
+    /// considered once the walk failed, which `Result::matched` tells apart:
+    ///
+    /// ```
+    /// use patricia_router::{Matched, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/static/*filepath", "static");
+    /// tree.set_fallback("not found");
+    /// assert_eq!(tree.find("/static/app.js").matched(), Matched::CatchAll);
+    /// assert_eq!(tree.find("/about").matched(), Matched::NotFound);
+    /// ```
+    pub fn set_fallback(&mut self, payload: T) {
+        self.fallback = Some(payload);
+    }
+
+    /// Sets whether paths only matched by a catch all route return the
+    /// fallback instead, e.g. to serve a 404 without unregistering
+    /// `/*filepath`. Catch alls still win over their siblings as configured
+    /// through `set_match_policy`, the walk doesn't try the other routes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{Matched, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// tree.add("/*filepath", "static");
+    /// tree.set_fallback("not found");
+    /// tree.set_catch_all_not_found(true);
+    /// let result = tree.find("/missing");
+    /// assert_eq!(result.payload, &Some("not found"));
+    /// assert_eq!(result.matched(), Matched::NotFound);
+    /// assert_eq!(tree.find("/about").matched(), Matched::Route);
+    /// ```
+    pub fn set_catch_all_not_found(&mut self, enabled: bool) {
+        self.catch_all_not_found = enabled;
+    }
+
+    /// Sets which sibling wins when several routes match the same path, e.g.
+    /// to let a catch all route override every other route.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{MatchPolicy, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/*path", "maintenance");
+    /// tree.add("/about", "about");
+    /// assert_eq!(tree.find("/about").payload, &Some("about"));
+    ///
+    /// tree.set_match_policy(MatchPolicy::GlobFirst);
+    /// assert_eq!(tree.find("/about").payload, &Some("maintenance"));
+    /// ```
+    pub fn set_match_policy(&mut self, policy: MatchPolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets what `find` returns when *path* doesn't match a route, e.g. to fall
+    /// back to the closest route above it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{PartialMatch, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/products/:id/edit", "edit");
+    /// assert_eq!(tree.find("/products/10").payload, &None);
+    ///
+    /// tree.set_partial_match(PartialMatch::LongestPrefix);
+    /// let result = tree.find("/products/10");
+    /// assert_eq!(result.payload, &Some("products"));
+    /// assert_eq!(result.key(), "/products");
+    /// ```
+    pub fn set_partial_match(&mut self, partial: PartialMatch) {
+        self.partial = partial;
+    }
+
+    /// Sets how `find` treats a path differing from a route only by a trailing
+    /// slash, e.g. to redirect clients to the canonical path. The route
+    /// matches in every mode, the redirect modes only tell which status to
+    /// answer with through `Result::redirect_status`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{TrailingSlash, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// assert_eq!(tree.find("/about/").redirect_status(), None);
+    ///
+    /// tree.set_trailing_slash(TrailingSlash::RedirectPermanent);
+    /// let result = tree.find("/about/");
+    /// assert_eq!(result.payload, &Some("about"));
+    /// assert_eq!(result.redirect_status(), Some(308));
+    /// assert_eq!(tree.find("/about").redirect_status(), None);
+    /// ```
+    pub fn set_trailing_slash(&mut self, trailing_slash: TrailingSlash) {
+        self.trailing_slash = trailing_slash;
+    }
+
+    /// Sets what adding a path already registered with a payload does, e.g.
+    /// to let configurations override earlier routes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{DuplicatePolicy, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.set_duplicate_policy(DuplicatePolicy::FirstWins);
+    /// tree.add("/about", "first");
+    /// tree.add("/about", "second");
+    /// assert_eq!(tree.find("/about").payload, &Some("first"));
+    ///
+    /// tree.set_duplicate_policy(DuplicatePolicy::LastWins);
+    /// tree.add("/about", "third");
+    /// assert_eq!(tree.find("/about").payload, &Some("third"));
+    /// ```
+    pub fn set_duplicate_policy(&mut self, duplicates: DuplicatePolicy) {
+        self.duplicates = duplicates;
+    }
+
+    /// Sets the length (in characters) above which paths match no route, to
+    /// reject absurdly long paths before walking the Tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/*path", "all");
+    /// tree.set_max_path_len(16);
+    /// assert_eq!(tree.find("/short").payload, &Some("all"));
+    /// assert_eq!(tree.find(format!("/{}", "a".repeat(64))).payload, &None);
+    /// ```
+    pub fn set_max_path_len(&mut self, max_path_len: usize) {
+        self.max_path_len = Some(max_path_len);
+    }
+
+    /// Sets the number of segments above which paths match no route, to
+    /// reject deep paths before walking the Tree. Segments are counted by
+    /// their leading separator, leaving out a trailing one, so `/a/b` and
+    /// `/a/b/` both have 2 segments.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/*path", "all");
+    /// tree.set_max_segments(3);
+    /// assert_eq!(tree.find("/a/b/c/").payload, &Some("all"));
+    /// assert_eq!(tree.find("/a/b/c/d").payload, &None);
+    /// ```
+    pub fn set_max_segments(&mut self, max_segments: usize) {
+        self.max_segments = Some(max_segments);
+    }
+
+    /// Sets whether paths are normalized to Unicode Normalization Form C when
+    /// added and looked up, so that composed and decomposed forms of the same
+    /// characters (e.g. `é` and `e` followed by a combining accent) match.
+    /// Disabled by default. Only enable it before adding routes.
+    ///
+    /// Every lookup then checks whether the path is in NFC, which is quick for
+    /// ASCII paths but scans the other ones. Paths that aren't get copied
+    /// while normalizing, and the positions of `Result::catch_all` and
+    /// `Result::param_range` refer to the normalized path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.set_nfc(true);
+    /// tree.add("/caf\u{e9}", "composed");
+    /// assert_eq!(tree.find("/cafe\u{301}").payload, &Some("composed"));
+    /// ```
+    #[cfg(feature = "nfc")]
+    pub fn set_nfc(&mut self, enabled: bool) {
+        self.nfc = enabled;
+    }
+
+    /// Replaces the state of the Tree by *snapshot*, see `Tree::snapshot`.
+    pub fn restore(&mut self, snapshot: TreeSnapshot<T>) {
+        let generation = self.generation;
+        *self = snapshot.tree;
+        self.generation = generation + 1;
+        self.reindex();
+    }
+
+    /// Sets whether lookups start from a hash index of the first segments of
+    /// the routes, instead of walking the nodes the first segment of the path
+    /// goes through. This speeds up Trees holding many routes whose first
+    /// segments share prefixes (e.g. hundreds of resources like `/users`,
+    /// `/user_groups`, `/posts`) at the cost of rebuilding the index after
+    /// every route added, so it is best enabled once the routes are added.
+    ///
+    /// Paths whose first segment isn't a static segment of a route, and
+    /// lookups relying on every node walked through (e.g. `find_explain`,
+    /// `find_strict`, `find_case_insensitive`, `find_ancestor` or
+    /// `MatchPolicy::GlobFirst`), walk the nodes as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "user");
+    /// tree.add("/user_groups/:id", "group");
+    /// tree.add("/*path", "all");
+    /// tree.set_segment_index(true);
+    /// assert_eq!(tree.find("/user_groups/7").params("id"), "7");
+    /// assert_eq!(tree.find("/posts/7").payload, &Some("all"));
+    /// ```
+    pub fn set_segment_index(&mut self, enabled: bool) {
+        self.segment_index.enabled = enabled;
+        self.reindex();
+    }
+
+    /// Counts a change to a payload, which leaves the nodes and so the segment
+    /// index as they were.
+    fn payload_changed(&mut self) {
+        if self.segment_index.generation == self.generation {
+            self.segment_index.generation += 1;
+        }
+        self.generation += 1;
+    }
+
+    /// Rebuilds the segment index, if enabled, for the current generation.
+    fn reindex(&mut self) {
+        let index = &mut self.segment_index;
+        index.nodes.clear();
+        if index.enabled {
+            index.generation = self.generation;
+            self.root
+                .index_segments(&mut String::new(), &mut Vec::new(), &mut index.nodes);
+        }
+    }
+
+    /// Returns a counter incremented by every change to the routes of the
+    /// Tree or their payloads (adding a route, `replace`, handing out a payload
+    /// through `get_or_insert_with`, `sort`, `compact` and `restore`), to tell when
+    /// structures derived from the Tree must be rebuilt. Settings such as the
+    /// fallback or the match policy don't count as changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// let generation = tree.generation();
+    /// tree.add("/about", "about");
+    /// assert_ne!(tree.generation(), generation);
+    /// ```
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn new_result(&self) -> Result<'_, T> {
+        let mut result = Result::new();
+        result.policy = self.policy;
+        result.trailing_slash = self.trailing_slash;
+        result.catch_all_not_found = self.catch_all_not_found;
+        result
+    }
+
+    /// Returns a `patricia_router::result::Result` after walking the tree looking up for *path*.
+    ///
+    /// Catch all parameters registered without a name are captured as
+    /// [`UNNAMED_CATCH_ALL`](constant.UNNAMED_CATCH_ALL.html). When nothing
+    /// matches, the payload set by `set_fallback` is returned instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// let result = tree.find("/about");
+    /// assert_eq!(result.matched_path(), Some("/about"));
+    /// ```
+    pub fn find<'a>(&'a self, path: impl Into<String>) -> Result<'a, T> {
+        let path = path.into();
+        let mut result = self.find_route(&path);
+        if result.payload.is_some() {
+            result.matched_path = Some(path);
+        }
+        result.or_fallback(&self.fallback)
+    }
+
+    /// Walks the tree like `find`, returning the payload along with the key of
+    /// the matched route, for callers that only need both.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id", "user");
+    /// assert_eq!(tree.find_keyed("/users/42"), (Some(&"user"), "/users/:id".to_string()));
+    /// assert_eq!(tree.find_keyed("/about"), (None, String::new()));
+    /// ```
+    pub fn find_keyed(&self, path: impl Into<String>) -> (Option<&T>, String) {
+        let (payload, key) = self.find(path).into_key();
+        (payload.as_ref(), key)
+    }
+
+    fn find_route<'a>(&'a self, path: &str) -> Result<'a, T> {
+        let mut result = self.new_result();
+        if self.partial == PartialMatch::LongestPrefix {
+            result.track_ancestor = true;
+            return self.walk(path, result, &mut None).into_ancestor();
+        }
+        self.walk(path, result, &mut None)
+    }
+
+    /// Walks the tree like `find`, also counting the nodes visited and the
+    /// characters compared to reach the result.
+    ///
+    /// Requires the `instrumentation` feature, which compiles the counters into
+    /// every lookup whenever it is enabled, release builds included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products", "products");
+    /// tree.add("/products/featured", "featured");
+    /// let (result, stats) = tree.find_with_stats("/products/featured");
+    /// assert_eq!(result.payload, &Some("featured"));
+    /// assert_eq!(stats.nodes, 2);
+    /// ```
+    #[cfg(feature = "instrumentation")]
+    pub fn find_with_stats<'a>(&'a self, path: &str) -> (Result<'a, T>, FindStats) {
+        let result = self.find_route(path).or_fallback(&self.fallback);
+        let stats = result.stats;
+        (result, stats)
+    }
+
+    /// Walks the tree like `find`, also returning why the walk didn't go
+    /// through the other parts of the Tree it reached, e.g. to tell why a
+    /// path doesn't match the expected route.
+    ///
+    /// Siblings are reported from the root down, each with the pattern up to
+    /// the rejected node.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{RejectionReason, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/posts/:status{draft|published}", "posts");
+    /// tree.add("/pages/about", "about");
+    /// let (result, rejections) = tree.find_explain("/posts/archived");
+    /// assert_eq!(result.payload, &None);
+    /// assert_eq!(
+    ///     rejections,
+    ///     vec![
+    ///         RejectionReason::FirstChar {
+    ///             pattern: "/pages/about".to_string()
+    ///         },
+    ///         RejectionReason::Constraint {
+    ///             pattern: "/posts/:status{draft|published}".to_string(),
+    ///             value: "archived".to_string()
+    ///         },
+    ///     ]
+    /// );
+    /// ```
+    #[cfg(feature = "debug")]
+    pub fn find_explain<'a>(&'a self, path: &str) -> (Result<'a, T>, Vec<RejectionReason>) {
+        let mut result = self.new_result();
+        result.rejections = Some(Vec::new());
+        let mut result = self.walk(path, result, &mut None);
+        let rejections = result.rejections.take().unwrap_or_default();
+        let rejections = if self.syntax.is_default() {
+            rejections
+        } else {
+            rejections
+                .into_iter()
+                .map(|reason| reason.with_syntax(&self.syntax))
+                .collect()
+        };
+        (result.or_fallback(&self.fallback), rejections)
+    }
+
+    /// Validates *path* before walking the tree like `find`, telling apart a
+    /// malformed path from a path without route.
+    ///
+    /// A path is malformed when it contains control characters (e.g. NUL).
+    /// Returns `Ok(None)` when a well-formed path matches no route.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{FindError, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// assert!(tree.try_find("/about").unwrap().is_some());
+    /// assert!(tree.try_find("/products").unwrap().is_none());
+    /// assert_eq!(tree.try_find("/ab\0out").unwrap_err(), FindError::ControlCharacter(3));
+    /// ```
+    pub fn try_find<'a>(
+        &'a self,
+        path: impl Into<String>,
+    ) -> std::result::Result<Option<Result<'a, T>>, FindError> {
+        let path = path.into();
+        if let Some(pos) = path.chars().position(|ch| ch.is_control()) {
+            return Err(FindError::ControlCharacter(pos));
+        }
+        let result = self.find_route(&path);
+        if result.payload.is_some() {
+            Ok(Some(result))
+        } else {
+            Ok(None)
+        }
+    }
 
-/// A [Radix tree](https://en.wikipedia.org/wiki/Radix_tree) implementation.
-pub struct Tree<T> {
-    root: Node<T>,
-}
+    /// Walks the tree looking up for *path*, handing each parameter to *sink*
+    /// as soon as it is captured instead of collecting them into a map.
+    /// Returns the payload of the matched route.
+    ///
+    /// As the walk doesn't look ahead, *sink* may receive parameters of a
+    /// route that ends up not matching, in which case `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:id/*rest", "user");
+    /// let mut params = Vec::new();
+    /// let payload = tree.find_streaming("/users/42/a/b", |name, value| {
+    ///     params.push(format!("{}={}", name, value))
+    /// });
+    /// assert_eq!(payload, Some(&"user"));
+    /// assert_eq!(params, vec!["id=42", "rest=a/b"]);
+    /// ```
+    pub fn find_streaming<'a>(
+        &'a self,
+        path: &str,
+        mut sink: impl FnMut(&str, &str),
+    ) -> Option<&'a T> {
+        let result = self.new_result();
+        let result = if self.syntax.is_default() {
+            self.walk(path, result, &mut Some(&mut sink))
+        } else {
+            let mut translate = |name: &str, value: &str| sink(name, &self.syntax.external(value));
+            self.walk(path, result, &mut Some(&mut translate))
+        };
+        result.payload.as_ref()
+    }
 
-impl<T> Tree<T> {
-    pub fn new() -> Self {
-        Self {
-            root: Node::<T>::new("", None, true),
+    /// Walks the tree like `find`, checking at every node walked through
+    /// whether a sibling as specific as the child taken (same kind and
+    /// priority) would match *path* as well, which `find` resolves by taking
+    /// the first one. Returns `None` when no route matches.
+    ///
+    /// Routes added through `add` never leave such siblings, they are rejected
+    /// as conflicting. This catches trees whose nodes were changed otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/files/*path", "files");
+    /// tree.add("/files/readme", "readme");
+    /// let result = tree.find_strict("/files/readme").unwrap().unwrap();
+    /// assert_eq!(result.payload, &Some("readme"));
+    /// assert!(tree.find_strict("/about").unwrap().is_none());
+    /// ```
+    pub fn find_strict<'a>(
+        &'a self,
+        path: &str,
+    ) -> std::result::Result<Option<Result<'a, T>>, AmbiguityError> {
+        let mut result = self.new_result();
+        result.strict = true;
+        let mut result = self.walk(path, result, &mut None);
+        if result.payload.is_none() {
+            return Ok(None);
+        }
+        match result.rival.take() {
+            Some(rival) => Err(AmbiguityError {
+                chosen: result.key(),
+                rival: self.external_path(rival),
+            }),
+            None => Ok(Some(result)),
         }
     }
 
-    /// Adds *path* into the Tree.
+    /// Walks the tree like `find` without capturing any parameter, each one
+    /// being captured when asked for through `LazyResult::get_param`. Suits
+    /// routes with many parameters whose handlers only read a few.
     ///
     /// # Examples
     ///
@@ -22,85 +2148,179 @@ impl<T> Tree<T> {
     /// use patricia_router::Tree;
     ///
     /// let mut tree = Tree::<&str>::new();
-    /// tree.add("/abc", "root");
+    /// tree.add("/orgs/:org/repos/:repo/issues/:issue", "issue");
+    /// let result = tree.find_lazy("/orgs/rust/repos/regex/issues/42");
+    /// assert_eq!(result.payload, &Some("issue"));
+    /// assert_eq!(result.get_param("issue").as_deref(), Some("42"));
+    /// assert_eq!(result.get_param("user"), None);
     /// ```
-    pub fn add(&mut self, path: impl Into<String>, payload: T) -> () {
-        if self.root.placeholder {
-            self.root = Node::<T>::new(&path.into(), Some(payload), false);
+    pub fn find_lazy<'a, 'p>(&'a self, path: &'p str) -> LazyResult<'a, 'p, T> {
+        let mut result = self.new_result();
+        result.borrowed = true;
+        let mut sink = |_: &str, _: &str| {};
+        let result = if self.partial == PartialMatch::LongestPrefix {
+            result.track_ancestor = true;
+            self.walk(path, result, &mut Some(&mut sink))
+                .into_ancestor()
         } else {
-            Tree::<T>::add_internal(&path.into(), Some(payload), &mut self.root);
-        }
+            self.walk(path, result, &mut Some(&mut sink))
+        };
+        let (nodes, payload) = result.or_fallback(&self.fallback).into_nodes();
+        let path = self.internal_path(path);
+        LazyResult::new(nodes, path, &self.syntax, payload)
     }
 
-    fn add_internal(path: &String, payload: Option<T>, node: &mut Node<T>) -> () {
-        let mut rest_path_peekable = path.chars().peekable();
-        let mut rest_key_peekable = node.key.chars().peekable();
-        let mut pos = 0;
+    /// Returns the pattern of the route matching *path*, e.g. to label metrics,
+    /// without building the parameters (captured values are dropped as soon
+    /// as they are matched). The fallback is not considered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products/:id/edit", "edit");
+    /// tree.add("/files/*path", "files");
+    /// assert_eq!(tree.template_for("/products/10/edit"), Some("/products/:id/edit".to_string()));
+    /// assert_eq!(tree.template_for("/files/a/b.png"), Some("/files/*path".to_string()));
+    /// assert_eq!(tree.template_for("/products/10"), None);
+    /// ```
+    pub fn template_for(&self, path: &str) -> Option<String> {
+        let mut result = self.new_result();
+        result.borrowed = true;
+        let result = self.walk(path, result, &mut Some(&mut |_: &str, _: &str| {}));
+        result.payload.as_ref().map(|_| result.key())
+    }
 
-        // move cursor position to last shared character between key and path
-        loop {
-            let p = rest_path_peekable.peek();
-            let k = rest_key_peekable.peek();
-            if p.is_none() || k.is_none() || p != k {
-                break;
-            }
-            rest_path_peekable.next();
-            rest_key_peekable.next();
-            pos += 1;
+    /// Returns the keys of the static children of the node where walking
+    /// *path* stopped, e.g. to suggest the allowed continuations of a path
+    /// matching no route. Children starting with a parameter are left out, the
+    /// others come in the order they are tried in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/products/new", "new");
+    /// tree.add("/products/featured", "featured");
+    /// tree.add("/products/:id", "product");
+    /// assert_eq!(tree.allowed_at("/products/"), vec!["featured", "new"]);
+    /// assert_eq!(tree.allowed_at("/products/10"), Vec::<String>::new());
+    /// ```
+    pub fn allowed_at(&self, path: &str) -> Vec<String> {
+        let mut result = self.new_result();
+        result.borrowed = true;
+        result.keep_stop = true;
+        let result = self.walk(path, result, &mut Some(&mut |_: &str, _: &str| {}));
+        match result.leaf() {
+            Some(node) => node
+                .children
+                .iter()
+                .filter(|child| child.kind() == &Kind::Normal)
+                .map(|child| self.external_path(child.key.to_string()))
+                .collect(),
+            None => Vec::new(),
         }
+    }
 
-        let rest_path = rest_path_peekable.collect::<String>();
-        let key_size = node.key.bytes().len();
-        let path_size = path.bytes().len();
+    /// Returns the kind of the node matching *path*, telling apart static
+    /// routes from routes reached through a named or catch all parameter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::{Kind, Tree};
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/*filepath", "all");
+    /// tree.add("/products/:id", "product");
+    /// assert_eq!(tree.classify("/products/10"), Some(Kind::Named));
+    /// assert_eq!(tree.classify("/about"), Some(Kind::Glob));
+    /// ```
+    pub fn classify(&self, path: impl Into<String>) -> Option<Kind> {
+        let result = self.find_route(&path.into());
+        result.payload.as_ref()?;
+        result.leaf().map(|node| node.kind().clone())
+    }
 
-        // determine split point difference between path and key
-        // compare if path is larger than key
-        if pos == 0 || (key_size <= pos && pos < path_size) {
-            // determine if a child of this node contains the remaining part
-            // of the path
-            let new_key = rest_path.as_str();
-            let child_op = node
-                .children
-                .iter_mut()
-                .find(|child| same_first_char(new_key, &child.key));
+    /// Walks the tree like `find`, but when *path* doesn't match a route returns
+    /// the deepest route found along the walk that *path* continues with a separator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/admin/users/:id", "user");
+    /// let result = tree.find_ancestor("/admin/users/42/edit");
+    /// assert_eq!(result.key(), "/admin/users/:id");
+    /// assert_eq!(result.payload, &Some("user"));
+    /// ```
+    pub fn find_ancestor<'a>(&'a self, path: impl Into<String>) -> Result<'a, T> {
+        let mut result = self.new_result();
+        result.track_ancestor = true;
+        self.walk(&path.into(), result, &mut None)
+            .into_ancestor()
+            .or_fallback(&self.fallback)
+    }
 
-            match child_op {
-                Some(mut child) => {
-                    Tree::<T>::add_internal(&new_key.to_string(), payload, &mut child)
-                }
-                None => node.children.push(Node::<T>::new(new_key, payload, false)),
-            }
-            node.sort_children();
-        } else if key_size == pos && pos == path_size {
-            // determine if path matches key and potentially be a duplicate
-            // and raise if is the case
+    /// Walks the tree like `find`, comparing static parts of the routes with
+    /// *path* ignoring ASCII case.
+    ///
+    /// The key of the Result keeps the casing the route was registered with,
+    /// while parameters keep the casing of *path*. As the walk doesn't backtrack,
+    /// siblings differing only by case are tried with the casing of *path* first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about/:name", "about");
+    /// let result = tree.find_case_insensitive("/About/Team");
+    /// assert_eq!(result.payload, &Some("about"));
+    /// assert_eq!(result.key(), "/about/:name");
+    /// assert_eq!(result.params("name"), "Team");
+    /// ```
+    pub fn find_case_insensitive<'a>(&'a self, path: &str) -> Result<'a, T> {
+        let mut result = self.new_result();
+        result.ignore_case = true;
+        self.walk(path, result, &mut None)
+            .or_fallback(&self.fallback)
+    }
 
-            if node.payload.is_some() {
-                panic!("duplicate error");
-            }
-            node.payload = payload;
-        } else if 0 < pos && pos < key_size {
-            // determine if current node key needs to be split to accomodate new
-            // children nodes
-            let rest_key = rest_key_peekable.collect::<String>();
-            let new_key = rest_key.as_str();
-            let mut new_node: Node<T> = Node::<T>::new(new_key, None, false);
-            new_node.payload = std::mem::replace(&mut node.payload, None);
-            new_node.children = std::mem::replace(&mut node.children, vec![]);
-            node.set_key(prefix(path, pos));
-            node.children.push(new_node);
-            // determine if path still continues
-            if pos < path_size {
-                node.children
-                    .push(Node::<T>::new(rest_path.as_str(), payload, false));
-            } else {
-                node.payload = payload;
-            }
-            node.sort_children();
-        }
+    /// Walks the tree like `find`, also recording where each parameter lies in
+    /// *path* as a byte range, see `Result::param_range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/users/:name/*rest", "user");
+    /// let path = "/users/ジョン/a/b";
+    /// let result = tree.find_with_ranges(path);
+    /// let range = result.param_range("name").unwrap();
+    /// assert_eq!(&path[range], "ジョン");
+    /// assert_eq!(result.param_range("rest"), Some(17..20));
+    /// ```
+    pub fn find_with_ranges<'a>(&'a self, path: &str) -> Result<'a, T> {
+        let mut result = self.new_result();
+        result.track_ranges = true;
+        self.walk(path, result, &mut None)
+            .or_fallback(&self.fallback)
     }
 
-    /// Returns a `patricia_router::result::Result` after walking the tree looking up for *path*.
+    /// Walks the tree like `find`, then percent-decodes the parameters.
+    ///
+    /// *path* is matched as is, so an encoded separator (`%2F`) stays inside
+    /// the segment holding it and only turns into `/` in the parameter value.
     ///
     /// # Examples
     ///
@@ -108,23 +2328,158 @@ impl<T> Tree<T> {
     /// use patricia_router::Tree;
     ///
     /// let mut tree = Tree::<&str>::new();
-    /// tree.add("/about", "about");
-    /// let result = tree.find("/about");
+    /// tree.add("/files/:name/meta", "meta");
+    /// let result = tree.find_decoded("/files/a%2Fb/meta");
+    /// assert_eq!(result.payload, &Some("meta"));
+    /// assert_eq!(result.params("name"), "a/b");
     /// ```
-    pub fn find<'a>(&'a self, path: impl Into<String>) -> Result<'a, T> {
-        let result = Result::<'a, T>::new();
-        return Tree::<T>::find_internal(&path.into(), result, &self.root, true);
+    pub fn find_decoded<'a>(&'a self, path: &str) -> Result<'a, T> {
+        self.find_route(path)
+            .decode_params()
+            .or_fallback(&self.fallback)
+    }
+
+    /// Walks the tree like `find`, but leaves the catch all parameter out of the
+    /// params to avoid copying it. The capture is borrowed from *path* instead
+    /// through `Result::catch_all`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/files/*filepath", "files");
+    /// let path = "/files/src/file.png";
+    /// let result = tree.find_borrowed(path);
+    /// assert_eq!(result.catch_all(path), Some("src/file.png"));
+    /// ```
+    pub fn find_borrowed<'a>(&'a self, path: &str) -> Result<'a, T> {
+        let mut result = self.new_result();
+        result.borrowed = true;
+        self.walk(path, result, &mut None)
+            .or_fallback(&self.fallback)
+    }
+
+    /// Walks the tree from the root looking up for *path*, translating the
+    /// separator and markers back and forth when the Tree uses other ones.
+    fn walk<'a>(&'a self, path: &str, mut result: Result<'a, T>, sink: &mut Sink) -> Result<'a, T> {
+        #[cfg(feature = "nfc")]
+        let normalized = if self.nfc {
+            to_nfc(path)
+        } else {
+            Cow::Borrowed(path)
+        };
+        #[cfg(feature = "nfc")]
+        let path = normalized.as_ref();
+        if let Some(max) = self.max_path_len {
+            // checking the bytes first avoids counting the characters of short paths
+            if path.len() > max && path.chars().nth(max).is_some() {
+                return result;
+            }
+        }
+        if let Some(max) = self.max_segments {
+            // stops counting at the first separator past the limit
+            let separator = self.syntax.separator();
+            let trimmed = path.strip_suffix(separator).unwrap_or(path);
+            if trimmed.matches(separator).nth(max).is_some() {
+                return result;
+            }
+        }
+        result.separators = self.syntax.extra_separators();
+        if self.syntax.is_default() {
+            return self.find_from_root(path, result, sink);
+        }
+        let internal = self.internal_path(path);
+        self.find_from_root(&internal, result, sink)
+            .with_syntax(&internal, &self.syntax)
+    }
+
+    /// Walks the tree from the root, or from the node the segment index gives
+    /// for the first segment of *path* when the walk can skip the nodes before.
+    fn find_from_root<'a>(
+        &'a self,
+        path: &str,
+        mut result: Result<'a, T>,
+        sink: &mut Sink,
+    ) -> Result<'a, T> {
+        let (positions, start) = match self.indexed_node(path, &result) {
+            Some(found) => found,
+            None => return Tree::<T>::find_internal(path, 0, result, &self.root, true, sink),
+        };
+        let mut node = &self.root;
+        for &pos in positions {
+            result = result.add(node, false);
+            node = &node.children[pos];
+        }
+        Tree::<T>::find_internal(&path[start..], start, result, node, false, sink)
+    }
+
+    /// Returns the positions of the node the walk would reach holding the
+    /// end of the first segment of *path*, along with the position of its key
+    /// in *path*, `None` when the segment index can't be used.
+    fn indexed_node<'a>(
+        &'a self,
+        path: &str,
+        result: &Result<'a, T>,
+    ) -> Option<(&'a [usize], usize)> {
+        let index = &self.segment_index;
+        if !index.enabled || index.generation != self.generation {
+            return None;
+        }
+        // these lookups look at every node walked through
+        if result.ignore_case
+            || result.strict
+            || result.track_ancestor
+            || result.policy == MatchPolicy::GlobFirst
+        {
+            return None;
+        }
+        #[cfg(feature = "debug")]
+        if result.rejections.is_some() {
+            return None;
+        }
+        let (end, _) = path.char_indices().skip(1).find(|&(_, ch)| ch == '/')?;
+        // a trailing slash alone may match the route ending before it
+        if path.len() - end < 2 {
+            return None;
+        }
+        let (positions, start) = index.nodes.get(&path[..end])?;
+        let mut node = &self.root;
+        for &pos in positions {
+            node = node.children.get(pos)?;
+        }
+        // the parent only walks into the node when its key starts the rest of
+        // the path, otherwise a dynamic sibling may match
+        if !shared_key(&path[*start..], &node.key) {
+            return None;
+        }
+        Some((positions, *start))
     }
 
+    // offset is the position (in bytes) of path inside the path given to find
     fn find_internal<'a>(
         path: &str,
+        offset: usize,
         mut result: Result<'a, T>,
         node: &'a Node<T>,
         first: bool,
+        sink: &mut Sink,
     ) -> Result<'a, T> {
+        #[cfg(feature = "instrumentation")]
+        {
+            result.stats.nodes += 1;
+        }
         let key_size = node.key.chars().count();
         let path_size = path.chars().count();
-        if first && path_size == key_size && path == &node.key && node.payload.is_some() {
+        let ignore_case = result.ignore_case;
+        let glob_first = result.policy == MatchPolicy::GlobFirst;
+        let same_path = if ignore_case {
+            path.eq_ignore_ascii_case(&node.key)
+        } else {
+            path == node.key
+        };
+        if first && path_size == key_size && same_path && node.payload.is_some() && !glob_first {
             return result.add(node, true);
         }
 
@@ -138,32 +2493,92 @@ impl<T> Tree<T> {
             if path_current.is_none() || key_current.is_none() {
                 break;
             }
-            if key_current != Some(&'*') && key_current != Some(&':') && path_current != key_current
+            #[cfg(feature = "instrumentation")]
             {
+                result.stats.comparisons += 1;
+            }
+            let same_char = path_current == key_current
+                || (ignore_case
+                    && path_current
+                        .zip(key_current)
+                        .is_some_and(|(p, k)| p.eq_ignore_ascii_case(k)));
+            if key_current != Some(&'*') && key_current != Some(&':') && !same_char {
                 break;
             }
             if let Some(k) = key_current {
-                if k == &'*' {
+                if k == &'*' && key_vec.get(key_pos + 1) == Some(&'?') {
+                    // deal with catch all parameter limited to a segment, captured
+                    // like a named parameter
+                    let key_size = detect_param_size(&node.key, key_pos, result.separators);
+                    let path_size = detect_param_size(path, path_pos, result.separators);
+                    let name = match slice(&node.key, key_pos + 2, key_size) {
+                        "" => UNNAMED_CATCH_ALL,
+                        name => name,
+                    };
+                    let start = offset + byte_offset(path, path_pos);
+                    result.capture(name, slice(path, path_pos, path_size), start, sink);
+                    path_pos = path_size;
+                    key_pos = key_size;
+                    continue;
+                } else if k == &'*' {
                     // deal with catch all (globbing) parameter
-                    // extract parameter name from key (exclude *) and value from path
-                    let name = suffix(&node.key, key_pos + 1);
-                    let value = suffix(path, path_pos);
-                    result.params.insert(name, value);
+                    // extract parameter name from key (exclude *) and value from path,
+                    // leaving out a trailing slash like other routes tolerate it
+                    let start = byte_offset(path, path_pos);
+                    let value = match path[start..].strip_suffix('/') {
+                        Some(value) => {
+                            result.slash_mismatch = true;
+                            value
+                        }
+                        None => &path[start..],
+                    };
+                    if node.is_excluded(value) {
+                        return result;
+                    }
+                    result.catch_all_start = Some(offset + start);
+                    if !result.borrowed {
+                        let name = catch_all_name(&node.key, key_pos + 1);
+                        result.capture(name, value, offset + start, sink);
+                    }
                     return result.add(node, true);
                 } else if k == &':' {
                     // deal with named parameter
                     // extract parameter name from key (from : until / or EOL) and
                     // value from path (same rules as key)
-                    let key_size = detect_param_size(&node.key, key_pos);
-                    let path_size = detect_param_size(path, path_pos);
+                    let key_size = detect_param_size(&node.key, key_pos, result.separators);
+                    let path_size = detect_param_size(path, path_pos, result.separators);
                     // obtain key and value using calculated sizes
                     // for name: skip ':' by moving one character forward and compensate
                     // key size.
-                    let name = substring(&node.key, key_pos + 1, key_size);
-                    let value = substring(path, path_pos, path_size);
-                    result.params.insert(name, value);
-                    path_pos += path_size;
-                    key_pos += key_size;
+                    let (raw, _) = split_default(slice(&node.key, key_pos + 1, key_size));
+                    let (raw, format) = split_format(raw);
+                    let (name, _) = split_constraint(raw);
+                    let segment = slice(path, path_pos, path_size);
+                    let (value, extension) = match format {
+                        Some(_) => split_extension(segment),
+                        None => (segment, ""),
+                    };
+                    if !node.allows(name, value) {
+                        #[cfg(feature = "debug")]
+                        result.explain(node, |pattern, rejections| {
+                            rejections.push(RejectionReason::Constraint {
+                                pattern,
+                                value: value.to_string(),
+                            })
+                        });
+                        return result;
+                    }
+                    let start = offset + byte_offset(path, path_pos);
+                    result.capture(name, value, start, sink);
+                    if let Some(format) = format {
+                        let start = start + segment.len() - extension.len();
+                        result.capture(format, extension, start, sink);
+                    }
+                    // sizes are end positions: move both cursors to the character
+                    // following the parameter, which still has to be compared
+                    path_pos = path_size;
+                    key_pos = key_size;
+                    continue;
                 }
             }
             path_pos += 1;
@@ -173,51 +2588,360 @@ impl<T> Tree<T> {
         let path_next = path_vec.get(path_pos);
         let key_next = key_vec.get(key_pos);
 
-        // check if we reached the end of the path & key
-        if path_next.is_none() && key_next.is_none() && node.payload.is_some() {
-            return result.add(node, true);
+        // check if we reached the end of the path & key
+        if path_next.is_none() && key_next.is_none() {
+            if node.payload.is_some() && !glob_first {
+                return result.add(node, true);
+            }
+            return Tree::<T>::find_optional_param(path, offset, result, node, sink);
+        }
+
+        // still path to walk, check for possible trailing slash or children nodes
+        if path_next.is_some() {
+            // only a trailing slash left once the whole key matched a route
+            if 0 < key_size
+                && key_next.is_none()
+                && node.payload.is_some()
+                && has_trailing_slash(path_pos, path_size, path, result.separators)
+            {
+                result.slash_mismatch = true;
+                return result.add(node, true);
+            }
+
+            let separator = |ch: char| is_separator(ch, result.separators);
+            let boundary =
+                path_next.is_some_and(|&ch| separator(ch)) || node.key.ends_with(separator);
+            if key_next.is_none() && boundary && node.payload.is_some() {
+                result.mark_ancestor(node);
+            }
+
+            // the children only continue the whole key, not a path diverging
+            // inside it
+            if key_next.is_some() {
+                #[cfg(feature = "debug")]
+                result.explain(node, |pattern, rejections| {
+                    rejections.push(RejectionReason::Diverged { pattern })
+                });
+                return result;
+            }
+            let new_path = suffix(path, path_pos);
+            #[cfg(feature = "debug")]
+            result.explain(node, |pattern, rejections| {
+                if node.children.is_empty() {
+                    rejections.push(RejectionReason::PathContinues { pattern });
+                } else {
+                    node.explain_children(&new_path, ignore_case, glob_first, &pattern, rejections)
+                }
+            });
+            if let Some(child) = node.child_for(&new_path, ignore_case, glob_first) {
+                let new_offset = offset + byte_offset(path, path_pos);
+                if result.strict && result.rival.is_none() {
+                    result.rival =
+                        Tree::<T>::find_rival(&new_path, new_offset, &result, node, child);
+                }
+                result = result.add(node, false);
+                return Tree::<T>::find_internal(&new_path, new_offset, result, child, false, sink);
+            }
+            // keep the node the walk stopped at, see `allowed_at`
+            return result.add(node, false);
+        }
+
+        // key still contains characters to walk
+        if key_next.is_some() {
+            if has_trailing_slash(key_pos, key_size, &node.key, result.separators) {
+                if node.payload.is_some() && !glob_first {
+                    result.slash_mismatch = true;
+                    return result.add(node, true);
+                }
+                return Tree::<T>::find_optional_param(path, offset, result, node, sink);
+            }
+
+            if node.has_catch_all(key_pos, key_size, result.separators) {
+                if key_next != Some(&'*') {
+                    key_pos += 1;
+                }
+                result.catch_all_start = Some(offset + path.len());
+                if !result.borrowed {
+                    let name = catch_all_name(&node.key, key_pos + 1);
+                    result.capture(name, "", offset + path.len(), sink);
+                }
+                return result.add(node, true);
+            }
+
+            let rest = &node.key[byte_offset(&node.key, key_pos)..];
+            if let Some((name, default)) = defaulted_param(rest) {
+                result.capture_default(name, default, offset + path.len(), sink);
+                return result.add(node, true);
+            }
+        }
+        #[cfg(feature = "debug")]
+        result.explain(node, |pattern, rejections| {
+            rejections.push(RejectionReason::PathEnded { pattern })
+        });
+        result
+    }
+
+    /// Walks *path* from the rivals of *child* (see `Node::rivals`), returning
+    /// the pattern of the first route one of them matches.
+    fn find_rival(
+        path: &str,
+        offset: usize,
+        result: &Result<'_, T>,
+        node: &Node<T>,
+        child: &Node<T>,
+    ) -> Option<String> {
+        node.rivals(child, path, result.ignore_case)
+            .find_map(|rival| {
+                let mut probe = Result::new();
+                probe.policy = result.policy;
+                probe.ignore_case = result.ignore_case;
+                probe.separators = result.separators;
+                probe.borrowed = true;
+                let mut sink = |_: &str, _: &str| {};
+                let probe = Tree::<T>::find_internal(
+                    path,
+                    offset,
+                    probe,
+                    rival,
+                    false,
+                    &mut Some(&mut sink),
+                );
+                probe.payload.as_ref().map(|_| probe.key())
+            })
+            .map(|rest| result.pattern_through(node) + &rest)
+    }
+
+    /// Matches the exhausted *path* against a catch all child of *node* (e.g. `*rest`,
+    /// `/*rest` or `/` followed by `*rest`), capturing an empty parameter like a
+    /// catch all inside the key of *node* does, or against a child holding a
+    /// named parameter with a default value, capturing the default value.
+    fn find_optional_param<'a>(
+        path: &str,
+        offset: usize,
+        mut result: Result<'a, T>,
+        node: &'a Node<T>,
+        sink: &mut Sink,
+    ) -> Result<'a, T> {
+        if let Some(child) = node.optional_catch_all() {
+            result = result.add(node, false);
+            return Tree::<T>::find_internal("", offset + path.len(), result, child, false, sink);
+        }
+        match node.defaulted_child() {
+            Some((child, name, default)) => {
+                result.capture_default(name, default, offset + path.len(), sink);
+                result.add(node, false).add(child, true)
+            }
+            None if node.payload.is_some() || result.keep_stop => result.add(node, true),
+            None => {
+                #[cfg(feature = "debug")]
+                result.explain(node, |pattern, rejections| {
+                    rejections.push(RejectionReason::NoRoute { pattern })
+                });
+                result
+            }
+        }
+    }
+}
+
+impl<T: Clone> Tree<T> {
+    /// Adds every path in *paths* into the Tree with the same *payload*.
+    ///
+    /// Each path holds its own clone of *payload* (the last one takes the
+    /// original), so later changes to one route do not affect the others.
+    /// Wrap the payload in an `Rc` or `Arc` to share a single value instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_all(&["/health", "/healthz"], "health");
+    /// assert_eq!(tree.find("/healthz").payload, &Some("health"));
+    /// ```
+    pub fn add_all(&mut self, paths: &[&str], payload: T) {
+        if let Some((last, rest)) = paths.split_last() {
+            for path in rest {
+                self.add(*path, payload.clone());
+            }
+            self.add(*last, payload);
+        }
+    }
+
+    /// Walks the tree like `find`, returning a result that owns a clone of the
+    /// payload instead of borrowing the Tree, e.g. to keep it across an `.await`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<String>::new();
+    /// tree.add("/users/:id", "user".to_string());
+    /// let result = tree.find_owned("/users/42");
+    /// drop(tree);
+    /// assert_eq!(result.payload, Some("user".to_string()));
+    /// assert_eq!(result.params("id"), "42");
+    /// ```
+    pub fn find_owned(&self, path: impl Into<String>) -> OwnedResult<T> {
+        self.find(path).into_owned()
+    }
+
+    /// Walks the tree like `find`, returning the matched route with its
+    /// pattern, parameters and a clone of its payload, borrowing nothing from
+    /// the Tree, e.g. to be cached by path. Returns `None` when no route
+    /// matched, leaving out the fallback.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use patricia_router::{ResolvedRoute, Tree};
+    ///
+    /// let mut tree = Tree::<String>::new();
+    /// tree.add("/users/:id", "user".to_string());
+    /// tree.set_fallback("fallback".to_string());
+    /// let mut cache: HashMap<String, Option<ResolvedRoute<String>>> = HashMap::new();
+    /// for path in ["/users/42", "/users/42", "/about"] {
+    ///     cache.entry(path.to_string()).or_insert_with(|| tree.resolve(path));
+    /// }
+    /// drop(tree);
+    /// let route = cache["/users/42"].as_ref().unwrap();
+    /// assert_eq!(route.pattern(), "/users/:id");
+    /// assert_eq!(route.params("id"), "42");
+    /// assert_eq!(route.payload, "user");
+    /// assert_eq!(cache["/about"], None);
+    /// ```
+    pub fn resolve(&self, path: impl Into<String>) -> Option<ResolvedRoute<T>> {
+        self.find(path).into_resolved()
+    }
+
+    /// Takes a copy of the routes, payloads and settings of the Tree, to be put
+    /// back by `restore` when a batch of changes has to be rolled back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/about", "about");
+    /// let snapshot = tree.snapshot();
+    /// tree.add("/products", "products");
+    /// tree.restore(snapshot);
+    /// assert_eq!(tree.find("/products").payload, &None);
+    /// assert_eq!(tree.find("/about").payload, &Some("about"));
+    /// ```
+    pub fn snapshot(&self) -> TreeSnapshot<T> {
+        TreeSnapshot { tree: self.clone() }
+    }
+}
+
+impl<T: PartialEq> Tree<T> {
+    /// Adds *path* into the Tree like `add`, ignoring a path already added with
+    /// an equal *payload*. A different payload is handled like `add` does,
+    /// following the duplicate policy (see `set_duplicate_policy`).
+    ///
+    /// # Panics
+    ///
+    /// Panics with "duplicate error" when *path* was added with a different
+    /// payload and the policy is `DuplicatePolicy::Panic`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add_idempotent("/about", "about");
+    /// tree.add_idempotent("/about", "about");
+    /// assert_eq!(tree.find("/about").payload, &Some("about"));
+    /// ```
+    pub fn add_idempotent(&mut self, path: impl Into<String>, payload: T) {
+        let path = path.into();
+        if self.get_pattern(&path) == Some(&payload) {
+            return;
         }
+        self.add(path, payload);
+    }
 
-        // still path to walk, check for possible trailing slash or children nodes
-        if path_next.is_some() {
-            if 0 < key_size && has_trailing_slash(path_pos, path_size, path) {
-                return result.add(node, true);
-            }
-
-            let new_path = suffix(path, path_pos);
-            if let Some(child) = node
-                .children
-                .iter()
-                .find(|&child| child.is_named_or_catch_all() || shared_key(&new_path, &child.key))
-            {
-                result = result.add(node, false);
-                return Tree::<T>::find_internal(&new_path, result, &child, false);
+    /// Compares the routes of the Tree with the routes of *other*, e.g. the
+    /// next version of a route table, by pattern and payload. Settings such
+    /// as the fallback are not compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut current = Tree::<&str>::new();
+    /// current.add("/about", "about");
+    /// current.add("/users/:id", "user");
+    /// let mut next = Tree::<&str>::new();
+    /// next.add("/users/:id", "user v2");
+    /// next.add("/contact", "contact");
+    /// let diff = current.diff(&next);
+    /// assert_eq!(diff.added, vec!["/contact"]);
+    /// assert_eq!(diff.removed, vec!["/about"]);
+    /// assert_eq!(diff.changed, vec!["/users/:id"]);
+    /// ```
+    pub fn diff(&self, other: &Tree<T>) -> RouteDiff {
+        let routes: HashMap<String, &T> = self.routes_with_prefix("").into_iter().collect();
+        let mut others: HashMap<String, &T> = other.routes_with_prefix("").into_iter().collect();
+        let mut diff = RouteDiff::default();
+        for (pattern, payload) in routes {
+            match others.remove(&pattern) {
+                Some(other) if other != payload => diff.changed.push(pattern),
+                Some(_) => {}
+                None => diff.removed.push(pattern),
             }
-            return result;
         }
+        diff.added = others.into_keys().collect();
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+}
 
-        // key still contains characters to walk
-        if key_next.is_some() {
-            if has_trailing_slash(key_pos, key_size, &node.key) {
-                return result.add(node, true);
-            }
+impl<T> IntoIterator for Tree<T> {
+    type Item = (String, T);
+    type IntoIter = IntoIter<T>;
 
-            if node.has_catch_all(key_pos, key_size) {
-                if key_next != Some(&'*') {
-                    key_pos += 1;
-                }
-                let name = suffix(&node.key, key_pos + 1);
-                result.params.insert(name, String::new());
-                return result.add(node, true);
-            }
+    /// Consumes the Tree, yielding every payload along with its full path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use patricia_router::Tree;
+    ///
+    /// let mut tree = Tree::<&str>::new();
+    /// tree.add("/", "root");
+    /// tree.add("/about", "about");
+    /// let routes: Vec<(String, &str)> = tree.into_iter().collect();
+    /// assert_eq!(routes, vec![("/".to_string(), "root"), ("/about".to_string(), "about")]);
+    /// ```
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::<T>::new(self.root, &self.syntax)
+    }
+}
+
+impl<T, P: Into<String>> std::iter::FromIterator<(P, T)> for Tree<T> {
+    /// Builds a Tree adding every *path* and *payload* pair in order.
+    fn from_iter<I: IntoIterator<Item = (P, T)>>(iter: I) -> Self {
+        let mut tree = Tree::<T>::new();
+        for (path, payload) in iter {
+            tree.add(path, payload);
         }
-        return result;
+        tree
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Tree;
+    use super::{DuplicatePolicy, Tree};
+    use crate::error::{ConflictKind, InsertError};
+    use crate::pattern::cmp_routes;
 
     #[test]
     fn single_node() {
@@ -348,6 +3072,472 @@ mod test {
         assert_eq!(router.root.children[1].children[0].key, "/repos");
     }
 
+    #[test]
+    fn insertion_of_mixed_routes_out_of_order_rooted() {
+        let mut router = Tree::<&str>::new_rooted();
+        router.add("/user/repos", "my_repos");
+        router.add("/users/:user/repos", "user_repos");
+        router.add("/users/:user", ":user");
+        router.add("/user", "me");
+        /*
+            (empty)
+                \-/user                (:me)
+                    +-/repos         (:my_repos)
+                    \-s/:user        (:user)
+                            \-/repos (:user_repos)
+        */
+        assert_eq!(router.root.key, "");
+        assert_eq!(router.root.payload, None);
+        assert_eq!(router.root.children.len(), 1);
+        let user = &router.root.children[0];
+        assert_eq!(user.key, "/user");
+        assert_eq!(user.payload, Some("me"));
+        assert_eq!(user.children[0].key, "/repos");
+        assert_eq!(user.children[1].key, "s/:user");
+        assert_eq!(user.children[1].children[0].key, "/repos");
+
+        // compacting keeps the empty root as well
+        router.add("", "empty");
+        router.compact();
+        assert_eq!(router.root.key, "");
+        assert_eq!(router.find("").payload, &Some("empty"));
+        assert_eq!(router.find("/users/1/repos").payload, &Some("user_repos"));
+    }
+
+    #[test]
+    fn root_without_shared_prefix() {
+        let mut router = Tree::<&str>::new();
+        router.add("users/:id", "user");
+        router.add("/about", "about");
+        /*
+            (empty)
+            +-users/:id (:user)
+            \-/about    (:about)
+        */
+        assert_eq!(router.root.key, "");
+        assert_eq!(router.root.payload, None);
+        assert_eq!(router.root.children.len(), 2);
+        assert_eq!(router.root.children[0].key, "/about");
+        assert_eq!(router.root.children[1].key, "users/:id");
+    }
+
+    #[test]
+    fn root_route_then_route_without_shared_prefix() {
+        let mut router = Tree::<&str>::new();
+        router.add("/", "root");
+        router.add("abc", "abc");
+        /*
+            (empty)
+            +-abc (:abc)
+            \-/   (:root)
+        */
+        assert_eq!(router.root.key, "");
+        assert_eq!(router.root.payload, None);
+        assert_eq!(router.root.children.len(), 2);
+        assert_eq!(router.root.children[0].key, "abc");
+        assert_eq!(router.root.children[0].payload, Some("abc"));
+        assert_eq!(router.root.children[1].key, "/");
+        assert_eq!(router.root.children[1].payload, Some("root"));
+        assert!(router
+            .root
+            .children
+            .iter()
+            .all(|child| child.children.is_empty()));
+
+        assert_eq!(router.find("/").payload, &Some("root"));
+        assert_eq!(router.find("abc").payload, &Some("abc"));
+        assert_eq!(router.find("/abc").payload, &None);
+        assert_eq!(router.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn conflicts_mirror_add() {
+        let mut router = Tree::<&str>::new();
+        assert_eq!(router.would_conflict("/products"), None);
+        router.add("/products/:id", "product");
+        router.add("/products/featured", "featured");
+        router.add("/files/*path", "files");
+
+        assert_eq!(router.would_conflict("/products/:id/edit"), None);
+        assert_eq!(router.would_conflict("/products"), None);
+        assert_eq!(
+            router.would_conflict("/products/:id"),
+            Some(ConflictKind::Duplicate)
+        );
+        assert_eq!(
+            router.would_conflict("/products/featured"),
+            Some(ConflictKind::Duplicate)
+        );
+        assert_eq!(
+            router.would_conflict("/products/:slug"),
+            Some(ConflictKind::AmbiguousDynamic)
+        );
+        assert_eq!(
+            router.would_conflict("/files/*rest"),
+            Some(ConflictKind::AmbiguousDynamic)
+        );
+
+        // the dry run leaves the tree untouched, a real add still succeeds
+        router.add("/products/:id/edit", "edit");
+        assert_eq!(router.root.children.len(), 2);
+    }
+
+    #[test]
+    fn map_keeps_structure() {
+        let mut router = Tree::<&str>::new();
+        router.add("/", "root");
+        router.add("/products", "products");
+        router.add("/products/:id", "product");
+        router.add("/*filepath", "all");
+        let router = router.map(|payload| payload.len());
+
+        assert_eq!(router.root.key, "/");
+        assert_eq!(router.root.payload, Some(4));
+        assert_eq!(router.root.children.len(), 2);
+        assert_eq!(router.root.children[0].key, "products");
+        assert_eq!(router.root.children[0].payload, Some(8));
+        assert_eq!(router.root.children[0].children[0].key, "/:id");
+        assert_eq!(router.root.children[0].children[0].payload, Some(7));
+        assert_eq!(router.root.children[1].key, "*filepath");
+        assert_eq!(router.root.children[1].payload, Some(3));
+    }
+
+    #[test]
+    fn find_borrowed_skips_catch_all_param() {
+        let mut router = Tree::<&str>::new();
+        router.add("/files/:dir/*filepath", "files");
+        let result = router.find_borrowed("/files/src/main.rs");
+        assert_eq!(result.params.len(), 1);
+        assert_eq!(result.params["dir"], "src");
+        assert_eq!(result.catch_all_start, Some(11));
+    }
+
+    #[test]
+    fn find_strict_reports_rival_siblings() {
+        use crate::node::Node;
+
+        let mut router = Tree::<&str>::new();
+        router.add("/posts", "posts");
+        router.add("/posts/:id/edit", "edit");
+        assert!(router.find_strict("/posts/10/edit").unwrap().is_some());
+
+        // siblings `add` would reject, as specific as each other, a rival
+        // matching nothing is no ambiguity
+        router
+            .root
+            .insert_child(Node::new("/:slug/show", Some("show"), false));
+        let result = router.find_strict("/posts/10/edit").unwrap().unwrap();
+        assert_eq!(result.payload, &Some("edit"));
+
+        router
+            .root
+            .insert_child(Node::new("/:slug/edit", Some("slug"), false));
+        let err = router.find_strict("/posts/10/edit").unwrap_err();
+        assert_eq!(err.chosen, "/posts/:id/edit");
+        assert_eq!(err.rival, "/posts/:slug/edit");
+        assert_eq!(
+            err.to_string(),
+            "path matches both /posts/:id/edit and /posts/:slug/edit"
+        );
+        // `find` takes the first one all the same
+        assert_eq!(router.find("/posts/10/edit").payload, &Some("edit"));
+        assert!(router.find_strict("/posts/10/delete").unwrap().is_none());
+    }
+
+    #[test]
+    fn check_invariants_reports_violations() {
+        use crate::error::InvariantViolation;
+        use crate::node::Node;
+
+        let tree = || {
+            let mut router = Tree::<&str>::new();
+            router.add("/a", "a");
+            router.add("/b", "b");
+            assert_eq!(router.check_invariants(), Ok(()));
+            router
+        };
+
+        let mut router = tree();
+        router
+            .root
+            .insert_child(Node::new("abc", Some("abc"), false));
+        assert_eq!(
+            router.check_invariants(),
+            Err(InvariantViolation::SharedFirstChar("/".to_string(), 'a'))
+        );
+
+        let mut router = tree();
+        router
+            .root
+            .insert_child(Node::new(":id", Some("id"), false));
+        router
+            .root
+            .insert_child(Node::new(":name", Some("name"), false));
+        assert_eq!(
+            router.check_invariants(),
+            Err(InvariantViolation::DynamicCollision("/".to_string()))
+        );
+
+        let mut router = tree();
+        router
+            .root
+            .children
+            .push(Node::new("cde", Some("cde"), false));
+        assert_eq!(
+            router.check_invariants(),
+            Err(InvariantViolation::Unsorted("/".to_string()))
+        );
+        router.sort();
+        assert_eq!(router.check_invariants(), Ok(()));
+
+        let mut router = tree();
+        let mut chain = Node::new("c/", None, false);
+        chain.children = vec![Node::new("d", Some("d"), false)];
+        chain.sort_children();
+        router.root.insert_child(chain);
+        assert_eq!(
+            router.check_invariants(),
+            Err(InvariantViolation::Uncompacted("/c/".to_string()))
+        );
+        router.compact();
+        assert_eq!(router.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn generation_counts_changes() {
+        let mut router = Tree::<&str>::new();
+        assert_eq!(router.generation(), 0);
+        router.add("/about", "about");
+        router.add_unsorted("/products", "products");
+        assert_eq!(router.generation(), 2);
+        router.sort();
+        router.compact();
+        assert_eq!(router.generation(), 4);
+
+        // settings and lookups leave it alone
+        router.set_fallback("not found");
+        router.find("/about");
+        assert_eq!(router.generation(), 4);
+        router.get_or_insert_with("/about", || "unused");
+        assert_eq!(router.generation(), 5);
+
+        // restoring goes forward, never back to the generation of the snapshot
+        let snapshot = router.snapshot();
+        router.add("/contact", "contact");
+        router.restore(snapshot);
+        assert_eq!(router.generation(), 7);
+
+        // changing a payload keeps the segment index in use
+        router.set_segment_index(true);
+        router.replace("/about", "about us");
+        assert_eq!(router.generation(), 8);
+        assert_eq!(router.segment_index.generation, 8);
+        assert_eq!(router.map(|payload| payload.len()).generation(), 8);
+    }
+
+    #[test]
+    fn with_capacity_keeps_root_children() {
+        let mut router = Tree::<&str>::with_capacity(16);
+        assert!(router.root.children.capacity() >= 16);
+        router.add("/users", "users");
+        router.add("/posts", "posts");
+        router.add("/orders", "orders");
+        assert_eq!(router.root.key, "/");
+        assert_eq!(router.root.children.len(), 3);
+        assert_eq!(router.find("/posts").payload, &Some("posts"));
+    }
+
+    #[test]
+    fn get_or_insert_with_builds_payload_once() {
+        let mut router = Tree::<i32>::new();
+        let mut calls = 0;
+        for _ in 0..3 {
+            *router.get_or_insert_with("/products/:id", || {
+                calls += 1;
+                0
+            }) += 1;
+        }
+        // placed like add, next to existing routes
+        *router.get_or_insert_with("/products", || 10) += 1;
+
+        assert_eq!(calls, 1);
+        assert_eq!(router.find("/products/7").payload, &Some(3));
+        assert_eq!(router.find("/products").payload, &Some(11));
+        assert_eq!(router.root.key, "/products");
+    }
+
+    #[test]
+    fn from_sorted_matches_add() {
+        let mut routes = vec![
+            ("/", 1),
+            ("/*filepath", 2),
+            ("/products", 3),
+            ("/products/:id", 4),
+            ("/products/:id/edit", 5),
+            ("/products/featured", 6),
+        ];
+        let mut expected = Tree::<i32>::new();
+        for (path, payload) in routes.iter().rev() {
+            expected.add(*path, *payload);
+        }
+
+        routes.sort_by(|a, b| cmp_routes(a.0, b.0));
+        let routes: Vec<_> = routes
+            .into_iter()
+            .map(|(path, payload)| (path.to_string(), payload))
+            .collect();
+        assert_eq!(Tree::from_sorted(routes.clone()).unwrap(), expected);
+
+        // lexicographic order puts dynamic routes before static ones
+        let mut unsorted = routes;
+        unsorted.sort();
+        assert_eq!(
+            Tree::from_sorted(unsorted).unwrap_err(),
+            InsertError::Unsorted(2)
+        );
+    }
+
+    #[test]
+    fn from_sorted_rejects_conflicts() {
+        let routes = vec![("/users/:id".to_string(), 1), ("/users/:id".to_string(), 2)];
+        assert_eq!(
+            Tree::from_sorted(routes).unwrap_err(),
+            InsertError::Conflict(1, ConflictKind::Duplicate)
+        );
+
+        let routes = vec![
+            ("/users/:id".to_string(), 1),
+            ("/users/:name".to_string(), 2),
+        ];
+        assert_eq!(
+            Tree::from_sorted(routes).unwrap_err(),
+            InsertError::Conflict(1, ConflictKind::AmbiguousDynamic)
+        );
+    }
+
+    #[test]
+    fn add_idempotent_ignores_equal_payload() {
+        let mut router = Tree::<&str>::new();
+        router.add_idempotent("/products/:id", "product");
+        router.add_idempotent("/products", "products");
+        router.add_idempotent("/products/:id", "product");
+        router.add_idempotent("/products", "products");
+
+        let mut expected = Tree::<&str>::new();
+        expected.add("/products/:id", "product");
+        expected.add("/products", "products");
+        assert_eq!(router, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate error")]
+    fn add_idempotent_rejects_different_payload() {
+        let mut router = Tree::<&str>::new();
+        router.add_idempotent("/products", "products");
+        router.add_idempotent("/products", "other");
+    }
+
+    #[test]
+    fn add_idempotent_follows_duplicate_policy() {
+        let mut router = Tree::<&str>::new();
+        router.set_duplicate_policy(DuplicatePolicy::LastWins);
+        router.add_idempotent("/products", "products");
+        router.add_idempotent("/products", "other");
+        assert_eq!(router.find("/products").payload, &Some("other"));
+
+        router.set_duplicate_policy(DuplicatePolicy::FirstWins);
+        router.add_idempotent("/products", "products");
+        assert_eq!(router.find("/products").payload, &Some("other"));
+    }
+
+    #[test]
+    fn boosted_sibling_goes_first() {
+        let mut router = Tree::<&str>::new();
+        router.add("/", "root");
+        router.add("/products", "products");
+        router.add_with_priority("/ab", "ab", 1);
+        assert_eq!(router.root.children[0].key, "ab");
+        assert_eq!(router.root.children[1].key, "products");
+
+        // the boost stays with both parts when the node is split
+        router.add("/ac", "ac");
+        assert_eq!(router.root.children[0].key, "a");
+        assert_eq!(router.root.children[0].boost, 1);
+        assert_eq!(router.root.children[0].children[0].key, "b");
+        assert_eq!(router.root.children[0].children[0].boost, 1);
+        assert_eq!(router.find("/ab").payload, &Some("ab"));
+    }
+
+    #[test]
+    fn parent_then_child() {
+        let mut router = Tree::<&str>::new();
+        router.add("/a", "a");
+        router.add("/a/b", "b");
+        assert_eq!(router.root.key, "/a");
+        assert_eq!(router.root.payload, Some("a"));
+        assert_eq!(router.root.children[0].key, "/b");
+        assert_eq!(router.root.children[0].payload, Some("b"));
+    }
+
+    #[test]
+    fn child_then_parent() {
+        let mut router = Tree::<&str>::new();
+        router.add("/a/b", "b");
+        router.add("/a/c", "c");
+        // lands on the payload-less node created by the split
+        router.add("/a/", "a");
+        assert_eq!(router.root.key, "/a/");
+        assert_eq!(router.root.payload, Some("a"));
+        assert_eq!(router.root.children.len(), 2);
+
+        router.add("/a", "a");
+        assert_eq!(router.root.key, "/a");
+        assert_eq!(router.root.children[0].key, "/");
+        assert_eq!(router.root.children[0].payload, Some("a"));
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate error")]
+    fn same_twice() {
+        let mut router = Tree::<&str>::new();
+        router.add("/a", "a");
+        router.add("/a", "a");
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate error")]
+    fn same_twice_after_split() {
+        let mut router = Tree::<&str>::new();
+        router.add("/a/b", "b");
+        router.add("/a", "a");
+        router.add("/a/b", "b");
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate error")]
+    fn same_named_parameter_twice_after_split() {
+        let mut router = Tree::<&str>::new();
+        router.add("/products/:id", "product");
+        router.add("/products/featured", "featured");
+        router.add("/products/:id", "product");
+    }
+
+    #[test]
+    #[should_panic(expected = "shared key error")]
+    fn named_parameters_with_different_names() {
+        let mut router = Tree::<&str>::new();
+        router.add("/users/:id", "user");
+        router.add("/users/:name", "user");
+    }
+
+    #[test]
+    #[should_panic(expected = "shared key error")]
+    fn catch_all_parameters_with_different_names() {
+        let mut router = Tree::<&str>::new();
+        router.add("/files/*path", "files");
+        router.add("/files/*rest", "files");
+    }
+
     #[test]
     fn dealing_with_unicode1() {
         let mut router = Tree::<&str>::new();
@@ -382,6 +3572,24 @@ mod test {
         assert_eq!(router.root.children[0].children[1].key, "う");
     }
 
+    #[test]
+    fn dealing_with_unicode3() {
+        let mut router = Tree::<&str>::new();
+        router.add("/", "root");
+        router.add("/あいう", "aiu");
+        router.add("/あい", "ai");
+        /*
+            /          (:root)
+            \-あい      (:ai)
+                \-う    (:aiu)
+        */
+        assert_eq!(router.root.children.len(), 1);
+        assert_eq!(router.root.children[0].key, "あい");
+        assert_eq!(router.root.children[0].payload, Some("ai"));
+        assert_eq!(router.root.children[0].children.len(), 1);
+        assert_eq!(router.root.children[0].children[0].key, "う");
+    }
+
     #[test]
     fn dealing_with_catch_all_and_named_parameters() {
         let mut router = Tree::<&str>::new();
@@ -412,4 +3620,27 @@ mod test {
 
         assert_eq!(router.root.children[1].key, "*filepath");
     }
+
+    #[test]
+    fn add_static_borrows_keys() {
+        use std::borrow::Cow;
+
+        let mut router = Tree::<&str>::new();
+        router.add_static("/products", "products");
+        router.add_static("/products/:id", "product");
+        router.add_static("/about", "about");
+        let keys = [
+            &router.root.key,
+            &router.root.children[0].key,
+            &router.root.children[0].children[0].key,
+            &router.root.children[1].key,
+        ];
+        assert_eq!(keys, ["/", "products", "/:id", "about"]);
+        assert!(keys.iter().all(|key| matches!(key, Cow::Borrowed(_))));
+
+        // owned paths keep allocating their keys
+        router.add("/contact", "contact");
+        assert!(matches!(router.root.children[1].key, Cow::Owned(_)));
+        assert_eq!(router.root.children[1].key, "contact");
+    }
 }
@@ -1,36 +1,96 @@
-pub(crate) fn detect_param_size(key: &str, old_pos: usize) -> usize {
-    let rest_key = key.chars().skip(old_pos).collect::<String>();
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
 
-    if let Some(pos) = rest_key.chars().position(|ch| ch == '/') {
-        return old_pos + pos;
+use crate::tree::UNNAMED_CATCH_ALL;
+
+/// Detects whether *ch* ends a segment: `/` or one of the *extra* separators
+/// of the Tree (e.g. `.`).
+pub(crate) fn is_separator(ch: char, extra: &[char]) -> bool {
+    ch == '/' || extra.contains(&ch)
+}
+
+/// Returns the position (in characters) where the parameter starting at
+/// *old_pos* ends: the next separator (`/` or one of *extra*) or the end of
+/// *key*.
+pub(crate) fn detect_param_size(key: &str, old_pos: usize, extra: &[char]) -> usize {
+    let mut rest_key = key.chars().skip(old_pos);
+    match rest_key.position(|ch| is_separator(ch, extra)) {
+        Some(pos) => old_pos + pos,
+        None => old_pos.max(key.chars().count()),
     }
-    old_pos + rest_key.len()
 }
 
-pub(crate) fn same_first_char(a: &str, b: &str) -> bool {
+/// Detects whether *a* and *b* start with the same kind of parameter
+/// but cannot share a node:
+///
+/// - Named parameters (`:`) with different names.
+/// - Catch all parameters (`*`) with different names.
+pub(crate) fn conflicting_dynamic(a: &str, b: &str, extra: &[char]) -> bool {
     let a_first = a.chars().next();
     let b_first = b.chars().next();
-    if a_first == Some(':') && b_first == Some(':') && !same_key(a, b) {
-        panic!("shared key error")
+    if a_first != b_first {
+        return false;
+    }
+    match a_first {
+        Some(':') => !same_key(a, b, extra) || !same_key(b, a, extra),
+        Some('*') if is_segment_glob(a) && is_segment_glob(b) => {
+            !same_key(a, b, extra) || !same_key(b, a, extra)
+        }
+        Some('*') => a != b,
+        _ => false,
     }
-    a_first == b_first
+}
+
+/// Detects whether *key* starts with a catch all parameter limited to a
+/// single segment (`*?`), which ends at the next separator like a named one.
+pub(crate) fn is_segment_glob(key: &str) -> bool {
+    key.starts_with("*?")
+}
+
+/// Detects whether *key* starts with a catch all parameter spanning the rest
+/// of the path (`*` not followed by `?`).
+pub(crate) fn is_greedy_glob(key: &str) -> bool {
+    key.starts_with('*') && !is_segment_glob(key)
+}
+
+/// Detects whether splitting at the end of *shared* would cut through the
+/// name of a named (`:`) or catch all (`*`) parameter, which happens when the
+/// last segment of *shared* holds a marker and either *key* or *path*
+/// continues that segment.
+pub(crate) fn splits_dynamic(
+    shared: &str,
+    key_next: Option<char>,
+    path_next: Option<char>,
+    extra: &[char],
+) -> bool {
+    let continues = |ch: Option<char>| ch.is_some_and(|ch| !is_separator(ch, extra));
+    let segment = shared
+        .rsplit(|ch| is_separator(ch, extra))
+        .next()
+        .unwrap_or("");
+    (segment.contains(':') || segment.contains('*'))
+        && (continues(key_next) || continues(path_next))
 }
 
 /// Compares *path* against *key* for differences until the
 /// following criteria is met:
 ///
 /// - End of *path* or *key* is reached.
-/// - A separator (`/`) is found.
+/// - A separator (`/` or one of *extra*) is found.
 /// - A character between *path* or *key* differs
-fn same_key(path: &str, key: &str) -> bool {
+fn same_key(path: &str, key: &str, extra: &[char]) -> bool {
     let mut it = path
         .chars()
         .zip(key.chars())
-        .skip_while(|&(p, k)| (p != '/') && (k != '/') && (p == k));
+        .skip_while(|&(p, k)| !is_separator(p, extra) && !is_separator(k, extra) && (p == k));
 
     match it.next() {
-        Some((p, _k)) => p == '/',
-        None => path.len() < key.len(),
+        Some((p, _k)) => is_separator(p, extra),
+        None => {
+            let next = path.chars().nth(key.chars().count());
+            next.map_or(true, |ch| is_separator(ch, extra))
+        }
     }
 }
 
@@ -75,10 +135,17 @@ pub(crate) fn substring(target: &str, begin: usize, end: usize) -> String {
     target
         .chars()
         .skip(begin)
-        .take(end - begin)
+        .take(end.saturating_sub(begin))
         .collect::<String>()
 }
 
+/// Borrows the characters of *target* between *begin* and *end*, like `substring`.
+pub(crate) fn slice(target: &str, begin: usize, end: usize) -> &str {
+    let start = byte_offset(target, begin);
+    let stop = start + byte_offset(&target[start..], end.saturating_sub(begin));
+    &target[start..stop]
+}
+
 pub(crate) fn prefix(target: &str, end: usize) -> String {
     target.chars().take(end).collect::<String>()
 }
@@ -87,26 +154,261 @@ pub(crate) fn suffix(target: &str, begin: usize) -> String {
     target.chars().skip(begin).collect::<String>()
 }
 
-pub(crate) fn has_trailing_slash(end: usize, size: usize, path: &str) -> bool {
-    end + 1 == size && path.chars().nth(end) == Some('/')
+/// Returns the bytes of *target* in *range*, borrowing them when *target*
+/// borrows a static string rather than copying them.
+// the variant of *target* matters, so it can't be taken as a &str
+#[allow(clippy::ptr_arg)]
+pub(crate) fn cow_slice(target: &Cow<'static, str>, range: Range<usize>) -> Cow<'static, str> {
+    match target {
+        Cow::Borrowed(target) => {
+            let target: &'static str = target;
+            Cow::Borrowed(&target[range])
+        }
+        Cow::Owned(target) => Cow::Owned(target[range].to_string()),
+    }
+}
+
+/// Converts the character position *pos* into a byte offset inside *target*.
+pub(crate) fn byte_offset(target: &str, pos: usize) -> usize {
+    target
+        .char_indices()
+        .nth(pos)
+        .map_or(target.len(), |(offset, _)| offset)
+}
+
+/// Splits the raw name of a named parameter (e.g. `status{draft|published}`)
+/// into its name and the values it is constrained to, if any.
+pub(crate) fn split_constraint(raw: &str) -> (&str, Option<Vec<&str>>) {
+    match raw.find('{') {
+        Some(open) if raw.ends_with('}') => {
+            let values = raw[open + 1..raw.len() - 1].split('|').collect();
+            (&raw[..open], Some(values))
+        }
+        _ => (raw, None),
+    }
+}
+
+/// Splits the raw name of a named parameter ending with a default value (e.g.
+/// `page=1`) into the rest of the raw name and the default value, if any.
+pub(crate) fn split_default(raw: &str) -> (&str, Option<&str>) {
+    // a `=` among the allowed values (e.g. `q{a=b|c}`) doesn't start the default
+    let start = match raw.find(['{', '=']) {
+        Some(open) if raw[open..].starts_with('{') => raw[open..]
+            .find('}')
+            .map_or(raw.len(), |close| open + close + 1),
+        _ => 0,
+    };
+    match raw[start..].find('=') {
+        Some(pos) => (&raw[..start + pos], Some(&raw[start + pos + 1..])),
+        None => (raw, None),
+    }
+}
+
+/// Returns the name and default value of the named parameter starting *rest*
+/// (the end of a key), optionally after a separator, when the parameter has a
+/// default value and ends *rest*.
+pub(crate) fn defaulted_param(rest: &str) -> Option<(&str, &str)> {
+    let raw = rest.strip_prefix('/').unwrap_or(rest).strip_prefix(':')?;
+    if raw.contains('/') {
+        return None;
+    }
+    let (raw, default) = split_default(raw);
+    Some((split_constraint(split_format(raw).0).0, default?))
+}
+
+/// Splits the raw name of a named parameter followed by an optional extension
+/// (e.g. `id.:format?`) into the raw name of the parameter and the name of the
+/// parameter capturing the extension, if any.
+pub(crate) fn split_format(raw: &str) -> (&str, Option<&str>) {
+    match raw.strip_suffix('?').and_then(|rest| rest.split_once(".:")) {
+        Some((raw, format)) => (raw, Some(format)),
+        None => (raw, None),
+    }
+}
+
+/// Splits *value* at its last `.` into the value and its extension, which is
+/// empty when *value* holds no `.`.
+pub(crate) fn split_extension(value: &str) -> (&str, &str) {
+    match value.rfind('.') {
+        Some(dot) => (&value[..dot], &value[dot + 1..]),
+        None => (value, ""),
+    }
+}
+
+/// Collects the values every constrained named parameter in *key* is allowed
+/// to match, keyed by the name of the parameter.
+pub(crate) fn parse_constraints(key: &str) -> Vec<(String, Vec<String>)> {
+    key.split('/')
+        .filter_map(|segment| segment.find(':').map(|pos| &segment[pos + 1..]))
+        .filter_map(
+            |raw| match split_constraint(split_format(split_default(raw).0).0) {
+                (name, Some(values)) => Some((
+                    name.to_string(),
+                    values.into_iter().map(|value| value.to_string()).collect(),
+                )),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// Extracts the name of the catch all parameter starting at *begin* in *key*,
+/// falling back to `UNNAMED_CATCH_ALL` when the `*` is not followed by a name.
+pub(crate) fn catch_all_name(key: &str, begin: usize) -> &str {
+    let name = &key[byte_offset(key, begin)..];
+    if name.is_empty() {
+        return UNNAMED_CATCH_ALL;
+    }
+    name
+}
+
+/// Builds a path from *pattern*, replacing every named (`:`) and catch all
+/// (`*`) parameter by its value in *params*, or by the default value of a named
+/// parameter. Parameters end at `/` or at one of the *extra* separators.
+/// Returns `None` when a value is missing.
+pub(crate) fn fill_pattern(
+    pattern: &str,
+    params: &HashMap<String, String>,
+    extra: &[char],
+) -> Option<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut path = String::with_capacity(pattern.len());
+    let mut pos = 0;
+    while let Some(&ch) = chars.get(pos) {
+        if ch == '*' && chars.get(pos + 1) == Some(&'?') {
+            let end = detect_param_size(pattern, pos, extra);
+            let name = match slice(pattern, pos + 2, end) {
+                "" => UNNAMED_CATCH_ALL,
+                name => name,
+            };
+            path.push_str(params.get(name)?);
+            pos = end;
+        } else if ch == '*' {
+            let name = catch_all_name(pattern, pos + 1);
+            path.push_str(params.get(name)?);
+            return Some(path);
+        } else if ch == ':' {
+            let end = detect_param_size(pattern, pos, extra);
+            let raw = substring(pattern, pos + 1, end);
+            let (raw, default) = split_default(&raw);
+            let (raw, format) = split_format(raw);
+            let (name, values) = split_constraint(raw);
+            let value = params.get(name).map(String::as_str).or(default)?;
+            if values.is_some_and(|values| !values.contains(&value)) {
+                return None;
+            }
+            path.push_str(value);
+            let extension = format.and_then(|format| params.get(format));
+            if let Some(extension) = extension.filter(|extension| !extension.is_empty()) {
+                path.push('.');
+                path.push_str(extension);
+            }
+            pos = end;
+        } else {
+            path.push(ch);
+            pos += 1;
+        }
+    }
+    Some(path)
+}
+
+/// Returns *value* in Unicode Normalization Form C, borrowing it when it
+/// already is (e.g. ASCII).
+#[cfg(feature = "nfc")]
+pub(crate) fn to_nfc(value: &str) -> Cow<'_, str> {
+    use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+    if is_nfc(value) {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(value.nfc().collect())
+    }
+}
+
+/// Decodes the `%XX` sequences of *value*. Sequences that are not followed by
+/// two hex digits are kept as is, and so is *value* when the decoded bytes are
+/// not valid UTF-8.
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        // from_str_radix alone would accept a sign (e.g. `%+F`)
+        let hex = bytes
+            .get(pos + 1..pos + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[pos], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                pos += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                pos += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| value.to_string())
+}
+
+/// Detects whether only a separator (`/` or one of *extra*) is left of
+/// *path* at *end*, *size* being the length of *path* in characters.
+pub(crate) fn has_trailing_slash(end: usize, size: usize, path: &str, extra: &[char]) -> bool {
+    end + 1 == size
+        && path
+            .chars()
+            .nth(end)
+            .is_some_and(|ch| is_separator(ch, extra))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_detect_param_size() {
+        assert_eq!(detect_param_size("/:id/edit", 1, &[]), 4);
+        assert_eq!(detect_param_size("/:id", 1, &[]), 4);
+        assert_eq!(detect_param_size("/:あいう", 1, &[]), 5);
+        assert_eq!(detect_param_size("/:", 1, &[]), 2);
+        // positions past the end stay in place
+        assert_eq!(detect_param_size("/:", 5, &[]), 5);
+        // extra separators end parameters as well
+        assert_eq!(detect_param_size("/:id.json", 1, &['.']), 4);
+        assert_eq!(detect_param_size("/:id.json", 1, &[]), 9);
+    }
+
     #[test]
     fn test_same_key() {
         // mismatch at 1st character
-        assert!(!same_key("foo", "bar"));
+        assert!(!same_key("foo", "bar", &[]));
         // only foo is compared
-        assert!(same_key("foo/bar", "foo/baz"));
+        assert!(same_key("foo/bar", "foo/baz", &[]));
         // zip is shorter
-        assert!(!same_key("zipcode", "zip"));
-        assert!(same_key("zip", "zipcode"));
-        assert!(!same_key("s", "/new"));
-        assert!(same_key("foo/bar", "fooa/baz"));
-        assert!(!same_key("fooa/bar", "foo/baz"));
+        assert!(!same_key("zipcode", "zip", &[]));
+        assert!(same_key("zip", "zipcode", &[]));
+        assert!(!same_key("s", "/new", &[]));
+        assert!(same_key("foo/bar", "fooa/baz", &[]));
+        assert!(!same_key("fooa/bar", "foo/baz", &[]));
+        // identical keys, or path continuing past key with a separator
+        assert!(same_key(":id", ":id", &[]));
+        assert!(same_key(":id/edit", ":id", &[]));
+    }
+
+    #[test]
+    fn test_conflicting_dynamic() {
+        assert!(!conflicting_dynamic("abc", "abd", &[]));
+        assert!(!conflicting_dynamic(":id", "*id", &[]));
+        assert!(!conflicting_dynamic(":id/edit", ":id", &[]));
+        assert!(conflicting_dynamic(":id", ":slug", &[]));
+        assert!(conflicting_dynamic(":i", ":id", &[]));
+        assert!(!conflicting_dynamic("*path", "*path", &[]));
+        assert!(conflicting_dynamic("*path", "*rest", &[]));
+        assert!(!conflicting_dynamic("*?dir/a", "*?dir/b", &[]));
+        assert!(conflicting_dynamic("*?dir", "*?name", &[]));
+        assert!(conflicting_dynamic("*?dir", "*dir", &[]));
     }
 
     #[test]
@@ -125,10 +427,173 @@ mod test {
         assert!(shared_key("search", "search/*extra"));
     }
 
+    #[test]
+    fn test_splits_dynamic() {
+        // no marker in the last segment
+        assert!(!splits_dynamic("/products/", Some('f'), Some(':'), &[]));
+        // split right after the parameter name
+        assert!(!splits_dynamic("/:id", Some('/'), None, &[]));
+        assert!(!splits_dynamic("/:id", None, Some('/'), &[]));
+        // split inside the parameter name
+        assert!(splits_dynamic("/:i", Some('d'), Some('x'), &[]));
+        assert!(splits_dynamic("/:id", None, Some('x'), &[]));
+        assert!(splits_dynamic("files/*", Some('p'), Some('r'), &[]));
+        // extra separators end segments as well
+        assert!(!splits_dynamic("/:id.", Some('j'), Some('x'), &['.']));
+        assert!(splits_dynamic("/:id.", Some('j'), Some('x'), &[]));
+    }
+
+    #[test]
+    fn test_glob_kinds() {
+        assert!(is_greedy_glob("*path"));
+        assert!(is_greedy_glob("*"));
+        assert!(!is_greedy_glob("*?dir/edit"));
+        assert!(is_segment_glob("*?dir/edit"));
+        assert!(!is_segment_glob(":id"));
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        assert_eq!(byte_offset("abcde", 2), 2);
+        assert_eq!(byte_offset("あいうえお", 2), 6);
+        assert_eq!(byte_offset("あいうえお", 5), 15);
+    }
+
+    #[test]
+    fn test_split_constraint() {
+        assert_eq!(split_constraint("id"), ("id", None));
+        assert_eq!(
+            split_constraint("status{draft|published}"),
+            ("status", Some(vec!["draft", "published"]))
+        );
+        // unterminated constraints are part of the name
+        assert_eq!(split_constraint("status{draft"), ("status{draft", None));
+    }
+
+    #[test]
+    fn test_split_default() {
+        assert_eq!(split_default("page"), ("page", None));
+        assert_eq!(split_default("page=1"), ("page", Some("1")));
+        assert_eq!(split_default("page{1|2}="), ("page{1|2}", Some("")));
+        assert_eq!(split_default("q{a=b|c}"), ("q{a=b|c}", None));
+        assert_eq!(split_default("q{a=b|c}=c"), ("q{a=b|c}", Some("c")));
+        assert_eq!(split_default("q=x{y}"), ("q", Some("x{y}")));
+        assert_eq!(defaulted_param(":page{1|2}=1"), Some(("page", "1")));
+        assert_eq!(defaulted_param("/:page=1"), Some(("page", "1")));
+        assert_eq!(defaulted_param(":page"), None);
+        assert_eq!(defaulted_param(":page=1/edit"), None);
+        assert_eq!(defaulted_param("page=1"), None);
+    }
+
+    #[test]
+    fn test_split_format() {
+        assert_eq!(split_format("id"), ("id", None));
+        assert_eq!(split_format("id.:format?"), ("id", Some("format")));
+        assert_eq!(
+            split_format("id{1|2}.:format?"),
+            ("id{1|2}", Some("format"))
+        );
+        // the extension is only optional with a trailing ?
+        assert_eq!(split_format("id.:format"), ("id.:format", None));
+    }
+
+    #[test]
+    fn test_split_extension() {
+        assert_eq!(split_extension("10.json"), ("10", "json"));
+        assert_eq!(split_extension("v1.2.json"), ("v1.2", "json"));
+        assert_eq!(split_extension("10"), ("10", ""));
+        assert_eq!(split_extension("10."), ("10", ""));
+    }
+
+    #[test]
+    fn test_parse_constraints() {
+        assert!(parse_constraints("/posts/:id").is_empty());
+        assert_eq!(
+            parse_constraints("/posts/:status{draft|published}/:id/x:fmt{json}"),
+            vec![
+                (
+                    "status".to_string(),
+                    vec!["draft".to_string(), "published".to_string()]
+                ),
+                ("fmt".to_string(), vec!["json".to_string()]),
+            ]
+        );
+        assert_eq!(
+            parse_constraints("/posts/:id{1|2}.:format?"),
+            vec![("id".to_string(), vec!["1".to_string(), "2".to_string()])]
+        );
+    }
+
+    #[test]
+    fn test_catch_all_name() {
+        assert_eq!(catch_all_name("/*filepath", 2), "filepath");
+        assert_eq!(catch_all_name("/*", 2), UNNAMED_CATCH_ALL);
+    }
+
+    #[test]
+    fn test_fill_pattern() {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), "10".to_string());
+        params.insert("filepath".to_string(), "a/b.png".to_string());
+        assert_eq!(
+            fill_pattern("/products", &params, &[]).unwrap(),
+            "/products"
+        );
+        assert_eq!(
+            fill_pattern("/products/:id/edit", &params, &[]).unwrap(),
+            "/products/10/edit"
+        );
+        assert_eq!(
+            fill_pattern("/:id/*filepath", &params, &[]).unwrap(),
+            "/10/a/b.png"
+        );
+        assert_eq!(fill_pattern("/products/:slug", &params, &[]), None);
+        assert_eq!(
+            fill_pattern("/products/:id{10|20}", &params, &[]).unwrap(),
+            "/products/10"
+        );
+        assert_eq!(fill_pattern("/products/:id{1|2}", &params, &[]), None);
+        assert_eq!(fill_pattern("/*", &params, &[]), None);
+        assert_eq!(
+            fill_pattern("/*?id/*filepath", &params, &[]).unwrap(),
+            "/10/a/b.png"
+        );
+        assert_eq!(
+            fill_pattern("/posts/:id.:format?", &params, &[]).unwrap(),
+            "/posts/10"
+        );
+        params.insert("format".to_string(), "json".to_string());
+        assert_eq!(
+            fill_pattern("/posts/:id.:format?", &params, &[]).unwrap(),
+            "/posts/10.json"
+        );
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%2Fb"), "a/b");
+        assert_eq!(percent_decode("%E3%81%82"), "あ");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+        assert_eq!(percent_decode("%FF"), "%FF");
+        assert_eq!(percent_decode("%+F%-0"), "%+F%-0");
+    }
+
     #[test]
     fn test_substring() {
         assert_eq!(substring("abcde", 1, 3), "bc");
         assert_eq!(substring("あいうえお", 2, 4), "うえ");
+        assert_eq!(substring("abcde", 3, 1), "");
+        assert_eq!(substring("abcde", 7, 9), "");
+    }
+
+    #[test]
+    fn test_slice() {
+        assert_eq!(slice("abcde", 1, 3), "bc");
+        assert_eq!(slice("あいうえお", 2, 4), "うえ");
+        assert_eq!(slice("あいうえお", 3, 9), "えお");
+        assert_eq!(slice("abcde", 3, 1), "");
+        assert_eq!(slice("abcde", 7, 9), "");
     }
 
     #[test]
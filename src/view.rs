@@ -0,0 +1,28 @@
+use crate::node::{Kind, Node};
+
+/// Read-only snapshot of a node, as returned by `Tree::debug_node`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeView<'a> {
+    /// Part of the pattern held by the node.
+    pub key: &'a str,
+    /// Kind of the first parameter in the key, if any.
+    pub kind: Kind,
+    /// Priority used to order the node among its siblings.
+    pub priority: i32,
+    /// Whether a route ends at the node.
+    pub has_payload: bool,
+    /// Number of direct children of the node.
+    pub children: usize,
+}
+
+impl<'a> NodeView<'a> {
+    pub(crate) fn new<T>(node: &'a Node<T>) -> Self {
+        Self {
+            key: &node.key,
+            kind: node.kind().clone(),
+            priority: node.priority(),
+            has_payload: node.payload.is_some(),
+            children: node.children.len(),
+        }
+    }
+}
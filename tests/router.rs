@@ -1,4 +1,10 @@
-use patricia_router::Router;
+use std::collections::HashMap;
+
+use patricia_router::{
+    cmp_routes, validate_pattern, ConflictKind, DuplicatePolicy, FindError, FromParams, Kind,
+    MatchPolicy, Matched, NodeView, ParamError, ParamSpec, PartialMatch, PatternError,
+    ResolvedRoute, Router, TrailingSlash, UNNAMED_CATCH_ALL,
+};
 
 #[test]
 fn single_node() {
@@ -12,7 +18,7 @@ fn single_node() {
 fn key_and_path_matches() {
     let mut router = Router::<&str>::new();
     router.add("/about", "about");
-    let mut result = router.find("/about");
+    let result = router.find("/about");
     assert_eq!(result.key(), "/about");
     assert_eq!(result.payload, &Some("about"));
 }
@@ -24,7 +30,7 @@ fn nodes_with_shared_parent() {
     router.add("/abc", "abc");
     router.add("/axyz", "axyz");
 
-    let mut result = router.find("/abc");
+    let result = router.find("/abc");
     assert_eq!(result.key(), "/abc");
     assert_eq!(result.payload, &Some("abc"));
 }
@@ -35,7 +41,7 @@ fn matching_path_across_separator() {
     router.add("/products", "products");
     router.add("/product/new", "product_new");
 
-    let mut result = router.find("/products");
+    let result = router.find("/products");
     assert_eq!(result.key(), "/products");
     assert_eq!(result.payload, &Some("products"));
 }
@@ -49,7 +55,7 @@ fn matching_path_across_parents() {
     router.add("/blog/tags", "tags");
     router.add("/blog/articles", "articles");
 
-    let mut result = router.find("/blog/tags/");
+    let result = router.find("/blog/tags/");
     assert_eq!(result.key(), "/blog/tags");
     assert_eq!(result.payload, &Some("tags"));
 }
@@ -61,7 +67,7 @@ fn unicode_nodes_with_shared_parent() {
     router.add("/あいう", "aiu");
     router.add("/あいかきくけこ", "aikakikukeko");
 
-    let mut result = router.find("/あいかきくけこ/");
+    let result = router.find("/あいかきくけこ/");
     assert_eq!(result.key(), "/あいかきくけこ");
 }
 
@@ -87,6 +93,42 @@ fn catch_all_in_parameters() {
     assert_eq!(result.params("filepath"), "src/file.png");
 }
 
+#[test]
+fn catch_all_leaves_out_trailing_slash() {
+    let mut router = Router::<&str>::new();
+    router.add("/*filepath", "all");
+    router.add("/about", "about");
+    router.add("/files/*", "files");
+    router.add_excluding("/php/*rest", "php", &[".php"]);
+    router.set_trailing_slash(TrailingSlash::RedirectPermanent);
+
+    for path in ["/src/file.png", "/src/file.png/"] {
+        let result = router.find_with_ranges(path);
+        assert_eq!(result.params("filepath"), "src/file.png", "{}", path);
+        assert_eq!(result.catch_all(path), Some("src/file.png"), "{}", path);
+        assert_eq!(result.param_range("filepath"), Some(1..13), "{}", path);
+    }
+    assert_eq!(router.find("/src/file.png").redirect_status(), None);
+    assert_eq!(router.find("/src/file.png/").redirect_status(), Some(308));
+
+    // only the last slash is left out
+    assert_eq!(router.find("/files/a//").params(UNNAMED_CATCH_ALL), "a/");
+    assert_eq!(router.find("/files//").params(UNNAMED_CATCH_ALL), "");
+    assert_eq!(router.find("/files/").params(UNNAMED_CATCH_ALL), "");
+    assert_eq!(router.find("/files/").redirect_status(), None);
+
+    // suffixes are checked without it as well
+    assert_eq!(router.find("/php/index.php/").payload, &None);
+    assert_eq!(router.find("/php/index.html/").payload, &Some("php"));
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("::src::*path", "src");
+    let path = "::src::a::b::";
+    let result = router.find(path);
+    assert_eq!(result.params("path"), "a::b");
+    assert_eq!(result.catch_all(path), Some("a::b"));
+}
+
 #[test]
 fn returns_optional_catch_all_after_slash() {
     let mut router = Router::<&str>::new();
@@ -97,6 +139,63 @@ fn returns_optional_catch_all_after_slash() {
     assert_eq!(result.params("extra"), "");
 }
 
+#[test]
+fn returns_optional_catch_all_at_root_and_below() {
+    let mut router = Router::<&str>::new();
+    router.add("/*files", "files");
+    for path in &["", "/"] {
+        let result = router.find(*path);
+        assert_eq!(result.payload, &Some("files"));
+        assert_eq!(result.params("files"), "");
+    }
+
+    // the catch all ends up in a child once the root gets split
+    router.add("/about", "about");
+    router.add("/search/*extra", "extra");
+    router.add("/search/advanced", "advanced");
+    for (path, name) in &[
+        ("", "files"),
+        ("/", "files"),
+        ("/search", "extra"),
+        ("/search/", "extra"),
+    ] {
+        let result = router.find(*path);
+        assert_eq!(result.params(*name), "", "{}", path);
+    }
+    assert_eq!(router.find("/").payload, &Some("files"));
+    assert_eq!(router.find("/search").payload, &Some("extra"));
+    assert_eq!(router.find("/search/").catch_all("/search/"), Some(""));
+}
+
+#[test]
+fn returns_optional_catch_all_behind_separator() {
+    let mut router = Router::<&str>::new();
+    router.add("/search/*extra", "extra");
+    router.add("/searching", "searching");
+    // the catch all sits in a `/*extra` child of `/search`
+    let result = router.find("/search");
+    assert_eq!(result.payload, &Some("extra"));
+    assert_eq!(result.params("extra"), "");
+
+    // then below a `/` child, once another route shares the separator
+    router.add("/search/advanced", "advanced");
+    let result = router.find("/search");
+    assert_eq!(result.payload, &Some("extra"));
+    assert_eq!(result.params("extra"), "");
+}
+
+#[test]
+fn does_not_return_nodes_without_payload() {
+    let mut router = Router::<&str>::new();
+    router.add("/a/b", "b");
+    router.add("/a/c", "c");
+    for path in &["/a", "/a/"] {
+        let result = router.find(*path);
+        assert_eq!(result.payload, &None);
+        assert_eq!(result.key(), "", "{}", path);
+    }
+}
+
 #[test]
 fn returns_optional_catch_all_by_globbing() {
     let mut router = Router::<&str>::new();
@@ -112,7 +211,7 @@ fn does_not_when_catch_all_is_not_full_match() {
     router.add("/", "root");
     router.add("/serach/public/*query", "search");
 
-    let mut result = router.find("/search");
+    let result = router.find("/search");
     assert_eq!(result.key(), "");
 }
 
@@ -121,7 +220,7 @@ fn does_not_when_path_search_has_been_exhausted() {
     let mut router = Router::<&str>::new();
     router.add("/members/*training", "members_catch_all");
 
-    let mut result = router.find("/members2");
+    let result = router.find("/members2");
     assert_eq!(result.key(), "");
 }
 
@@ -131,17 +230,38 @@ fn does_prefer_specific_path_over_catch_all_if_both_are_present() {
     router.add("/members", "members");
     router.add("/members/*training", "members_catch_all");
 
-    let mut result = router.find("/members");
+    let result = router.find("/members");
     assert_eq!(result.key(), "/members");
 }
 
+#[test]
+fn does_prefer_catch_all_when_glob_first() {
+    let mut router = Router::<&str>::new();
+    router.add("/members", "members");
+    router.add("/members/*training", "members_catch_all");
+    router.add("/members/:id", "member");
+    router.set_match_policy(MatchPolicy::GlobFirst);
+
+    let result = router.find("/members");
+    assert_eq!(result.key(), "/members/*training");
+    assert_eq!(result.params("training"), "");
+
+    let result = router.find("/members/42");
+    assert_eq!(result.payload, &Some("members_catch_all"));
+    assert_eq!(result.params("training"), "42");
+
+    router.set_match_policy(MatchPolicy::SpecificFirst);
+    assert_eq!(router.find("/members").payload, &Some("members"));
+    assert_eq!(router.find("/members/42").payload, &Some("member"));
+}
+
 #[test]
 fn does_prefer_catch_all_over_specific_key_with_partially_shared_key() {
     let mut router = Router::<&str>::new();
     router.add("/orders/*anything", "orders_catch_all");
     router.add("/orders/closed", "closed_orders");
 
-    let mut result = router.find("/orders/cancelled");
+    let result = router.find("/orders/cancelled");
     assert_eq!(result.key(), "/orders/*anything");
     assert_eq!(result.params("anything"), "cancelled");
 }
@@ -154,7 +274,7 @@ fn dealing_with_named_parameters() {
     router.add("/products/:id", "product");
     router.add("/products/:id/edit", "edit");
 
-    let mut result = router.find("/products/10");
+    let result = router.find("/products/10");
     assert_eq!(result.key(), "/products/:id");
     assert_eq!(result.payload, &Some("product"));
 }
@@ -170,6 +290,26 @@ fn does_not_partial_matchin_path() {
     assert_eq!(result.payload, &None);
 }
 
+#[test]
+fn longest_prefix_returns_partial_match() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/products", "products");
+    router.add("/products/:id/edit", "edit");
+    router.set_partial_match(PartialMatch::LongestPrefix);
+
+    let result = router.find("/products/10");
+    assert_eq!(result.payload, &Some("products"));
+    assert_eq!(result.key(), "/products");
+
+    let result = router.find("/products/10/edit");
+    assert_eq!(result.payload, &Some("edit"));
+    assert_eq!(result.params("id"), "10");
+
+    router.set_partial_match(PartialMatch::Strict);
+    assert_eq!(router.find("/products/10").payload, &None);
+}
+
 #[test]
 fn returns_named_parameters_in_result() {
     let mut router = Router::<&str>::new();
@@ -186,7 +326,7 @@ fn returns_named_parameters_in_result() {
 fn returns_unicode_values_in_parameters() {
     let mut router = Router::<&str>::new();
     router.add("/one/:あいう", "one");
-    let mut result = router.find("/one/10");
+    let result = router.find("/one/10");
     assert_eq!(result.key(), "/one/:あいう");
     assert_eq!(result.params("あいう"), "10");
 }
@@ -197,7 +337,7 @@ fn does_prefer_specific_path_over_named_parameter_one_if_both_are_present() {
     router.add("/tag-edit/:tag", "root");
     router.add("/tag-edit2", "products");
 
-    let mut result = router.find("/tag-edit2");
+    let result = router.find("/tag-edit2");
     assert_eq!(result.key(), "/tag-edit2");
 }
 
@@ -207,7 +347,7 @@ fn does_prefer_named_parameter_over_specific_key_with_partially_shared_key() {
     router.add("/orders/:id", "specific_order");
     router.add("/orders/closed", "closed_orders");
 
-    let mut result = router.find("/orders/10");
+    let result = router.find("/orders/10");
     assert_eq!(result.key(), "/orders/:id");
     assert_eq!(result.params("id"), "10");
 }
@@ -255,7 +395,2499 @@ fn dealing_with_named_parameters_and_shared_key() {
     let mut router = Router::<&str>::new();
     router.add("/one/:id", "one");
     router.add("/one-longer/:id", "two");
-    let mut result = router.find("/one-longer/10");
+    let result = router.find("/one-longer/10");
     assert_eq!(result.key(), "/one-longer/:id");
     assert_eq!(result.params("id"), "10");
 }
+
+#[test]
+fn paths_without_leading_separator() {
+    let mut router = Router::<&str>::new();
+    router.add("users/:id", "user");
+    router.add("/about", "about");
+    router.add("users", "users");
+
+    let mut result = router.find("users/42");
+    assert_eq!(result.key(), "users/:id");
+    assert_eq!(result.params("id"), "42");
+
+    result = router.find("/about");
+    assert_eq!(result.key(), "/about");
+    assert_eq!(result.payload, &Some("about"));
+
+    result = router.find("users");
+    assert_eq!(result.key(), "users");
+    assert_eq!(result.payload, &Some("users"));
+
+    result = router.find("about");
+    assert_eq!(result.key(), "");
+}
+
+#[test]
+fn nested_route_under_split_named_parameter() {
+    let mut router = Router::<&str>::new();
+    router.add("/products/:id", "product");
+    router.add("/products/featured", "featured");
+    router.add("/products/:id/edit", "edit");
+
+    let result = router.find("/products/10/edit");
+    assert_eq!(result.key(), "/products/:id/edit");
+    assert_eq!(result.params("id"), "10");
+}
+
+#[test]
+fn consecutive_named_parameters_match_payload() {
+    let mut router = Router::<&str>::new();
+    router.add("/:section/:page", "static_page");
+
+    let result = router.find("/about/shipping");
+    assert_eq!(result.params("section"), "about");
+    assert_eq!(result.params("page"), "shipping");
+    assert_eq!(result.payload, &Some("static_page"));
+}
+
+#[test]
+fn unnamed_catch_all_in_parameters() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*", "all");
+
+    let result = router.find("/a/b/c");
+    assert_eq!(result.key(), "/*");
+    assert_eq!(result.params(UNNAMED_CATCH_ALL), "a/b/c");
+    assert_eq!(result.params("splat"), "a/b/c");
+}
+
+#[test]
+fn into_iter_yields_owned_payloads() {
+    let mut router = Router::<String>::new();
+    router.add("/", "root".to_string());
+    router.add("/products", "products".to_string());
+    router.add("/products/:id", "product".to_string());
+    router.add("/*filepath", "all".to_string());
+
+    let mut routes: Vec<(String, String)> = router.into_iter().collect();
+    routes.sort();
+    assert_eq!(
+        routes,
+        vec![
+            ("/".to_string(), "root".to_string()),
+            ("/*filepath".to_string(), "all".to_string()),
+            ("/products".to_string(), "products".to_string()),
+            ("/products/:id".to_string(), "product".to_string()),
+        ]
+    );
+
+    let router: Router<usize> = routes
+        .into_iter()
+        .map(|(path, payload)| (path, payload.len()))
+        .collect();
+    let result = router.find("/products/10");
+    assert_eq!(result.key(), "/products/:id");
+    assert_eq!(result.payload, &Some(7));
+}
+
+#[test]
+fn catch_all_borrowed_from_path() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/あ/*filepath", "all");
+    router.add("/search/*extra", "extra");
+
+    let path = "/あ/src/file.png";
+    let result = router.find_borrowed(path);
+    assert_eq!(result.key(), "/あ/*filepath");
+    assert_eq!(result.catch_all(path), Some("src/file.png"));
+
+    let path = "/search";
+    let result = router.find_borrowed(path);
+    assert_eq!(result.catch_all(path), Some(""));
+
+    let path = "/あ/src/file.png";
+    let result = router.find(path);
+    assert_eq!(result.catch_all(path), Some("src/file.png"));
+    assert_eq!(result.params("filepath"), "src/file.png");
+
+    assert_eq!(router.find_borrowed("/").catch_all("/"), None);
+}
+
+#[test]
+fn structurally_eq_ignores_payloads() {
+    let mut a = Router::<&str>::new();
+    a.add("/", "root");
+    a.add("/products/:id", "product");
+    a.add("/products/featured", "featured");
+
+    let mut b = Router::<usize>::new();
+    b.add("/", 1);
+    b.add("/products/featured", 2);
+    b.add("/products/:id", 3);
+    assert!(a.structurally_eq(&b));
+
+    b.add("/about", 4);
+    assert!(!a.structurally_eq(&b));
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn add_with_info_reports_branches() {
+    use patricia_router::InsertBranch;
+
+    let mut router = Router::<&str>::new();
+    let info = router.add_with_info("/user/repos", "my_repos");
+    assert_eq!(info.branch, InsertBranch::Root);
+
+    let info = router.add_with_info("/users/:user/repos", "user_repos");
+    assert_eq!(info.branch, InsertBranch::Split);
+    assert_eq!(info.pos, 5);
+    assert_eq!(info.depth, 0);
+
+    let info = router.add_with_info("/users/:user", ":user");
+    assert_eq!(info.branch, InsertBranch::Split);
+    assert_eq!(info.pos, 7);
+    assert_eq!(info.depth, 1);
+
+    let info = router.add_with_info("/user", "me");
+    assert_eq!(info.branch, InsertBranch::PayloadSet);
+    assert_eq!(info.pos, 5);
+
+    let info = router.add_with_info("/user/new", "new");
+    assert_eq!(info.branch, InsertBranch::Split);
+    assert_eq!(info.depth, 1);
+}
+
+#[test]
+fn catch_all_excluding_suffixes() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add_excluding("/*filepath", "static", &[".php", ".env"]);
+    router.add("/about", "about");
+
+    let result = router.find("/css/site.css");
+    assert_eq!(result.payload, &Some("static"));
+    assert_eq!(result.params("filepath"), "css/site.css");
+
+    let result = router.find("/admin/index.php");
+    assert_eq!(result.payload, &None);
+
+    let result = router.find("/.env");
+    assert_eq!(result.payload, &None);
+
+    let result = router.find("/about");
+    assert_eq!(result.payload, &Some("about"));
+}
+
+#[test]
+fn get_payload_by_exact_pattern() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products", "products");
+    router.add("/products/:id", "product");
+    router.add("/products/:id/edit", "edit");
+    router.add("/products/featured", "featured");
+
+    assert_eq!(router.get_pattern("/"), Some(&"root"));
+    assert_eq!(router.get_pattern("/*filepath"), Some(&"all"));
+    assert_eq!(router.get_pattern("/products/:id"), Some(&"product"));
+    assert_eq!(router.get_pattern("/products/:id/edit"), Some(&"edit"));
+    assert_eq!(router.get_pattern("/products/featured"), Some(&"featured"));
+    // intermediate nodes and dynamic values are not registered patterns
+    assert_eq!(router.get_pattern("/products/"), None);
+    assert_eq!(router.get_pattern("/products/10"), None);
+    assert_eq!(router.get_pattern("/about"), None);
+
+    assert_eq!(Router::<&str>::new().get_pattern(""), None);
+}
+
+#[test]
+fn replace_payload_by_exact_pattern() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products/:id", "product");
+    router.add("/products/featured", "featured");
+    let before = router.dump_routes();
+    let generation = router.generation();
+
+    assert_eq!(router.replace("/products/:id", "item"), Some("product"));
+    assert_eq!(router.replace("/*filepath", "static"), Some("all"));
+    assert_eq!(router.generation(), generation + 2);
+    assert_eq!(router.find("/products/10").payload, &Some("item"));
+    assert_eq!(router.find("/src/main.rs").payload, &Some("static"));
+    assert_eq!(router.get_pattern("/products/featured"), Some(&"featured"));
+
+    // intermediate nodes, dynamic values and missing routes are left alone
+    assert_eq!(router.replace("/products/", "new"), None);
+    assert_eq!(router.replace("/products/10", "new"), None);
+    assert_eq!(router.replace("/about", "new"), None);
+    assert_eq!(router.dump_routes(), before);
+    assert_eq!(router.generation(), generation + 2);
+
+    assert_eq!(Router::<&str>::new().replace("/", "root"), None);
+}
+
+#[test]
+fn url_for_registered_pattern() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/products/:id/edit", "edit");
+    router.add("/files/*filepath", "files");
+
+    let mut params = HashMap::new();
+    params.insert("id".to_string(), "10".to_string());
+    assert_eq!(
+        router.url_for("/products/:id/edit", &params),
+        Some("/products/10/edit".to_string())
+    );
+    assert_eq!(router.url_for("/", &params), Some("/".to_string()));
+    // missing parameter
+    assert_eq!(router.url_for("/files/*filepath", &params), None);
+    // unregistered pattern
+    assert_eq!(router.url_for("/products/:id", &params), None);
+
+    params.insert("filepath".to_string(), "src/file.png".to_string());
+    assert_eq!(
+        router.url_for("/files/*filepath", &params),
+        Some("/files/src/file.png".to_string())
+    );
+}
+
+#[test]
+fn find_ancestor_of_unregistered_path() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/admin", "admin");
+    router.add("/admin/users/:id", "user");
+    router.add("/admin/users/:id/posts/:post", "post");
+
+    let result = router.find_ancestor("/admin/users/42/edit");
+    assert_eq!(result.key(), "/admin/users/:id");
+    assert_eq!(result.payload, &Some("user"));
+    assert_eq!(result.params("id"), "42");
+
+    let result = router.find_ancestor("/admin/settings");
+    assert_eq!(result.key(), "/admin");
+    assert_eq!(result.payload, &Some("admin"));
+
+    // registered routes are their own deepest ancestor
+    let result = router.find_ancestor("/admin/users/42/posts/7");
+    assert_eq!(result.key(), "/admin/users/:id/posts/:post");
+    assert_eq!(result.params("post"), "7");
+
+    // ancestors must end on a separator
+    let result = router.find_ancestor("/administrators");
+    assert_eq!(result.key(), "/");
+    assert_eq!(result.payload, &Some("root"));
+}
+
+#[test]
+fn try_find_rejects_malformed_paths() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/products/:id", "product");
+
+    let result = router.try_find("/products/10").unwrap().unwrap();
+    assert_eq!(result.params("id"), "10");
+    assert!(router.try_find("/about").unwrap().is_none());
+
+    assert_eq!(
+        router.try_find("/products/1\u{0}0").unwrap_err(),
+        FindError::ControlCharacter(11)
+    );
+    assert_eq!(
+        router.try_find("/products\n").unwrap_err(),
+        FindError::ControlCharacter(9)
+    );
+}
+
+#[test]
+fn add_all_shares_payload() {
+    use std::rc::Rc;
+
+    let mut router = Router::<Rc<String>>::new();
+    let handler = Rc::new("health".to_string());
+    router.add_all(&["/health", "/healthz", "/status/health"], handler.clone());
+    assert_eq!(Rc::strong_count(&handler), 4);
+
+    for path in &["/health", "/healthz", "/status/health"] {
+        let result = router.find(*path);
+        assert_eq!(result.payload.as_deref(), Some(&"health".to_string()));
+    }
+    assert_eq!(router.find("/status").payload, &None);
+}
+
+#[test]
+fn add_unsorted_then_sort() {
+    let routes = [
+        ("/", "root"),
+        ("/*filepath", "all"),
+        ("/products", "products"),
+        ("/products/:id", "product"),
+        ("/products/:id/edit", "edit"),
+        ("/products/featured", "featured"),
+    ];
+    let mut sorted = Router::<&str>::new();
+    let mut unsorted = Router::<&str>::new();
+    for (path, payload) in routes.iter() {
+        sorted.add(*path, *payload);
+        unsorted.add_unsorted(*path, *payload);
+    }
+    unsorted.sort();
+    assert!(sorted.structurally_eq(&unsorted));
+
+    let result = unsorted.find("/products/featured");
+    assert_eq!(result.payload, &Some("featured"));
+    let result = unsorted.find("/products/10/edit");
+    assert_eq!(result.payload, &Some("edit"));
+}
+
+#[test]
+fn classify_matched_route() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products", "products");
+    router.add("/products/:id", "product");
+    router.add("/products/featured", "featured");
+
+    assert_eq!(router.classify("/"), Some(Kind::Normal));
+    assert_eq!(router.classify("/products/featured"), Some(Kind::Normal));
+    assert_eq!(router.classify("/products/xyz"), Some(Kind::Named));
+    assert_eq!(router.classify("/admin/articles"), Some(Kind::Glob));
+
+    let mut router = Router::<&str>::new();
+    router.add("/products/:id/edit", "edit");
+    assert_eq!(router.classify("/products/10"), None);
+}
+
+#[test]
+fn trees_built_in_different_orders_are_equal() {
+    let routes = [
+        ("/", "root"),
+        ("/a", "a"),
+        ("/b", "b"),
+        ("/products", "products"),
+        ("/products/:id", "product"),
+        ("/products/featured", "featured"),
+        ("/*filepath", "all"),
+    ];
+    let mut a = Router::<&str>::new();
+    for (path, payload) in routes.iter() {
+        a.add(*path, *payload);
+    }
+    let mut b = Router::<&str>::new();
+    for (path, payload) in routes.iter().rev() {
+        b.add(*path, *payload);
+    }
+    assert_eq!(a, b);
+
+    b.add("/c", "c");
+    assert_ne!(a, b);
+}
+
+#[test]
+fn find_streaming_hands_params_to_sink() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/:section/:page", "static_page");
+
+    let mut params = Vec::new();
+    let payload = router.find_streaming("/about/shipping", |name, value| {
+        params.push((name.to_string(), value.to_string()))
+    });
+    assert_eq!(payload, Some(&"static_page"));
+    assert_eq!(
+        params,
+        vec![
+            ("section".to_string(), "about".to_string()),
+            ("page".to_string(), "shipping".to_string())
+        ]
+    );
+
+    let mut router = Router::<&str>::new();
+    router.add("/files/*filepath", "files");
+    let mut params = Vec::new();
+    let payload = router.find_streaming("/files/src/file.png", |name, value| {
+        params.push((name.to_string(), value.to_string()))
+    });
+    assert_eq!(payload, Some(&"files"));
+    assert_eq!(
+        params,
+        vec![("filepath".to_string(), "src/file.png".to_string())]
+    );
+
+    assert_eq!(router.find_streaming("/a/b/c", |_, _| {}), None);
+}
+
+#[test]
+fn adversarial_parameters_do_not_panic() {
+    let mut router = Router::<&str>::new();
+    router.add("/users/:", "unnamed");
+    router.add("/nested/:a:b", "nested");
+    router.add("/unicode/:あ", "unicode");
+
+    let result = router.find("/users/42");
+    assert_eq!(result.payload, &Some("unnamed"));
+    assert_eq!(result.params(""), "42");
+
+    let result = router.find("/nested/x:y");
+    assert_eq!(result.payload, &Some("nested"));
+    assert_eq!(result.params("a:b"), "x:y");
+
+    let result = router.find("/unicode/あいう");
+    assert_eq!(result.params("あ"), "あいう");
+
+    for path in &[
+        "/users/", "/users//", "/users/:", "/nested/", "/:", "/*", "::", "",
+    ] {
+        router.find(*path);
+    }
+}
+
+#[test]
+fn validate_pattern_before_adding() {
+    let mut router = Router::new();
+    for pattern in &["/", "/products/:id/edit", "/files/*filepath"] {
+        assert_eq!(validate_pattern(pattern), Ok(()));
+        router.add(*pattern, *pattern);
+    }
+    assert_eq!(
+        validate_pattern("/products/:id/variants/:id"),
+        Err(PatternError::DuplicateParamName("id".to_string()))
+    );
+    assert_eq!(
+        validate_pattern("/files/*filepath/raw")
+            .unwrap_err()
+            .to_string(),
+        "catch all parameter at position 7 is not last"
+    );
+}
+
+#[test]
+fn dynamic_routes_list_their_parameters() {
+    let mut router = Router::new();
+    router.add("/", "root");
+    router.add("/products", "products");
+    router.add("/products/:id/edit", "edit");
+    router.add("/products/:id", "product");
+    router.add("/orders/:status{open|closed}", "orders");
+    router.add("/files/*", "files");
+
+    let routes: Vec<(String, Vec<ParamSpec>)> = router.dynamic_routes().collect();
+    let named = |name: &str| ParamSpec {
+        name: name.to_string(),
+        kind: Kind::Named,
+    };
+    assert_eq!(routes.len(), 4);
+    assert!(routes.contains(&("/products/:id".to_string(), vec![named("id")])));
+    assert!(routes.contains(&("/products/:id/edit".to_string(), vec![named("id")])));
+    assert!(routes.contains(&(
+        "/orders/:status{open|closed}".to_string(),
+        vec![named("status")]
+    )));
+    let glob = ParamSpec {
+        name: UNNAMED_CATCH_ALL.to_string(),
+        kind: Kind::Glob,
+    };
+    assert!(routes.contains(&("/files/*".to_string(), vec![glob])));
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("std::vec::Vec", "vec");
+    router.add("std:::module", "module");
+    let routes: Vec<(String, Vec<ParamSpec>)> = router.dynamic_routes().collect();
+    assert_eq!(
+        routes,
+        vec![("std:::module".to_string(), vec![named("module")])]
+    );
+}
+
+#[test]
+fn debug_node_exposes_tree_shape() {
+    let mut router = Router::new();
+    router.add("/", "root");
+    router.add("/products/:id", "product");
+    router.add("/products/featured", "featured");
+
+    assert_eq!(
+        router.debug_node("/products/"),
+        Some(NodeView {
+            key: "products/",
+            kind: Kind::Normal,
+            priority: 9,
+            has_payload: false,
+            children: 2,
+        })
+    );
+    let node = router.debug_node("/products/:id").unwrap();
+    assert_eq!(
+        (node.key, node.kind, node.has_payload),
+        (":id", Kind::Named, true)
+    );
+    assert_eq!(router.debug_node("/").unwrap().children, 1);
+    assert!(router.debug_node("/products/10").is_none());
+}
+
+#[test]
+fn catch_all_split_into_segments() {
+    let mut router = Router::new();
+    router.add("/files/*path", "files");
+
+    assert_eq!(
+        router.find("/files/a/b/c").param_segments("path"),
+        vec!["a", "b", "c"]
+    );
+    assert_eq!(
+        router.find("/files/a/b/").param_segments("path"),
+        vec!["a", "b"]
+    );
+    assert!(router.find("/files/").param_segments("path").is_empty());
+}
+
+#[test]
+fn fallback_when_nothing_matches() {
+    let mut router = Router::new();
+    router.add("/users/:id/edit", "edit");
+    assert_eq!(router.find("/users/42").payload, &None);
+
+    router.set_fallback("not found");
+    let result = router.find("/users/42");
+    assert!(result.is_fallback());
+    assert_eq!(result.payload, &Some("not found"));
+    assert_eq!(result.key(), "");
+
+    let result = router.find("/users/42/edit");
+    assert!(!result.is_fallback());
+    assert_eq!(result.params("id"), "42");
+
+    assert!(router.find_borrowed("/about").is_fallback());
+    assert!(router.find_ancestor("/about").is_fallback());
+    assert!(router.try_find("/about").unwrap().is_none());
+    assert_eq!(router.classify("/about"), None);
+}
+
+#[test]
+fn case_insensitive_find_reports_canonical_key() {
+    let mut router = Router::new();
+    router.add("/about", "about");
+    router.add("/Contact/legacy", "legacy");
+    router.add("/products/:id/Edit", "edit");
+
+    assert_eq!(router.find("/About").payload, &None);
+    let result = router.find_case_insensitive("/ABOUT");
+    assert_eq!(result.payload, &Some("about"));
+    assert_eq!(result.key(), "/about");
+
+    let result = router.find_case_insensitive("/contact/LEGACY");
+    assert_eq!(result.key(), "/Contact/legacy");
+
+    let result = router.find_case_insensitive("/Products/AbC/edit");
+    assert_eq!(result.key(), "/products/:id/Edit");
+    assert_eq!(result.params("id"), "AbC");
+
+    // only ASCII letters are folded
+    router.add("/ÉTÉ", "summer");
+    assert_eq!(router.find_case_insensitive("/été").payload, &None);
+}
+
+#[test]
+fn parameter_ranges_slice_the_path() {
+    let mut router = Router::new();
+    router.add("/ユーザー/:id/files/*path", "files");
+    router.add("/search/*query", "search");
+
+    let path = "/ユーザー/太郎/files/写真/a.png";
+    let result = router.find_with_ranges(path);
+    let ranges = result.param_ranges();
+    assert_eq!(ranges.len(), 2);
+    assert_eq!(
+        (ranges[0].0.as_str(), &path[ranges[0].1.clone()]),
+        ("id", "太郎")
+    );
+    assert_eq!(
+        (ranges[1].0.as_str(), &path[ranges[1].1.clone()]),
+        ("path", "写真/a.png")
+    );
+
+    let result = router.find_with_ranges("/search");
+    assert_eq!(result.param_range("query"), Some(7..7));
+
+    assert_eq!(router.find(path).param_range("id"), None);
+}
+
+#[test]
+fn owned_result_outlives_the_router() {
+    let mut router = Router::new();
+    router.add("/users/:id", "user".to_string());
+    router.set_fallback("not found".to_string());
+
+    let result = router.find_owned("/users/42");
+    let missing = router.find_owned("/about");
+    drop(router);
+
+    let handle = std::thread::spawn(move || (result.params("id").clone(), result.payload));
+    assert_eq!(
+        handle.join().unwrap(),
+        ("42".to_string(), Some("user".to_string()))
+    );
+    assert!(missing.is_fallback());
+    assert_eq!(missing.key(), "");
+}
+
+#[test]
+fn resolved_routes_can_be_cached() {
+    let mut router = Router::new();
+    router.add("/users/:id", "user".to_string());
+    router.add("/files/*path", "file".to_string());
+    router.set_fallback("not found".to_string());
+
+    let mut cache: HashMap<&str, Option<ResolvedRoute<String>>> = HashMap::new();
+    for path in ["/users/42", "/files/a/b", "/about"].iter() {
+        cache.insert(*path, router.resolve(*path));
+    }
+    drop(router);
+
+    let user = cache["/users/42"].as_ref().unwrap();
+    assert_eq!(user.pattern(), "/users/:id");
+    assert_eq!(user.params("id"), "42");
+    assert_eq!(user.payload, "user");
+    let file = cache["/files/a/b"].as_ref().unwrap();
+    assert_eq!(file.pattern(), "/files/*path");
+    assert_eq!(file.params_map().len(), 1);
+    assert_eq!(file.params("path"), "a/b");
+    // the fallback isn't a route
+    assert_eq!(cache["/about"], None);
+}
+
+#[test]
+fn retain_lays_the_router_out_like_the_remaining_routes() {
+    let routes = [
+        ("/", true),
+        ("/products", false),
+        ("/products/:id", true),
+        ("/products/featured", false),
+        ("/beta/search", false),
+        ("/beta/*path", false),
+        ("/about", true),
+    ];
+    let mut router = Router::new();
+    for (path, enabled) in routes.iter() {
+        router.add(*path, *enabled);
+    }
+    let mut patterns = Vec::new();
+    router.retain(|pattern, &enabled| {
+        patterns.push(pattern.to_string());
+        enabled
+    });
+    assert_eq!(patterns.len(), routes.len());
+
+    let mut expected = Router::new();
+    for (path, enabled) in routes.iter().filter(|(_, enabled)| *enabled) {
+        expected.add(*path, *enabled);
+    }
+    assert_eq!(router, expected);
+    assert_eq!(router.find("/products/featured").payload, &Some(true));
+    assert_eq!(router.find("/beta/search").payload, &None);
+
+    router.retain(|_, _| false);
+    assert_eq!(router, Router::new());
+    router.add("/beta/search", true);
+    assert_eq!(router.find("/beta/search").payload, &Some(true));
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("std::vec::Vec", "vec");
+    router.add("std:::module", "module");
+    router.retain(|pattern, _| pattern != "std:::module");
+    assert_eq!(router.find("std::vec::Vec").payload, &Some("vec"));
+    assert_eq!(router.find("std::fs").payload, &None);
+}
+
+#[test]
+fn compact_keeps_find_results() {
+    let paths = [
+        "/products/:id/edit",
+        "/api/v1/users/:id",
+        "/products",
+        "/api/v1/users",
+        "/*filepath",
+        "/products/featured",
+        "/api/v2/status",
+        "/",
+    ];
+    let mut router = Router::new();
+    for path in paths.iter() {
+        router.add_unsorted(*path, *path);
+    }
+    router.sort();
+
+    let lookups = [
+        "/",
+        "/products",
+        "/products/10/edit",
+        "/products/featured",
+        "/api/v1/users",
+        "/api/v1/users/42",
+        "/api/v2/status",
+        "/api/v3",
+        "/src/main.rs",
+    ];
+    let before: Vec<_> = lookups
+        .iter()
+        .map(|path| router.find_owned(*path))
+        .collect();
+    router.compact();
+    let after: Vec<_> = lookups
+        .iter()
+        .map(|path| router.find_owned(*path))
+        .collect();
+    assert_eq!(before, after);
+}
+
+#[test]
+fn named_parameter_constrained_to_values() {
+    let mut router = Router::new();
+    router.add("/posts/:status{draft|published|archived}", "status");
+    router.add("/posts/:status{draft|published|archived}/count", "count");
+    router.add("/posts/*rest", "rest");
+    router.add("/posts/new", "new");
+
+    let result = router.find("/posts/draft");
+    assert_eq!(result.payload, &Some("status"));
+    assert_eq!(result.params("status"), "draft");
+    assert_eq!(result.key(), "/posts/:status{draft|published|archived}");
+    assert_eq!(router.find("/posts/archived/count").payload, &Some("count"));
+    assert_eq!(router.find("/posts/new").payload, &Some("new"));
+
+    // values outside the set fall back to the catch all sibling
+    let result = router.find("/posts/deleted");
+    assert_eq!(result.payload, &Some("rest"));
+    assert_eq!(result.params("rest"), "deleted");
+    assert_eq!(router.find("/posts/drafts/count").payload, &Some("rest"));
+
+    let mut params = HashMap::new();
+    params.insert("status".to_string(), "deleted".to_string());
+    let pattern = "/posts/:status{draft|published|archived}";
+    assert_eq!(router.url_for(pattern, &params), None);
+    params.insert("status".to_string(), "draft".to_string());
+    assert_eq!(
+        router.url_for(pattern, &params),
+        Some("/posts/draft".to_string())
+    );
+}
+
+#[test]
+fn constrained_parameter_inside_a_key() {
+    let mut router = Router::new();
+    router.add("/reports/:format{csv|json}", "report");
+
+    assert_eq!(router.find("/reports/csv").payload, &Some("report"));
+    assert_eq!(router.find("/reports/xml").payload, &None);
+}
+
+#[cfg(feature = "instrumentation")]
+#[test]
+fn find_with_stats_counts_work() {
+    let mut router = Router::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products", "products");
+    router.add("/products/featured", "featured");
+
+    let (result, stats) = router.find_with_stats("/products/featured");
+    assert_eq!(result.payload, &Some("featured"));
+    assert_eq!(stats.nodes, 3);
+    assert_eq!(stats.comparisons, "/products/featured".len());
+
+    // the catch all stops comparing as soon as it is reached
+    let (result, stats) = router.find_with_stats("/src/main.rs");
+    assert_eq!(result.payload, &Some("all"));
+    assert_eq!(stats.nodes, 2);
+    assert_eq!(stats.comparisons, 2);
+}
+
+#[test]
+fn multi_character_separator() {
+    let mut router = Router::with_separator_str("::");
+    router.add("crate", "crate");
+    router.add("crate::io::*rest", "io");
+    router.add("crate:::module::Error", "error");
+
+    let result = router.find("crate::fmt::Error");
+    assert_eq!(result.key(), "crate:::module::Error");
+    assert_eq!(result.params("module"), "fmt");
+
+    // catch all parameters span across separators
+    let path = "crate::io::fs::File";
+    let result = router.find_with_ranges(path);
+    assert_eq!(result.params("rest"), "fs::File");
+    assert_eq!(result.param_segments("rest"), vec!["fs", "File"]);
+    assert_eq!(&path[result.param_range("rest").unwrap()], "fs::File");
+    assert_eq!(router.find_borrowed(path).catch_all(path), Some("fs::File"));
+    assert_eq!(router.find("crate::io").params("rest"), "");
+
+    assert_eq!(router.get_pattern("crate:::module::Error"), Some(&"error"));
+    let mut params = HashMap::new();
+    params.insert("module".to_string(), "fmt".to_string());
+    assert_eq!(
+        router.url_for("crate:::module::Error", &params),
+        Some("crate::fmt::Error".to_string())
+    );
+
+    let mut paths: Vec<_> = router.into_iter().map(|(path, _)| path).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec!["crate", "crate:::module::Error", "crate::io::*rest"]
+    );
+}
+
+#[test]
+fn percent_encoded_separator_stays_in_one_segment() {
+    let mut router = Router::new();
+    router.add("/files/:name", "file");
+    router.add("/files/:name/meta", "meta");
+    router.add("/static/*path", "static");
+
+    let result = router.find_decoded("/files/a%2Fb");
+    assert_eq!(result.payload, &Some("file"));
+    assert_eq!(result.key(), "/files/:name");
+    assert_eq!(result.params("name"), "a/b");
+
+    let result = router.find_decoded("/files/a%2Fb/meta");
+    assert_eq!(result.payload, &Some("meta"));
+    assert_eq!(result.params("name"), "a/b");
+
+    let result = router.find_decoded("/static/css/a%20b.css");
+    assert_eq!(result.params("path"), "css/a b.css");
+
+    // without decoding the raw value is captured
+    assert_eq!(router.find("/files/a%2Fb").params("name"), "a%2Fb");
+}
+
+#[test]
+fn restore_snapshot_after_failed_batch() {
+    let mut router = Router::new();
+    router.add("/users/:id", "user");
+    router.set_fallback("not found");
+
+    let snapshot = router.snapshot();
+    let batch = ["/posts", "/posts/:slug", "/users/:name/edit", "/comments"];
+    let failed = batch.iter().find(|path| {
+        if router.would_conflict(**path).is_some() {
+            return true;
+        }
+        router.add(**path, "batch");
+        false
+    });
+    assert_eq!(failed, Some(&"/users/:name/edit"));
+    assert_eq!(router.find("/posts").payload, &Some("batch"));
+
+    router.restore(snapshot);
+    assert!(router.find("/posts").is_fallback());
+    assert!(router.find("/posts/hello").is_fallback());
+    assert_eq!(router.find("/users/42").payload, &Some("user"));
+    // the batch can be applied again once fixed
+    router.add("/posts", "posts");
+    assert_eq!(router.find("/posts").payload, &Some("posts"));
+}
+
+#[test]
+fn routes_with_prefix_lists_the_subtree() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/api/v1", "v1");
+    router.add("/api/v1/users", "users");
+    router.add("/api/v1/users/:id", "user");
+    router.add("/api/v1/files/*path", "files");
+    router.add("/api/v2/users", "users v2");
+
+    let mut routes = router.routes_with_prefix("/api/v1");
+    routes.sort();
+    assert_eq!(
+        routes,
+        vec![
+            ("/api/v1".to_string(), &"v1"),
+            ("/api/v1/files/*path".to_string(), &"files"),
+            ("/api/v1/users".to_string(), &"users"),
+            ("/api/v1/users/:id".to_string(), &"user"),
+        ]
+    );
+    // prefixes may end inside a node key
+    assert_eq!(router.routes_with_prefix("/api/v").len(), 5);
+    assert_eq!(router.routes_with_prefix("").len(), 6);
+    assert!(router.routes_with_prefix("/api/v3").is_empty());
+}
+
+#[test]
+fn rejects_paths_longer_than_max_len() {
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id", "user");
+    router.add("/*path", "all");
+    router.set_max_path_len(10);
+
+    assert_eq!(router.find("/users/42").payload, &Some("user"));
+    // 10 characters but more bytes
+    assert_eq!(router.find("/users/あいう").params("id"), "あいう");
+    assert_eq!(router.find("/users/4242").payload, &None);
+    assert_eq!(router.find_borrowed("/users/4242").payload, &None);
+    assert_eq!(router.find_streaming("/users/4242", |_, _| ()), None);
+
+    router.set_fallback("too long");
+    assert!(router.find("/users/4242").is_fallback());
+}
+
+#[test]
+fn rejects_paths_deeper_than_max_segments() {
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id", "user");
+    router.add("/*path", "all");
+    router.set_max_segments(2);
+
+    assert_eq!(router.find("/users/42").payload, &Some("user"));
+    assert_eq!(router.find("/users/42/").payload, &Some("user"));
+    assert_eq!(router.find("/a/b/c").payload, &None);
+    assert_eq!(router.find_borrowed("/a/b/c").payload, &None);
+    assert_eq!(router.find_streaming("/a/b/c", |_, _| ()), None);
+
+    router.set_fallback("too deep");
+    assert!(router.find("/a/b/c").is_fallback());
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("::*path", "all");
+    router.set_max_segments(2);
+    assert_eq!(router.find("::a::b").payload, &Some("all"));
+    assert_eq!(router.find("::a::b::c").payload, &None);
+}
+
+#[test]
+fn leaf_key_is_the_end_of_the_key() {
+    let mut router = Router::<&str>::new();
+    router.add("/users", "users");
+    router.add("/users/:id", "user");
+    router.add("/users/new", "new");
+
+    let result = router.find("/users/42");
+    assert_eq!(result.leaf_key(), Some(":id"));
+    assert!(result.key().ends_with(result.leaf_key().unwrap()));
+    assert_eq!(router.find("/users").leaf_key(), Some("/users"));
+    assert_eq!(router.find("/posts").leaf_key(), None);
+}
+
+#[test]
+fn to_flat_lists_every_route() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "static");
+    router.add("/products", "products");
+    router.add("/products/:id", "product");
+    router.set_fallback("not found");
+
+    let (routes, payloads) = router.to_flat();
+    assert_eq!(routes.len(), 4);
+    assert_eq!(payloads.len(), 4);
+    let dumped: Vec<String> = routes
+        .iter()
+        .map(|route| {
+            format!(
+                "{} {} {}",
+                route.pattern, route.kind, payloads[route.payload_index]
+            )
+        })
+        .collect();
+    assert_eq!(
+        dumped,
+        vec![
+            "/ 0 root",
+            "/products 0 products",
+            "/products/:id 1 product",
+            "/*filepath 2 static",
+        ]
+    );
+}
+
+#[test]
+fn segment_index_matches_like_walking() {
+    let routes = [
+        "/",
+        "/*filepath",
+        "/users",
+        "/users/:id",
+        "/users/:id/posts",
+        "/user_groups/:id",
+        "/user_groups/new",
+        "/posts/",
+        "/posts/:id",
+        "/p/:page",
+        "/:lang/about",
+        "/api/v1/*rest",
+    ];
+    let paths = [
+        "/",
+        "/users",
+        "/users/",
+        "/users/42",
+        "/users/42/posts",
+        "/users/42/comments",
+        "/user_groups/7",
+        "/user_groups/new",
+        "/user_groups/",
+        "/posts",
+        "/posts/",
+        "/posts/1",
+        "/p/2",
+        "/p",
+        "/en/about",
+        "/api/v1/a/b",
+        "/api/v2/a",
+        "/unknown/path",
+        "/users//42",
+        "",
+    ];
+
+    let mut walked = Router::<usize>::new();
+    for (i, route) in routes.iter().enumerate() {
+        walked.add(*route, i);
+    }
+    let mut indexed = Router::<usize>::new();
+    indexed.set_segment_index(true);
+    for (i, route) in routes.iter().enumerate() {
+        indexed.add(*route, i);
+    }
+    assert_eq!(indexed, walked);
+
+    let check = |indexed: &Router<usize>, walked: &Router<usize>| {
+        for path in paths.iter() {
+            let (expected, result) = (
+                walked.find_with_ranges(path),
+                indexed.find_with_ranges(path),
+            );
+            assert_eq!(result.payload, expected.payload, "{}", path);
+            assert_eq!(result.key(), expected.key(), "{}", path);
+            assert_eq!(result.params_map(), expected.params_map(), "{}", path);
+            assert_eq!(result.param_ranges(), expected.param_ranges(), "{}", path);
+            assert_eq!(result.catch_all(path), expected.catch_all(path), "{}", path);
+            assert_eq!(
+                indexed.allowed_at(path),
+                walked.allowed_at(path),
+                "{}",
+                path
+            );
+        }
+    };
+    check(&indexed, &walked);
+
+    // the index follows the routes added, sorted and compacted later on
+    walked.add("/users_archive/:id", 100);
+    indexed.add("/users_archive/:id", 100);
+    walked.add_unsorted("/orders/:id", 101);
+    indexed.add_unsorted("/orders/:id", 101);
+    walked.sort();
+    indexed.sort();
+    assert_eq!(indexed.find("/users_archive/3").payload, &Some(100));
+    assert_eq!(indexed.find("/orders/3").payload, &Some(101));
+    check(&indexed, &walked);
+    walked.compact();
+    indexed.compact();
+    check(&indexed, &walked);
+
+    indexed.set_segment_index(false);
+    check(&indexed, &walked);
+}
+
+#[test]
+fn named_parameter_with_default_value() {
+    let mut router = Router::<&str>::new();
+    router.add("/posts/:page=1", "posts");
+    router.add("/users/:id/:tab{posts|likes}=posts", "tab");
+    router.add("/about", "about");
+
+    let result = router.find("/posts/3");
+    assert_eq!(result.payload, &Some("posts"));
+    assert_eq!(result.key(), "/posts/:page=1");
+    assert_eq!(result.params("page"), "3");
+    assert_eq!(router.find("/posts").params("page"), "1");
+    assert_eq!(router.find("/posts/").params("page"), "1");
+    assert_eq!(
+        router.find_lazy("/posts").get_param("page").as_deref(),
+        Some("1")
+    );
+    assert_eq!(
+        router.find_lazy("/posts/3").get_param("page").as_deref(),
+        Some("3")
+    );
+
+    let path = "/posts";
+    let result = router.find_with_ranges(path);
+    assert_eq!(result.param_range("page"), Some(path.len()..path.len()));
+
+    assert_eq!(router.find("/users/7").params("tab"), "posts");
+    assert_eq!(router.find("/users/7/likes").params("tab"), "likes");
+    assert_eq!(router.find("/users/7/other").payload, &None);
+
+    // an empty segment is captured as is
+    let mut empty = Router::<&str>::new();
+    empty.add("/:p=1", "p");
+    assert_eq!(empty.find("//").params("p"), "");
+    assert_eq!(empty.find_lazy("//").get_param("p").as_deref(), Some(""));
+    assert_eq!(empty.find("/").params("p"), "1");
+    assert_eq!(empty.find_lazy("/").get_param("p").as_deref(), Some("1"));
+
+    // a `=` among the allowed values doesn't start a default value
+    router.add("/s/:q{a=b|c}", "search");
+    assert_eq!(router.find("/s/a=b").params("q"), "a=b");
+    assert_eq!(router.find("/s/c").payload, &Some("search"));
+    assert_eq!(router.find("/s/zz").payload, &None);
+    assert_eq!(router.find("/s").payload, &None);
+
+    // the parameter in a node of its own, next to a static sibling
+    router.add("/posts/new", "new");
+    assert_eq!(router.find("/posts/new").payload, &Some("new"));
+    assert_eq!(router.find("/posts").params("page"), "1");
+    assert_eq!(router.find("/posts/").params("page"), "1");
+    assert_eq!(
+        router.find_lazy("/posts/").get_param("page").as_deref(),
+        Some("1")
+    );
+
+    assert_eq!(router.param_names_for("/posts/:page=1"), vec!["page"]);
+    let mut params = HashMap::new();
+    assert_eq!(
+        router.url_for("/posts/:page=1", &params),
+        Some("/posts/1".to_string())
+    );
+    params.insert("page".to_string(), "2".to_string());
+    assert_eq!(
+        router.url_for("/posts/:page=1", &params),
+        Some("/posts/2".to_string())
+    );
+}
+
+#[test]
+fn add_segments_matches_like_add() {
+    let routes: [&[&str]; 5] = [
+        &[],
+        &["users"],
+        &["users", ":id"],
+        &["users", ":id", "*rest"],
+        &["posts", ""],
+    ];
+
+    let mut joined = Router::<usize>::new();
+    let mut segmented = Router::<usize>::new();
+    for (i, segments) in routes.iter().enumerate() {
+        joined.add(format!("/{}", segments.join("/")), i);
+        segmented.add_segments(segments, i);
+    }
+    assert_eq!(segmented, joined);
+    assert_eq!(segmented.find("/posts/").payload, &Some(4));
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add_segments(&["std", ":module"], "module");
+    assert_eq!(router.find("::std::vec").params("module"), "vec");
+}
+
+#[test]
+fn dump_routes_is_deterministic() {
+    let routes = [
+        "/",
+        "/*filepath",
+        "/products",
+        "/products/:id",
+        "/products/featured",
+    ];
+    let mut router = Router::<&str>::new();
+    for path in routes {
+        router.add(path, "payload");
+    }
+    let mut reversed = Router::<&str>::new();
+    for path in routes.iter().rev() {
+        reversed.add(*path, "payload");
+    }
+
+    let dump = router.dump_routes();
+    assert_eq!(dump, reversed.dump_routes());
+    assert_eq!(
+        dump.lines().collect::<Vec<_>>(),
+        vec![
+            "/\tNormal\t1",
+            "/products\tNormal\t8",
+            "/products/featured\tNormal\t8",
+            "/products/:id\tNamed\t0",
+            "/*filepath\tGlob\t0",
+        ]
+    );
+}
+
+#[test]
+fn find_keyed_returns_payload_and_key() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/products/:id", "product");
+    router.add("/*filepath", "all");
+
+    assert_eq!(
+        router.find_keyed("/products/10"),
+        (Some(&"product"), "/products/:id".to_string())
+    );
+    assert_eq!(
+        router.find_keyed("/src/file.png"),
+        (Some(&"all"), "/*filepath".to_string())
+    );
+    assert_eq!(router.find_keyed("/"), (Some(&"root"), "/".to_string()));
+}
+
+#[test]
+fn empty_path_is_its_own_route() {
+    let mut router = Router::<&str>::new();
+    router.add("", "empty");
+    assert_eq!(router.find("").payload, &Some("empty"));
+    assert_eq!(router.find("").key(), "");
+    assert_eq!(router.find("/").payload, &None);
+
+    router.add("/", "root");
+    router.add("/about", "about");
+    assert_eq!(router.find("").payload, &Some("empty"));
+    assert_eq!(router.find("/").payload, &Some("root"));
+    assert_eq!(router.find("/about").payload, &Some("about"));
+
+    // the same routes added the other way around
+    let mut reversed = Router::<&str>::new();
+    reversed.add("/about", "about");
+    reversed.add("/", "root");
+    reversed.add("", "empty");
+    assert_eq!(router, reversed);
+}
+
+#[test]
+#[should_panic(expected = "duplicate error")]
+fn empty_path_added_twice() {
+    let mut router = Router::<&str>::new();
+    router.add("", "empty");
+    router.add("", "again");
+}
+
+#[test]
+fn nearest_dynamic_ancestor_of_deep_route() {
+    let mut router = Router::<&str>::new();
+    router.add("/users", "users");
+    router.add("/users/:id", "auth");
+    router.add("/users/:id/posts", "posts");
+    router.add("/users/:id/posts/:slug", "post");
+    router.add("/users/:id/files/*path", "files");
+
+    let result = router.find("/users/42/posts");
+    assert_eq!(result.nearest_dynamic_ancestor(), Some(&"auth"));
+    let result = router.find("/users/42/posts/hello");
+    assert_eq!(result.nearest_dynamic_ancestor(), Some(&"auth"));
+    let result = router.find("/users/42/files/a/b");
+    assert_eq!(result.nearest_dynamic_ancestor(), Some(&"auth"));
+
+    assert_eq!(router.find("/users/42").nearest_dynamic_ancestor(), None);
+    assert_eq!(router.find("/users").nearest_dynamic_ancestor(), None);
+}
+
+#[test]
+fn duplicate_policies() {
+    let mut router = Router::<&str>::new();
+    router.set_duplicate_policy(DuplicatePolicy::FirstWins);
+    router.add("/products", "first");
+    router.add("/products", "second");
+    assert_eq!(router.try_add("/products", "third"), Ok(()));
+    assert_eq!(router.find("/products").payload, &Some("first"));
+
+    router.set_duplicate_policy(DuplicatePolicy::LastWins);
+    router.add("/products", "second");
+    assert_eq!(router.find("/products").payload, &Some("second"));
+    assert_eq!(router.try_add("/products", "third"), Ok(()));
+    assert_eq!(router.find("/products").payload, &Some("third"));
+
+    router.set_duplicate_policy(DuplicatePolicy::Error);
+    assert_eq!(
+        router.try_add("/products", "fourth"),
+        Err(ConflictKind::Duplicate)
+    );
+    assert_eq!(router.find("/products").payload, &Some("third"));
+    router.add("/products", "fifth");
+    assert_eq!(router.find("/products").payload, &Some("third"));
+    // intermediate nodes without payload are not duplicates
+    router.add("/products/:id", "product");
+    router.add("/products/:id/edit", "edit");
+    assert_eq!(router.try_add("/products/", "index"), Ok(()));
+    assert_eq!(router.get_pattern("/products/"), Some(&"index"));
+}
+
+#[test]
+#[should_panic(expected = "duplicate error")]
+fn duplicate_policy_panics_by_default() {
+    let mut router = Router::<&str>::new();
+    router.add("/products", "first");
+    assert_eq!(
+        router.try_add("/products", "second"),
+        Err(ConflictKind::Duplicate)
+    );
+    assert_eq!(router.find("/products").payload, &Some("first"));
+    router.add("/products", "third");
+}
+
+#[test]
+fn for_each_route_reuses_buffer() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/products", "products");
+    router.add("/products/:id", "product");
+    router.add("/*filepath", "all");
+
+    let mut buf = String::new();
+    let mut first = Vec::new();
+    router.for_each_route(&mut buf, |path, payload| {
+        first.push((path.to_string(), *payload))
+    });
+    let capacity = buf.capacity();
+
+    let mut second = Vec::new();
+    router.for_each_route(&mut buf, |path, payload| {
+        second.push((path.to_string(), *payload))
+    });
+    assert_eq!(first, second);
+    assert_eq!(buf.capacity(), capacity);
+
+    let mut expected: Vec<_> = router.into_iter().collect();
+    expected.sort();
+    first.sort();
+    assert_eq!(first, expected);
+}
+
+#[test]
+fn catch_all_following_named_parameter() {
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id/*rest", "rest");
+
+    let result = router.find("/users/42/a/b/c");
+    assert_eq!(result.payload, &Some("rest"));
+    assert_eq!(result.key(), "/users/:id/*rest");
+    assert_eq!(result.params("id"), "42");
+    assert_eq!(result.params("rest"), "a/b/c");
+
+    let path = "/users/7/a/b";
+    let result = router.find_with_ranges(path);
+    assert_eq!(result.params("id"), "7");
+    assert_eq!(&path[result.param_range("id").unwrap()], "7");
+    assert_eq!(&path[result.param_range("rest").unwrap()], "a/b");
+    assert_eq!(router.find_borrowed(path).catch_all(path), Some("a/b"));
+
+    // the node gets split once siblings are added after the named parameter
+    router.add("/users/:id", "user");
+    router.add("/users/:id/edit", "edit");
+    let result = router.find("/users/42/a/b/c");
+    assert_eq!(result.payload, &Some("rest"));
+    assert_eq!(result.key(), "/users/:id/*rest");
+    assert_eq!(result.params("id"), "42");
+    assert_eq!(result.params("rest"), "a/b/c");
+
+    let result = router.find("/users/4/a");
+    assert_eq!(
+        (result.params("id").as_str(), result.params("rest").as_str()),
+        ("4", "a")
+    );
+    assert_eq!(router.find("/users/42").payload, &Some("user"));
+    assert_eq!(router.find("/users/42/edit").payload, &Some("edit"));
+}
+
+#[test]
+fn patterns_overlap_detects_shadowed_routes() {
+    assert!(Router::<()>::patterns_overlap("/users/:id", "/users/42"));
+    assert!(Router::<()>::patterns_overlap("/users/42", "/users/:id"));
+    assert!(!Router::<()>::patterns_overlap("/users/:id", "/posts/:id"));
+    assert!(Router::<()>::patterns_overlap(
+        "/*filepath",
+        "/products/:id/edit"
+    ));
+    assert!(Router::<()>::patterns_overlap(
+        "/users/:id/*rest",
+        "/users/:id"
+    ));
+    assert!(!Router::<()>::patterns_overlap(
+        "/products/featured",
+        "/products/:id/edit"
+    ));
+    assert!(Router::<()>::patterns_overlap(
+        "/ユーザー/:id",
+        "/ユーザー/太郎"
+    ));
+}
+
+#[test]
+fn matched_path_keeps_the_input() {
+    let mut router = Router::<&str>::new();
+    router.add("/products/:id", "product");
+
+    let result = router.find("/products/10");
+    assert_eq!(result.key(), "/products/:id");
+    assert_eq!(result.matched_path(), Some("/products/10"));
+    assert_eq!(router.find("/about").matched_path(), None);
+
+    router.set_fallback("not found");
+    assert_eq!(router.find("/about").matched_path(), None);
+    assert_eq!(router.find_borrowed("/products/10").matched_path(), None);
+}
+
+#[test]
+fn require_parses_parameters() {
+    use std::error::Error;
+    use std::net::Ipv4Addr;
+
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id/hosts/:ip", "host");
+
+    let result = router.find("/users/42/hosts/10.0.0.1");
+    assert_eq!(result.require::<u64>("id").unwrap(), 42);
+    assert_eq!(
+        result.require::<Ipv4Addr>("ip").unwrap(),
+        Ipv4Addr::new(10, 0, 0, 1)
+    );
+
+    let err = result.require::<u32>("slug").unwrap_err();
+    assert!(matches!(&err, ParamError::Missing(name) if name == "slug"));
+    assert_eq!(err.to_string(), "missing parameter slug");
+    assert!(err.source().is_none());
+
+    let result = router.find("/users/-1/hosts/localhost");
+    let err = result.require::<u32>("id").unwrap_err();
+    assert!(matches!(&err, ParamError::Parse { name, .. } if name == "id"));
+    assert!(err.source().is_some());
+    assert!(result.require::<Ipv4Addr>("ip").is_err());
+}
+
+#[test]
+fn add_normalized_lowercases_static_parts() {
+    let mut router = Router::<&str>::new();
+    router.add_normalized("/Products/:ProductId/Edit", "edit");
+    router.add_normalized("/ÜBER/Uns", "about");
+
+    let result = router.find_case_insensitive("/PRODUCTS/AbC/EDIT");
+    assert_eq!(result.payload, &Some("edit"));
+    assert_eq!(result.key(), "/products/:ProductId/edit");
+    assert_eq!(result.params("ProductId"), "AbC");
+
+    let path = "/ÜBER/UNS".to_lowercase();
+    assert_eq!(router.find(path).payload, &Some("about"));
+    assert_eq!(router.get_pattern("/über/uns"), Some(&"about"));
+}
+
+#[test]
+fn segment_catch_all_stops_at_separator() {
+    let mut router = Router::<&str>::new();
+    router.add("/files/*?dir/edit", "edit");
+    router.add("/files/*?dir", "dir");
+    router.add("/files/raw", "raw");
+
+    let result = router.find("/files/docs/edit");
+    assert_eq!(result.payload, &Some("edit"));
+    assert_eq!(result.params("dir"), "docs");
+
+    assert_eq!(router.find("/files/docs").payload, &Some("dir"));
+    assert_eq!(router.find("/files/raw").payload, &Some("raw"));
+    assert_eq!(router.find("/files/docs/a/b").payload, &None);
+
+    let mut params = HashMap::new();
+    params.insert("dir".to_string(), "docs".to_string());
+    assert_eq!(
+        router.url_for("/files/*?dir/edit", &params).unwrap(),
+        "/files/docs/edit"
+    );
+}
+
+#[test]
+fn shadowed_routes_depend_on_match_policy() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*all", "all");
+    router.add("/admin/secret", "secret");
+    router.add("/admin/:page", "page");
+    router.add("/files/:name", "name");
+    router.add("/files/*?dir/raw", "raw");
+    router.add("/posts/:status{draft|published}", "posts");
+    router.add("/posts/*?slug", "slug");
+
+    assert_eq!(router.shadowed_routes(), vec!["/files/*?dir/raw"]);
+    assert_eq!(router.find("/files/docs/raw").payload, &None);
+    assert_eq!(router.find("/posts/hello").payload, &Some("slug"));
+
+    router.set_match_policy(MatchPolicy::GlobFirst);
+    let shadowed = router.shadowed_routes();
+    assert_eq!(
+        shadowed,
+        vec![
+            "/",
+            "/admin/secret",
+            "/admin/:page",
+            "/files/:name",
+            "/files/*?dir/raw",
+            "/posts/:status{draft|published}",
+            "/posts/*?slug",
+        ]
+    );
+    for pattern in shadowed {
+        assert_eq!(router.find(pattern.as_str()).payload, &Some("all"));
+    }
+}
+
+#[test]
+fn param_names_for_registered_patterns() {
+    let mut router = Router::<&str>::new();
+    router.add("/posts/:status{draft|published}/:id", "post");
+    router.add("/static/*", "static");
+    router.add("/about", "about");
+
+    assert_eq!(
+        router.param_names_for("/posts/:status{draft|published}/:id"),
+        vec!["status", "id"]
+    );
+    assert_eq!(router.param_names_for("/static/*"), vec![UNNAMED_CATCH_ALL]);
+    assert!(router.param_names_for("/about").is_empty());
+    assert!(router.param_names_for("/posts/:status").is_empty());
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("std::*path", "std");
+    assert_eq!(router.param_names_for("std::*path"), vec!["path"]);
+}
+
+#[test]
+fn redirect_status_for_trailing_slash_mismatch() {
+    let mut router = Router::<&str>::new();
+    router.add("/about", "about");
+    router.add("/docs/", "docs");
+    router.set_fallback("not found");
+    router.set_trailing_slash(TrailingSlash::RedirectTemporary);
+
+    assert_eq!(router.find("/about/").redirect_status(), Some(307));
+    let result = router.find("/docs");
+    assert_eq!(result.payload, &Some("docs"));
+    assert_eq!(result.redirect_status(), Some(307));
+    assert_eq!(router.find("/docs/").redirect_status(), None);
+    assert_eq!(router.find("/missing/").redirect_status(), None);
+
+    router.set_trailing_slash(TrailingSlash::RedirectPermanent);
+    assert_eq!(router.find("/docs").redirect_status(), Some(308));
+    router.set_trailing_slash(TrailingSlash::RedirectMovedPermanently);
+    assert_eq!(router.find("/docs").redirect_status(), Some(301));
+    router.set_trailing_slash(TrailingSlash::RedirectFound);
+    assert_eq!(router.find("/about/").redirect_status(), Some(302));
+
+    router.set_trailing_slash(TrailingSlash::Match);
+    assert_eq!(router.find("/docs").redirect_status(), None);
+}
+
+#[test]
+fn add_unchecked_then_sort() {
+    let mut router = Router::<&str>::new();
+    router.add_unchecked("/*filepath", "all");
+    router.add_unchecked("/products/:id", "product");
+    router.add_unchecked("/products", "products");
+    router.add_unchecked("/products/featured", "featured");
+    router.sort();
+
+    let mut expected = Router::<&str>::new();
+    expected.add("/*filepath", "all");
+    expected.add("/products/:id", "product");
+    expected.add("/products", "products");
+    expected.add("/products/featured", "featured");
+    assert_eq!(router, expected);
+    assert_eq!(router.find("/products/featured").payload, &Some("featured"));
+}
+
+#[test]
+fn add_after_add_unchecked() {
+    let mut router = Router::<&str>::new();
+    router.add_unchecked("/ab", "ab");
+    router.add_unchecked("/ac", "ac");
+    router.add("/ad", "ad");
+    assert_eq!(router.check_invariants(), Ok(()));
+
+    router.add_unchecked("/a/:id", "id");
+    router.add_unchecked("/a/*rest", "rest");
+    router.add("/a/b", "b");
+    assert_eq!(router.check_invariants(), Ok(()));
+    assert_eq!(router.find("/a/b").payload, &Some("b"));
+    assert_eq!(router.find("/a/42").payload, &Some("id"));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "duplicate error")]
+fn add_unchecked_checks_duplicates_in_debug_builds() {
+    let mut router = Router::<&str>::new();
+    router.add_unchecked("/products", "first");
+    router.add_unchecked("/products", "second");
+}
+
+#[test]
+fn priority_of_registered_patterns() {
+    let mut router = Router::<&str>::new();
+    router.add("/posts/:id", "post");
+    router.add("/search/*query", "search");
+
+    // both routes sit below "/", their keys start with "posts" and "search"
+    assert_eq!(router.priority_of("/posts/:id"), Some(6));
+    assert_eq!(router.priority_of("/search/*query"), Some(7));
+    assert_eq!(router.priority_of("/"), None);
+    assert_eq!(router.priority_of("/posts"), None);
+}
+
+#[test]
+fn prefix_of_single_route_matches_nothing() {
+    let mut router = Router::<&str>::new();
+    router.add("/products/detail", "detail");
+
+    for path in ["/products", "/products/", "/products/d", "/prod", "/", ""] {
+        let result = router.find(path);
+        assert_eq!(result.payload, &None, "{}", path);
+        assert_eq!(result.key(), "", "{}", path);
+        assert_eq!(result.matched_path(), None, "{}", path);
+    }
+
+    // intermediate nodes without payload are not reported either
+    router.add("/about", "about");
+    router.add("/products/list", "list");
+    for path in ["/products", "/products/", "/products//", "/"] {
+        let result = router.find(path);
+        assert_eq!(result.payload, &None, "{}", path);
+        assert_eq!(result.key(), "", "{}", path);
+    }
+}
+
+#[test]
+fn trailing_slash_needs_the_whole_key() {
+    let mut router = Router::<&str>::new();
+    router.add("/products/detail", "detail");
+    router.add("/products/:id/edit", "edit");
+    router.add("/about", "about");
+
+    assert_eq!(router.find("/products/detail/").payload, &Some("detail"));
+    assert_eq!(router.find("/products/10/edit/").payload, &Some("edit"));
+    assert_eq!(router.find("/products//").payload, &None);
+    assert_eq!(router.find("/products/detai/").payload, &None);
+    assert_eq!(router.find("/products/10//").payload, &None);
+    assert_eq!(router.find("/products/10/ed/").payload, &None);
+}
+
+#[test]
+fn diverging_inside_a_key_skips_its_children() {
+    let mut router = Router::<&str>::new();
+    router.add("/products/detail", "detail");
+    router.add("/products/detail/x", "x");
+    router.add("/about", "about");
+
+    assert_eq!(router.find("/products/detail/x").payload, &Some("x"));
+    let result = router.find("/products/det/x");
+    assert_eq!(result.payload, &None);
+    assert_eq!(result.key(), "");
+}
+
+#[derive(Debug, PartialEq)]
+struct FileParams {
+    user_id: u64,
+    path: String,
+}
+
+impl FromParams for FileParams {
+    const NAMES: &'static [&'static str] = &["user_id", "path"];
+
+    fn from_params<T>(
+        result: &patricia_router::Result<'_, T>,
+    ) -> std::result::Result<Self, ParamError> {
+        Ok(FileParams {
+            user_id: result.require("user_id")?,
+            path: result.require("path")?,
+        })
+    }
+}
+
+#[test]
+fn add_typed_binds_params_to_a_struct() {
+    let mut router = Router::<&str>::new();
+    router.add_typed::<FileParams>("/users/:user_id/files/*path", "file");
+    router.add("/users/:user_id", "user");
+
+    let params: FileParams = router.find("/users/42/files/a/b.txt").params_as().unwrap();
+    assert_eq!(
+        params,
+        FileParams {
+            user_id: 42,
+            path: "a/b.txt".to_string()
+        }
+    );
+
+    let err = router
+        .find("/users/me/files/a.txt")
+        .params_as::<FileParams>()
+        .unwrap_err();
+    assert!(matches!(err, ParamError::Parse { name, .. } if name == "user_id"));
+    let err = router
+        .find("/users/42")
+        .params_as::<FileParams>()
+        .unwrap_err();
+    assert!(matches!(err, ParamError::Missing(name) if name == "path"));
+}
+
+#[test]
+#[should_panic(expected = "missing parameter error: path")]
+fn add_typed_checks_the_pattern() {
+    let mut router = Router::<&str>::new();
+    router.add_typed::<FileParams>("/users/:user_id/files/:name", "file");
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn find_explain_reports_rejected_siblings() {
+    use patricia_router::RejectionReason;
+
+    let mut router = Router::<&str>::new();
+    router.add("/products", "products");
+    router.add("/products/featured", "featured");
+    router.add("/products/:id/edit", "edit");
+    router.add("/about", "about");
+
+    let (result, rejections) = router.find_explain("/products/featured");
+    assert_eq!(result.payload, &Some("featured"));
+    assert_eq!(
+        rejections,
+        vec![
+            RejectionReason::FirstChar {
+                pattern: "/about".to_string()
+            },
+            RejectionReason::Precedence {
+                pattern: "/products/:id/edit".to_string(),
+                chosen: "/products/featured".to_string()
+            },
+        ]
+    );
+
+    let (result, rejections) = router.find_explain("/products/10/show");
+    assert_eq!(result.payload, &None);
+    assert_eq!(
+        rejections.last(),
+        Some(&RejectionReason::Diverged {
+            pattern: "/products/:id/edit".to_string()
+        })
+    );
+
+    let (_, rejections) = router.find_explain("/products/fea");
+    assert!(rejections.contains(&RejectionReason::Prefix {
+        pattern: "/products/featured".to_string()
+    }));
+
+    let (_, rejections) = router.find_explain("/about/team");
+    assert_eq!(
+        rejections.last().map(RejectionReason::pattern),
+        Some("/about")
+    );
+}
+
+#[test]
+fn optional_format_on_named_parameter() {
+    let mut router = Router::<&str>::new();
+    router.add("/posts/:id.:format?", "post");
+    router.add("/posts/:id.:format?/comments", "comments");
+    router.add("/releases/:version{v1|v1.2}.:format?", "release");
+    router.add("/files/*path", "files");
+
+    let result = router.find("/posts/10.json");
+    assert_eq!(result.payload, &Some("post"));
+    assert_eq!(result.params("id"), "10");
+    assert_eq!(result.params("format"), "json");
+
+    // without extension the format is empty
+    let result = router.find("/posts/10");
+    assert_eq!(result.payload, &Some("post"));
+    assert_eq!(result.params("id"), "10");
+    assert_eq!(result.params("format"), "");
+
+    // the extension starts at the last dot
+    let result = router.find("/posts/v1.2.xml/comments");
+    assert_eq!(result.payload, &Some("comments"));
+    assert_eq!(result.params("id"), "v1.2");
+    assert_eq!(result.params("format"), "xml");
+
+    // constraints apply to the value without its extension
+    let result = router.find("/releases/v1.2.json");
+    assert_eq!(result.params("version"), "v1.2");
+    assert_eq!(result.params("format"), "json");
+    // without extension, the last dot still starts one
+    let result = router.find("/releases/v1.2");
+    assert_eq!(result.params("version"), "v1");
+    assert_eq!(result.params("format"), "2");
+    assert_eq!(router.find("/releases/v2.json").payload, &None);
+
+    // catch all parameters keep the extension
+    let result = router.find("/files/a/b.json");
+    assert_eq!(result.params("path"), "a/b.json");
+    assert!(matches!(
+        result.require::<String>("format"),
+        Err(ParamError::Missing(_))
+    ));
+
+    let result = router.find_with_ranges("/posts/10.json");
+    assert_eq!(result.param_range("format"), Some(10..14));
+
+    let mut params = HashMap::new();
+    params.insert("id".to_string(), "10".to_string());
+    assert_eq!(
+        router.url_for("/posts/:id.:format?", &params).unwrap(),
+        "/posts/10"
+    );
+    params.insert("format".to_string(), "xml".to_string());
+    assert_eq!(
+        router.url_for("/posts/:id.:format?", &params).unwrap(),
+        "/posts/10.xml"
+    );
+    assert_eq!(
+        router.param_names_for("/posts/:id.:format?"),
+        vec!["id", "format"]
+    );
+}
+
+#[test]
+fn template_for_returns_the_matched_pattern() {
+    let mut router = Router::<&str>::new();
+    router.add("/products", "products");
+    router.add("/products/:id/edit", "edit");
+    router.add("/products/featured", "featured");
+    router.set_fallback("not found");
+
+    assert_eq!(
+        router.template_for("/products/featured").as_deref(),
+        Some("/products/featured")
+    );
+    assert_eq!(
+        router.template_for("/products/10/edit/").as_deref(),
+        Some("/products/:id/edit")
+    );
+    assert_eq!(router.template_for("/about"), None);
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("std::*path", "std");
+    assert_eq!(
+        router.template_for("std::io::Read").as_deref(),
+        Some("std::*path")
+    );
+}
+
+#[test]
+fn add_static_matches_like_add() {
+    let routes = [
+        "/",
+        "/*filepath",
+        "/products",
+        "/products/:id",
+        "/products/:id/edit",
+        "/products/featured",
+        "/あいう/:name",
+    ];
+    let mut router = Router::<&str>::new();
+    let mut expected = Router::<&str>::new();
+    for route in routes {
+        router.add_static(route, route);
+        expected.add(route, route);
+    }
+    assert_eq!(router, expected);
+    assert_eq!(
+        router.find("/products/10/edit").payload,
+        &Some("/products/:id/edit")
+    );
+    assert_eq!(router.find("/あいう/え").params("name"), "え");
+}
+
+#[test]
+fn not_found_is_distinct_from_catch_all() {
+    let mut router = Router::<&str>::new();
+    router.add("/about", "about");
+    router.add("/static/*filepath", "static");
+    router.add("/files/*path?", "files");
+    assert_eq!(router.find("/missing").matched(), Matched::Nothing);
+
+    router.set_fallback("not found");
+    let result = router.find("/static/app.js");
+    assert_eq!(result.matched(), Matched::CatchAll);
+    assert_eq!(result.params("filepath"), "app.js");
+    assert_eq!(router.find("/files").matched(), Matched::CatchAll);
+    assert_eq!(router.find("/about").matched(), Matched::Route);
+    let result = router.find("/missing");
+    assert_eq!(result.matched(), Matched::NotFound);
+    assert_eq!(result.key(), "");
+
+    router.set_catch_all_not_found(true);
+    let result = router.find("/static/app.js");
+    assert_eq!(result.matched(), Matched::NotFound);
+    assert_eq!(result.payload, &Some("not found"));
+    assert_eq!(result.matched_path(), None);
+    assert!(result.require::<String>("filepath").is_err());
+    assert_eq!(router.find("/about").payload, &Some("about"));
+}
+
+#[test]
+fn allowed_at_lists_static_continuations() {
+    let mut router = Router::<&str>::new();
+    router.add("/products", "products");
+    router.add("/products/featured", "featured");
+    router.add("/products/:id/edit", "edit");
+    router.add("/products/:id/reviews", "reviews");
+    router.add("/about", "about");
+
+    // the path ended at a node without payload
+    assert_eq!(router.allowed_at("/products/10/"), vec!["reviews", "edit"]);
+    // the path continued past a node without a matching child
+    assert_eq!(
+        router.allowed_at("/products/10/share"),
+        vec!["reviews", "edit"]
+    );
+    // the path diverged inside a key, its siblings are offered
+    assert_eq!(router.allowed_at("/abx"), vec!["products", "about"]);
+    assert!(router.allowed_at("/about").is_empty());
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("::crate::fs::read", "read");
+    router.add("::crate::fs::write", "write");
+    assert_eq!(
+        router.allowed_at("::crate::fs::open"),
+        vec!["write", "read"]
+    );
+}
+
+#[test]
+fn rooted_tree_matches_like_new() {
+    let routes = [
+        "/products/:id",
+        "/",
+        "/*filepath",
+        "/products",
+        "about",
+        "/products/featured",
+    ];
+    let mut router = Router::<&str>::new_rooted();
+    let mut expected = Router::<&str>::new();
+    for route in routes {
+        router.add(route, route);
+        expected.add(route, route);
+    }
+    for path in [
+        "/products/10",
+        "/",
+        "/src/app.js",
+        "/products",
+        "about",
+        "/products/featured",
+    ] {
+        assert_eq!(
+            router.find(path).payload,
+            expected.find(path).payload,
+            "{}",
+            path
+        );
+    }
+    assert_eq!(
+        router.would_conflict("/products/:name"),
+        Some(ConflictKind::AmbiguousDynamic)
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn params_json_collects_every_param() {
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id/files/*", "files");
+    router.add("/about", "about");
+    let json = router.find("/users/%C3%A9/files/a/b").params_json();
+    assert_eq!(
+        json,
+        serde_json::json!({"id": "%C3%A9", UNNAMED_CATCH_ALL: "a/b"})
+    );
+    assert_eq!(router.find("/about").params_json(), serde_json::json!({}));
+    assert_eq!(router.find("/missing").params_json(), serde_json::json!({}));
+}
+
+#[test]
+fn hosts_as_leading_segment() {
+    let mut router = Router::<&str>::new();
+    router.add_host_route("example.com", "/", "home");
+    router.add_host_route("example.com", "users/:id", "user");
+    router.add_host_route("example.com", "/files/:name.:format?", "file");
+    router.add_host_route("example.org", "/users/:id", "org user");
+    router.add_host_route(":tenant", "/", "tenant");
+    router.add("/users/:id", "any host");
+
+    assert_eq!(router.find("example.com/").payload, &Some("home"));
+    let result = router.find("example.com/users/42");
+    assert_eq!(result.payload, &Some("user"));
+    assert_eq!(result.key(), "example.com/users/:id");
+    assert_eq!(
+        router.find("example.org/users/42").payload,
+        &Some("org user")
+    );
+    assert_eq!(router.find("/users/42").payload, &Some("any host"));
+    assert_eq!(router.find("example.com.evil/users/42").payload, &None);
+    assert_eq!(router.find("example.co/users/42").payload, &None);
+
+    // the dots of the host don't start an extension
+    let result = router.find("example.com/files/report.pdf");
+    assert_eq!(result.params("name"), "report");
+    assert_eq!(result.params("format"), "pdf");
+
+    // a named parameter as the host captures the whole host
+    let result = router.find("acme.example.net/");
+    assert_eq!(result.payload, &Some("tenant"));
+    assert_eq!(result.params("tenant"), "acme.example.net");
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add_host_route("example.com", "users::list", "users");
+    assert_eq!(
+        router.find("example.com::users::list").payload,
+        &Some("users")
+    );
+}
+
+#[test]
+fn find_strict_agrees_with_find_on_added_routes() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products", "products");
+    router.add("/products/:id", "product");
+    router.add("/products/:id/edit", "edit");
+    router.add("/products/featured", "featured");
+    for path in [
+        "/",
+        "/src/app.js",
+        "/products",
+        "/products/10",
+        "/products/10/edit",
+    ] {
+        let result = router.find_strict(path).unwrap().unwrap();
+        assert_eq!(result.payload, router.find(path).payload, "{}", path);
+        assert_eq!(result.key(), router.find(path).key(), "{}", path);
+    }
+    let result = router.find_strict("/products/10/edit").unwrap().unwrap();
+    assert_eq!(result.params("id"), "10");
+
+    let mut router = Router::<&str>::new();
+    router.add("/about", "about");
+    assert!(router.find_strict("/products").unwrap().is_none());
+}
+
+#[test]
+fn params_map_holds_every_param() {
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id/files/*path", "files");
+    let result = router.find("/users/42/files/a/b");
+    let params: HashMap<&str, &str> = result
+        .params_map()
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    assert_eq!(params, HashMap::from([("id", "42"), ("path", "a/b")]));
+    assert_eq!(
+        router.find_owned("/users/42/files/a/b").params_map(),
+        result.params_map()
+    );
+    assert!(router.find("/missing").params_map().is_empty());
+}
+
+#[cfg(feature = "nfc")]
+#[test]
+fn nfc_matches_equivalent_paths() {
+    let composed = "/caf\u{e9}/:name";
+    let decomposed = "/cafe\u{301}/:name";
+
+    let mut router = Router::<&str>::new();
+    router.add(decomposed, "cafe");
+    assert_eq!(router.find("/caf\u{e9}/menu").payload, &None);
+
+    let mut router = Router::<&str>::new();
+    router.set_nfc(true);
+    router.add(decomposed, "cafe");
+    router.add("/about", "about");
+    let result = router.find("/caf\u{e9}/menu");
+    assert_eq!(result.payload, &Some("cafe"));
+    assert_eq!(result.key(), composed);
+    let result = router.find("/cafe\u{301}/ve\u{301}lo");
+    assert_eq!(result.payload, &Some("cafe"));
+    assert_eq!(result.params("name"), "v\u{e9}lo");
+    assert_eq!(router.find("/about").payload, &Some("about"));
+    assert_eq!(router.get_pattern(composed), Some(&"cafe"));
+}
+
+#[cfg(feature = "nfc")]
+#[test]
+fn nfc_normalizes_patterns_given_to_lookups() {
+    let composed = "/caf\u{e9}/:name";
+    let decomposed = "/cafe\u{301}/:name";
+
+    let mut router = Router::<Vec<&str>>::new();
+    router.set_nfc(true);
+    router.get_or_insert_with(decomposed, Vec::new).push("menu");
+    router
+        .get_or_insert_with(decomposed, Vec::new)
+        .push("drinks");
+    assert_eq!(router.get_pattern(composed), Some(&vec!["menu", "drinks"]));
+    assert_eq!(
+        router.get_pattern(decomposed),
+        Some(&vec!["menu", "drinks"])
+    );
+    assert_eq!(
+        router.replace(decomposed, vec!["all"]),
+        Some(vec!["menu", "drinks"])
+    );
+    assert!(router.debug_node(decomposed).unwrap().has_payload);
+    assert_eq!(router.param_names_for(decomposed), vec!["name"]);
+    assert_eq!(
+        router.would_conflict(decomposed),
+        Some(ConflictKind::Duplicate)
+    );
+    let mut params = HashMap::new();
+    params.insert("name".to_string(), "menu".to_string());
+    assert_eq!(
+        router.url_for(decomposed, &params).as_deref(),
+        Some("/caf\u{e9}/menu")
+    );
+
+    router.add("/v\u{e9}lo", vec!["velo"]);
+    assert!(router.priority_of("/ve\u{301}lo").is_some());
+    assert_eq!(
+        router.priority_of("/ve\u{301}lo"),
+        router.priority_of("/v\u{e9}lo")
+    );
+
+    router.add_excluding("/files/*path", vec!["files"], &[".cafe\u{301}"]);
+    assert_eq!(router.find("/files/menu.caf\u{e9}").payload, &None);
+    assert_eq!(router.find("/files/menu.txt").payload, &Some(vec!["files"]));
+
+    let mut boosted = Router::<&str>::new();
+    boosted.set_nfc(true);
+    boosted.add_with_priority("/ve\u{301}lo/a", "a", 10);
+    let mut expected = Router::<&str>::new();
+    expected.set_nfc(true);
+    expected.add_with_priority("/v\u{e9}lo/a", "a", 10);
+    assert_eq!(boosted, expected);
+}
+
+#[test]
+fn generation_is_ignored_by_equality() {
+    let mut router = Router::<&str>::new();
+    router.add_unsorted("/about", "about");
+    router.add_unsorted("/products", "products");
+    router.sort();
+    let mut expected = Router::<&str>::new();
+    expected.add("/about", "about");
+    expected.add("/products", "products");
+    assert_ne!(router.generation(), expected.generation());
+    assert_eq!(router, expected);
+}
+
+#[test]
+fn diff_compares_route_sets() {
+    let current: Router<&str> = vec![
+        ("/", "root"),
+        ("/products", "products"),
+        ("/products/:id", "product"),
+        ("/*filepath", "all"),
+    ]
+    .into_iter()
+    .collect();
+    assert!(current.diff(&current).is_empty());
+    assert_eq!(
+        current.diff(&Router::new()).removed,
+        vec!["/", "/*filepath", "/products", "/products/:id"]
+    );
+
+    // the order routes were added in doesn't matter
+    let mut next = Router::<&str>::new();
+    next.add("/products/:id", "product v2");
+    next.add("/*filepath", "all");
+    next.add("/products/featured", "featured");
+    next.add("", "empty");
+    next.add("/", "root");
+    let diff = current.diff(&next);
+    assert_eq!(diff.added, vec!["", "/products/featured"]);
+    assert_eq!(diff.removed, vec!["/products"]);
+    assert_eq!(diff.changed, vec!["/products/:id"]);
+    assert!(!diff.is_empty());
+
+    let reverse = next.diff(&current);
+    assert_eq!(reverse.added, diff.removed);
+    assert_eq!(reverse.removed, diff.added);
+    assert_eq!(reverse.changed, diff.changed);
+
+    let mut current = Router::<&str>::with_separator_str("::");
+    current.add("::fs::read", "read");
+    let mut next = Router::<&str>::with_separator_str("::");
+    next.add("::fs::write", "write");
+    assert_eq!(current.diff(&next).added, vec!["::fs::write"]);
+}
+
+#[test]
+fn find_lazy_captures_like_find() {
+    let mut router = Router::<&str>::new();
+    router.add("/", "root");
+    router.add("/*filepath", "all");
+    router.add("/products/:id", "product");
+    router.add("/products/:id/edit", "edit");
+    router.add("/releases/:version.:format?", "release");
+    router.add("/posts/:status{draft|published}/*?slug", "post");
+    router.add("/files/*", "files");
+    router.add("/dirs/*?/x", "dir");
+    router.add("/search/*extra", "search");
+    router.add("/あいう/:name/え", "unicode");
+
+    let paths = [
+        "/src/file.png/",
+        "/products/10",
+        "/products/10/edit",
+        "/releases/v1.2.json",
+        "/releases/v1",
+        "/posts/draft/hello",
+        "/files/a/b",
+        "/dirs/abc/x",
+        "/search",
+        "/あいう/かき/え",
+    ];
+    let names = [
+        "filepath", "id", "version", "format", "status", "slug", "splat", "extra", "name",
+    ];
+    for path in paths {
+        let result = router.find(path);
+        let lazy = router.find_lazy(path);
+        assert_eq!(lazy.payload, result.payload, "{}", path);
+        assert_eq!(lazy.key(), result.key(), "{}", path);
+        for name in names {
+            let expected = result.params_map().get(name).map(|value| value.as_str());
+            assert_eq!(
+                lazy.get_param(name).as_deref(),
+                expected,
+                "{} {}",
+                path,
+                name
+            );
+        }
+    }
+
+    router.set_fallback("not found");
+    let lazy = router.find_lazy("/products/10/missing");
+    assert_eq!(lazy.payload, &Some("not found"));
+    assert_eq!(lazy.get_param("id"), None);
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add("::crate::*path", "crate");
+    let lazy = router.find_lazy("::crate::fs::read");
+    assert_eq!(lazy.get_param("path").as_deref(), Some("fs::read"));
+    assert_eq!(lazy.key(), "::crate::*path");
+
+    let mut router = Router::<&str>::new();
+    router.add("/users/:id", "user");
+    router.set_partial_match(PartialMatch::LongestPrefix);
+    let lazy = router.find_lazy("/users/42/unknown");
+    assert_eq!(lazy.payload, &Some("user"));
+    assert_eq!(lazy.get_param("id").as_deref(), Some("42"));
+}
+
+#[test]
+fn custom_markers_keep_default_ones_literal() {
+    let mut router = Router::<&str>::with_markers('$', '%');
+    router.add("/at/12:30", "lunch");
+    router.add("/at/$time", "time");
+    router.add("/glob/*", "star");
+    router.add("/users/$id/files/%path", "file");
+
+    let result = router.find("/at/12:30");
+    assert_eq!(result.payload, &Some("lunch"));
+    assert_eq!(result.key(), "/at/12:30");
+
+    let result = router.find("/at/12:45");
+    assert_eq!(result.payload, &Some("time"));
+    assert_eq!(result.key(), "/at/$time");
+    assert_eq!(result.params("time"), "12:45");
+
+    assert_eq!(router.find("/glob/*").payload, &Some("star"));
+    assert_eq!(router.find("/glob/a").payload, &None);
+
+    let path = "/users/42/files/a*b/$c";
+    let result = router.find_with_ranges(path);
+    assert_eq!(result.key(), "/users/$id/files/%path");
+    assert_eq!(result.params("path"), "a*b/$c");
+    assert_eq!(&path[result.param_range("path").unwrap()], "a*b/$c");
+    assert_eq!(result.catch_all(path), Some("a*b/$c"));
+
+    let lazy = router.find_lazy(path);
+    assert_eq!(lazy.key(), "/users/$id/files/%path");
+    assert_eq!(lazy.get_param("path").as_deref(), Some("a*b/$c"));
+
+    let routes: Vec<String> = router.into_iter().map(|(path, _)| path).collect();
+    assert!(routes.contains(&"/at/12:30".to_string()));
+    assert!(routes.contains(&"/users/$id/files/%path".to_string()));
+}
+
+#[test]
+fn custom_markers_in_excluded_suffixes() {
+    let mut router = Router::<&str>::with_markers('$', '%');
+    router.add_excluding("/f/%rest", "files", &[":secret"]);
+    assert_eq!(router.find("/f/a:secret").payload, &None);
+    assert_eq!(router.find("/f/a.txt").payload, &Some("files"));
+
+    let mut router = Router::<&str>::with_separator_str("::");
+    router.add_excluding("::f::*rest", "files", &["::.git"]);
+    assert_eq!(router.find("::f::a::.git").payload, &None);
+    assert_eq!(router.find("::f::a.git").payload, &Some("files"));
+}
+
+#[test]
+#[should_panic(expected = "ambiguous markers")]
+fn custom_markers_must_differ() {
+    Router::<&str>::with_markers('$', '$');
+}
+
+#[test]
+fn dots_as_extra_separators() {
+    let mut router = Router::<&str>::with_separators(&['.']);
+    router.add("/api/:version.users.:id", "user");
+    router.add("/api/:version.users.:id/posts", "posts");
+    router.add("/api/:version.:resource.show", "show");
+    router.add("/api/:version.:resource.list", "list");
+    router.add("/files/*?name.:ext", "file");
+    router.add("/static/*path", "static");
+    assert_eq!(router.would_conflict("/api/:version.:resource.edit"), None);
+    router.add("/api/:version.:resource.edit", "edit");
+
+    let result = router.find("/api/v1.users.42");
+    assert_eq!(result.payload, &Some("user"));
+    assert_eq!(result.params("version"), "v1");
+    assert_eq!(result.params("id"), "42");
+    // a trailing separator is tolerated like a trailing slash
+    assert_eq!(router.find("/api/v1.users.42.").params("id"), "42");
+
+    let result = router.find("/api/v2.users.42/posts");
+    assert_eq!(result.payload, &Some("posts"));
+    assert_eq!(result.params("version"), "v2");
+    assert_eq!(result.params("id"), "42");
+
+    let result = router.find("/api/v1.posts.list");
+    assert_eq!(result.payload, &Some("list"));
+    assert_eq!(result.key(), "/api/:version.:resource.list");
+    assert_eq!(result.params("resource"), "posts");
+    assert_eq!(router.find("/api/v1.posts.edit").payload, &Some("edit"));
+    assert_eq!(router.find("/api/v1.posts.delete").payload, &None);
+
+    let result = router.find("/files/report.pdf");
+    assert_eq!(result.payload, &Some("file"));
+    assert_eq!(result.params("name"), "report");
+    assert_eq!(result.params("ext"), "pdf");
+
+    // catch all parameters still span separators
+    assert_eq!(
+        router.find("/static/css/app.min.css").params("path"),
+        "css/app.min.css"
+    );
+
+    let lazy = router.find_lazy("/api/v1.posts.show");
+    assert_eq!(lazy.get_param("version").as_deref(), Some("v1"));
+    assert_eq!(lazy.get_param("resource").as_deref(), Some("posts"));
+
+    let mut params = HashMap::new();
+    params.insert("version".to_string(), "v3".to_string());
+    params.insert("resource".to_string(), "tags".to_string());
+    assert_eq!(
+        router
+            .url_for("/api/:version.:resource.show", &params)
+            .as_deref(),
+        Some("/api/v3.tags.show")
+    );
+}
+
+#[test]
+fn dots_are_literal_by_default() {
+    let mut router = Router::<&str>::new();
+    router.add("/api/:version", "version");
+    let result = router.find("/api/v1.users");
+    assert_eq!(result.payload, &Some("version"));
+    assert_eq!(result.params("version"), "v1.users");
+}
+
+#[test]
+#[should_panic(expected = "ambiguous separator")]
+fn extra_separators_must_differ_from_markers() {
+    Router::<&str>::with_separators(&['.', ':']);
+}
+
+#[test]
+fn custom_markers_with_extra_separators() {
+    let mut router = Router::<&str>::with_markers_and_separators('$', '%', &['.']);
+    router.add("/api/$version.$resource", "resource");
+    router.add("/at/12:30.utc", "lunch");
+    router.add("/static/%path", "static");
+
+    let result = router.find("/api/v1.users");
+    assert_eq!(result.key(), "/api/$version.$resource");
+    assert_eq!(result.params("version"), "v1");
+    assert_eq!(result.params("resource"), "users");
+    assert_eq!(router.find("/at/12:30.utc").payload, &Some("lunch"));
+    assert_eq!(router.find("/static/a.b/c").params("path"), "a.b/c");
+}
+
+#[test]
+#[should_panic(expected = "ambiguous markers")]
+fn custom_markers_must_differ_from_extra_separators() {
+    Router::<&str>::with_markers_and_separators('$', '%', &['.', '$']);
+}
+
+#[test]
+fn trees_keep_their_invariants() {
+    let routes = [
+        "/",
+        "/*filepath",
+        "/about",
+        "/api/v1/users",
+        "/api/v1/users/:id",
+        "/api/v1/posts/:id/comments",
+        "/api/v2/*rest",
+        "/products",
+        "/products/:id",
+        "/products/:id/edit",
+        "/products/featured",
+    ];
+
+    let mut router = Router::<usize>::new();
+    let mut rooted = Router::<usize>::new_rooted();
+    let mut unsorted = Router::<usize>::new();
+    for (i, route) in routes.iter().enumerate() {
+        router.add(*route, i);
+        rooted.add(*route, i);
+        unsorted.add_unsorted(*route, i);
+    }
+    assert_eq!(router.check_invariants(), Ok(()));
+    assert_eq!(rooted.check_invariants(), Ok(()));
+    unsorted.sort();
+    assert_eq!(unsorted.check_invariants(), Ok(()));
+
+    let snapshot = router.snapshot();
+    router.compact();
+    rooted.compact();
+    assert_eq!(router.check_invariants(), Ok(()));
+    assert_eq!(rooted.check_invariants(), Ok(()));
+    router.restore(snapshot);
+    assert_eq!(router.check_invariants(), Ok(()));
+    router.retain(|pattern, _| !pattern.starts_with("/api"));
+    rooted.retain(|pattern, _| !pattern.starts_with("/products"));
+    assert_eq!(router.check_invariants(), Ok(()));
+    assert_eq!(rooted.check_invariants(), Ok(()));
+    assert_eq!(router.map(|i| i * 2).check_invariants(), Ok(()));
+
+    let sorted = {
+        let mut routes: Vec<(String, usize)> = routes
+            .iter()
+            .enumerate()
+            .map(|(i, route)| (route.to_string(), i))
+            .collect();
+        routes.sort_by(|a, b| cmp_routes(&a.0, &b.0));
+        routes
+    };
+    let router = Router::from_sorted(sorted).unwrap();
+    assert_eq!(router.check_invariants(), Ok(()));
+}